@@ -0,0 +1,142 @@
+//! Derive macro to load a config struct from the secrets stored in VaultAPI server.
+//!
+//! Use it through the `derive` feature of the `VaultAPI-Client` crate, which re-exports the macro.
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{parse_macro_input, Attribute, Data, DeriveInput, Fields, LitStr};
+
+/// Struct to store the options parsed from the `#[vault(...)]` attributes.
+#[derive(Default)]
+struct VaultAttributes {
+    table: Option<LitStr>,
+    key: Option<LitStr>,
+    env: Option<LitStr>,
+    default: Option<LitStr>,
+}
+
+/// Parses the `#[vault(...)]` attributes of a struct or a field.
+///
+/// # Arguments
+/// * `attrs` - Attributes of the struct or the field.
+///
+/// # Returns
+/// * A `Result` with the parsed `VaultAttributes`.
+fn vault_attributes(attrs: &[Attribute]) -> syn::Result<VaultAttributes> {
+    let mut parsed = VaultAttributes::default();
+    for attr in attrs.iter().filter(|attr| attr.path().is_ident("vault")) {
+        attr.parse_nested_meta(|meta| {
+            let option = if meta.path.is_ident("table") {
+                &mut parsed.table
+            } else if meta.path.is_ident("key") {
+                &mut parsed.key
+            } else if meta.path.is_ident("env") {
+                &mut parsed.env
+            } else if meta.path.is_ident("default") {
+                &mut parsed.default
+            } else {
+                return Err(meta.error("expected one of: table, key, env, default"));
+            };
+            *option = Some(meta.value()?.parse::<LitStr>()?);
+            Ok(())
+        })?;
+    }
+    Ok(parsed)
+}
+
+/// Converts an optional string literal into tokens for an `Option<&str>`.
+fn optional(value: &Option<LitStr>) -> TokenStream2 {
+    match value {
+        Some(value) => quote!(::std::option::Option::Some(#value)),
+        None => quote!(::std::option::Option::None),
+    }
+}
+
+/// Generates the `load` function for the struct.
+fn expand(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(syn::Error::new_spanned(
+                    input,
+                    "VaultSecrets can only be derived for structs with named fields",
+                ))
+            }
+        },
+        _ => {
+            return Err(syn::Error::new_spanned(
+                input,
+                "VaultSecrets can only be derived for structs",
+            ))
+        }
+    };
+    let struct_attributes = vault_attributes(&input.attrs)?;
+    let mut initializers = Vec::new();
+    for field in fields {
+        let ident = field.ident.as_ref().expect("named fields have identifiers");
+        let attributes = vault_attributes(&field.attrs)?;
+        let table = attributes
+            .table
+            .as_ref()
+            .or(struct_attributes.table.as_ref())
+            .cloned()
+            .ok_or_else(|| {
+                syn::Error::new_spanned(field, "missing table name, use #[vault(table = \"...\")]")
+            })?;
+        // Secret keys default to the field name in uppercase
+        let key = attributes
+            .key
+            .clone()
+            .unwrap_or_else(|| LitStr::new(&ident.to_string().to_uppercase(), ident.span()));
+        let env = optional(&attributes.env);
+        let default = optional(&attributes.default);
+        initializers.push(quote! {
+            #ident: loader.field(#table, #key, #env, #default)?
+        });
+    }
+
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics #name #ty_generics #where_clause {
+            /// Loads the struct from the secrets stored in the vault.
+            ///
+            /// # Arguments
+            /// * `client` - Client object to make requests to the server.
+            ///
+            /// # Returns
+            /// * A `Result` with the loaded struct.
+            pub fn load(
+                client: &::vaultapi::client::VaultClient,
+            ) -> ::std::result::Result<Self, ::vaultapi::error::VaultError> {
+                let mut loader = ::vaultapi::typed::TableLoader::new(client);
+                ::std::result::Result::Ok(Self {
+                    #(#initializers,)*
+                })
+            }
+        }
+    })
+}
+
+/// Derives a `load(&client)` function that populates the struct from the secrets stored in the vault.
+///
+/// Each table is retrieved only once, and the values are resolved in the following order.
+/// 1. Secret stored in the table.
+/// 2. Environment variable set with `env`.
+/// 3. Default value set with `default`.
+///
+/// `Option` fields that are not found anywhere are set to `None`.
+///
+/// # Attributes
+/// * `#[vault(table = "...")]` - Table name, either for all the fields on the struct, or for a single field.
+/// * `#[vault(key = "...")]` - Secret key for a field. Defaults to the field name in uppercase.
+/// * `#[vault(env = "...")]` - Environment variable to fall back to.
+/// * `#[vault(default = "...")]` - Default value to fall back to.
+#[proc_macro_derive(VaultSecrets, attributes(vault))]
+pub fn derive_vault_secrets(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(&input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
@@ -0,0 +1,320 @@
+use crate::secret::Secret;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+/// Struct to represent the number of cache hits and misses.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+}
+
+/// Decrypted secrets of a single table, along with the time they were cached.
+///
+/// The secrets are wiped from memory when they are invalidated, expire or the cache is dropped.
+#[derive(Default)]
+struct CachedTable {
+    secrets: HashMap<String, (Instant, Secret<Value>)>,
+    /// Time when the entire table was cached, if it still holds every secret in the table.
+    loaded: Option<Instant>,
+}
+
+/// In-memory cache for the decrypted secrets, stored per table and key.
+///
+/// Entries expire after the configured TTL, and a TTL of zero disables the cache.
+pub struct SecretCache {
+    ttl: Duration,
+    tables: Mutex<HashMap<String, CachedTable>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl SecretCache {
+    /// Creates a new cache.
+    ///
+    /// # Arguments
+    /// * `ttl` - Duration for which the secrets are cached.
+    ///
+    /// # Returns
+    /// * A `SecretCache` object.
+    pub fn new(ttl: Duration) -> SecretCache {
+        SecretCache {
+            ttl,
+            tables: Mutex::new(HashMap::new()),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    /// Checks if the cache is enabled.
+    pub fn is_enabled(&self) -> bool {
+        !self.ttl.is_zero()
+    }
+
+    /// Returns the number of cache hits and misses so far.
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Removes all the cached secrets, and resets the counters.
+    pub fn clear(&self) {
+        self.lock().clear();
+        self.hits.store(0, Ordering::Relaxed);
+        self.misses.store(0, Ordering::Relaxed);
+    }
+
+    /// Acquires the lock on the cached tables, recovering from a poisoned lock.
+    fn lock(&self) -> MutexGuard<'_, HashMap<String, CachedTable>> {
+        self.tables.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// Checks if an entry cached at the given time is still valid.
+    fn is_fresh(&self, cached_at: Instant) -> bool {
+        cached_at.elapsed() < self.ttl
+    }
+
+    /// Updates the hit/miss counters.
+    fn record(&self, hit: bool) {
+        let counter = if hit { &self.hits } else { &self.misses };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Retrieves a cached secret.
+    ///
+    /// # Arguments
+    /// * `table_name` - Table name where the secret is stored.
+    /// * `key` - Secret key.
+    ///
+    /// # Returns
+    /// * An `Option` with the secret value, if it is cached and hasn't expired.
+    pub fn get(&self, table_name: &str, key: &str) -> Option<Secret<Value>> {
+        if !self.is_enabled() {
+            return None;
+        }
+        let mut tables = self.lock();
+        let value = match tables.get_mut(table_name) {
+            Some(table) => match table.secrets.get(key) {
+                Some((cached_at, value)) if self.is_fresh(*cached_at) => Some(value.clone()),
+                // Expired secrets are wiped right away, instead of staying in memory until they are replaced
+                Some(_) => {
+                    table.secrets.remove(key);
+                    table.loaded = None;
+                    None
+                }
+                None => None,
+            },
+            None => None,
+        };
+        drop(tables);
+        self.record(value.is_some());
+        value
+    }
+
+    /// Retrieves multiple cached secrets.
+    ///
+    /// # Arguments
+    /// * `table_name` - Table name where the secrets are stored.
+    /// * `keys` - Secret keys.
+    ///
+    /// # Returns
+    /// * A tuple of the cached secrets, and the keys that have to be retrieved from the server.
+    pub fn get_many<'k>(
+        &self,
+        table_name: &str,
+        keys: &[&'k str],
+    ) -> (HashMap<String, Secret<Value>>, Vec<&'k str>) {
+        let mut secrets = HashMap::new();
+        let mut missing = Vec::new();
+        for key in keys {
+            match self.get(table_name, key) {
+                Some(value) => {
+                    secrets.insert(key.to_string(), value);
+                }
+                None => missing.push(*key),
+            }
+        }
+        (secrets, missing)
+    }
+
+    /// Retrieves all the secrets of a cached table.
+    ///
+    /// # Arguments
+    /// * `table_name` - Table name.
+    ///
+    /// # Returns
+    /// * An `Option` with the secrets as key-value pairs, if the table is cached and hasn't expired.
+    pub fn get_table(&self, table_name: &str) -> Option<Secret<Value>> {
+        if !self.is_enabled() {
+            return None;
+        }
+        let mut tables = self.lock();
+        let value = match tables.get_mut(table_name) {
+            Some(table) if table.loaded.is_some_and(|loaded| self.is_fresh(loaded)) => {
+                let secrets: Map<String, Value> = table
+                    .secrets
+                    .iter()
+                    .map(|(key, (_, value))| (key.to_string(), value.expose().clone()))
+                    .collect();
+                Some(Secret::json(Value::Object(secrets)))
+            }
+            // Expired secrets of the table are wiped, and the newer ones are kept
+            Some(table) => {
+                table.loaded = None;
+                table
+                    .secrets
+                    .retain(|_, (cached_at, _)| self.is_fresh(*cached_at));
+                None
+            }
+            None => None,
+        };
+        drop(tables);
+        self.record(value.is_some());
+        value
+    }
+
+    /// Stores the secrets from a decrypted payload.
+    ///
+    /// # Arguments
+    /// * `table_name` - Table name where the secrets are stored.
+    /// * `payload` - Decrypted payload with the secrets as key-value pairs.
+    pub fn insert(&self, table_name: &str, payload: &Value) {
+        if !self.is_enabled() {
+            return;
+        }
+        if let Value::Object(secrets) = payload {
+            let now = Instant::now();
+            let mut tables = self.lock();
+            let table = tables.entry(table_name.to_string()).or_default();
+            for (key, value) in secrets {
+                table
+                    .secrets
+                    .insert(key.to_string(), (now, Secret::json(value.clone())));
+            }
+        }
+    }
+
+    /// Stores all the secrets of a table, replacing the previously cached secrets.
+    ///
+    /// # Arguments
+    /// * `table_name` - Table name.
+    /// * `payload` - Decrypted payload with all the secrets in the table.
+    pub fn insert_table(&self, table_name: &str, payload: &Value) {
+        if !self.is_enabled() {
+            return;
+        }
+        if let Value::Object(secrets) = payload {
+            let now = Instant::now();
+            let table = CachedTable {
+                secrets: secrets
+                    .iter()
+                    .map(|(key, value)| (key.to_string(), (now, Secret::json(value.clone()))))
+                    .collect(),
+                loaded: Some(now),
+            };
+            self.lock().insert(table_name.to_string(), table);
+        }
+    }
+
+    /// Removes a secret from the cache, along with the cached table it belongs to.
+    ///
+    /// # Arguments
+    /// * `table_name` - Table name where the secret is stored.
+    /// * `key` - Secret key.
+    pub fn invalidate(&self, table_name: &str, key: &str) {
+        if let Some(table) = self.lock().get_mut(table_name) {
+            table.secrets.remove(key);
+            table.loaded = None;
+        }
+    }
+
+    /// Removes all the secrets of a table from the cache.
+    ///
+    /// # Arguments
+    /// * `table_name` - Table name.
+    pub fn invalidate_table(&self, table_name: &str) {
+        self.lock().remove(table_name);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// Number of secrets held by the cache for a table.
+    fn cached(cache: &SecretCache, table_name: &str) -> usize {
+        cache
+            .lock()
+            .get(table_name)
+            .map_or(0, |table| table.secrets.len())
+    }
+
+    #[test]
+    fn secrets_expire_after_the_ttl() {
+        let cache = SecretCache::new(Duration::from_millis(50));
+        cache.insert_table("production", &json!({"A": "1", "B": "2"}));
+        assert_eq!(*cache.get("production", "A").unwrap().expose(), json!("1"));
+        assert_eq!(
+            *cache.get_table("production").unwrap().expose(),
+            json!({"A": "1", "B": "2"})
+        );
+        std::thread::sleep(Duration::from_millis(60));
+        assert!(cache.get("production", "A").is_none());
+        // The expired secret is removed on the lookup, along with the table
+        assert_eq!(cached(&cache, "production"), 1);
+        cache.insert("production", &json!({"C": "3"}));
+        assert!(cache.get_table("production").is_none());
+        assert_eq!(cached(&cache, "production"), 1);
+        assert_eq!(*cache.get("production", "C").unwrap().expose(), json!("3"));
+    }
+
+    #[test]
+    fn zero_ttl_disables_the_cache() {
+        let cache = SecretCache::new(Duration::ZERO);
+        assert!(!cache.is_enabled());
+        cache.insert_table("production", &json!({"A": "1"}));
+        cache.insert("production", &json!({"B": "2"}));
+        assert!(cache.get("production", "A").is_none());
+        assert!(cache.get_table("production").is_none());
+        assert_eq!(cached(&cache, "production"), 0);
+        // Disabled lookups are neither hits nor misses
+        assert_eq!(cache.stats(), CacheStats::default());
+    }
+
+    #[test]
+    fn hits_and_misses_are_counted() {
+        let cache = SecretCache::new(Duration::from_secs(60));
+        cache.insert("production", &json!({"A": "1", "B": "2"}));
+        let (secrets, missing) = cache.get_many("production", &["A", "B", "C"]);
+        assert_eq!(secrets.len(), 2);
+        assert_eq!(missing, vec!["C"]);
+        // The table was never loaded as a whole, so it is a miss
+        assert!(cache.get_table("production").is_none());
+        assert_eq!(cache.stats(), CacheStats { hits: 2, misses: 2 });
+        cache.clear();
+        assert_eq!(cache.stats(), CacheStats::default());
+        assert!(cache.get("production", "A").is_none());
+    }
+
+    #[test]
+    fn writes_invalidate_the_cached_secrets() {
+        let cache = SecretCache::new(Duration::from_secs(60));
+        cache.insert_table("production", &json!({"A": "1", "B": "2"}));
+        cache.insert_table("staging", &json!({"A": "1"}));
+        cache.invalidate("production", "A");
+        assert!(cache.get("production", "A").is_none());
+        assert!(cache.get("production", "B").is_some());
+        // The table no longer holds every secret, so it has to be retrieved again
+        assert!(cache.get_table("production").is_none());
+        assert!(cache.get_table("staging").is_some());
+        cache.invalidate_table("staging");
+        assert!(cache.get_table("staging").is_none());
+        assert!(cache.get("staging", "A").is_none());
+    }
+}
@@ -0,0 +1,769 @@
+use crate::client::VaultClient;
+use crate::credentials::{self, Store};
+use crate::diff::Diff;
+use crate::error::VaultError;
+use crate::export;
+use crate::format;
+use crate::import::{self, FileFormat, ImportPlan};
+use crate::manifest::{Action, Manifest, Plan, TablePlan};
+use crate::parser::{self, Command, Endpoint, Precedence};
+use crate::profile;
+use crate::secret::Secret;
+use crate::util;
+use reqwest::Url;
+use serde_json::{json, Map, Value};
+use std::collections::BTreeMap;
+
+/// Enum to represent the output of a CLI command.
+#[derive(Debug)]
+pub enum Output {
+    /// Value to be printed to the terminal.
+    Value(Value),
+    /// Exit code of a child process, that has to be propagated.
+    ExitCode(i32),
+    /// Value to be printed to the terminal, exiting with `1` as the compared secrets have drifted.
+    Drift(Value),
+    /// Text to be printed to the terminal as-is, regardless of the output format.
+    Text(Secret<String>),
+}
+
+/// Prints the warnings reported by a client to the standard error, since the library never prints them.
+///
+/// # Arguments
+/// * `vault_client` - Client object that was used for the command.
+pub fn report_warnings(vault_client: &VaultClient) {
+    for warning in vault_client.take_warnings() {
+        eprintln!("Warning: {}", warning);
+    }
+}
+
+/// Executes a CLI subcommand against the server.
+///
+/// # Arguments
+/// * `command` - Subcommand parsed from the commandline arguments.
+/// * `vault_client` - Client object to make requests to the server.
+///
+/// # Returns
+/// * A `Result<Output, VaultError>` with the output of the command.
+pub fn execute(command: &Command, vault_client: &VaultClient) -> Result<Output, VaultError> {
+    let value = match command {
+        Command::Get { table_name, keys } => get(vault_client, table_name, keys)?,
+        Command::Put {
+            table_name,
+            secrets,
+        } => vault_client.put_secret(secrets, table_name)?,
+        Command::Delete { table_name, key } => vault_client.delete_secret(key, table_name)?,
+        Command::ListTables => Value::from(vault_client.list_tables()?),
+        Command::CreateTable { table_name } => vault_client.create_table(table_name)?,
+        Command::DeleteTable { table_name } => vault_client.delete_table(table_name)?,
+        Command::Exec {
+            table_name,
+            keys,
+            prefix,
+            override_env,
+            program,
+        } => {
+            let secrets = get(vault_client, table_name, keys)?;
+            return exec(&secrets, prefix, *override_env, program).map(Output::ExitCode);
+        }
+        Command::Import {
+            table_name,
+            path,
+            file_format,
+            create_table,
+            dry_run,
+            no_overwrite,
+            chunk_size,
+        } => {
+            let file_format = file_format.unwrap_or_else(|| FileFormat::from_path(path));
+            let secrets = import::read_file(path, file_format)?;
+            import_secrets(
+                vault_client,
+                table_name,
+                &secrets,
+                *create_table,
+                *dry_run,
+                false,
+                *no_overwrite,
+                *chunk_size,
+            )?
+        }
+        Command::Diff {
+            table_name,
+            path,
+            file_format,
+            reveal,
+        } => {
+            let file_format = file_format.unwrap_or_else(|| FileFormat::from_path(path));
+            let local = import::read_file(path, file_format)?;
+            let remote = match vault_client.get_table(table_name)?.expose() {
+                Value::Object(remote) => remote.clone(),
+                _ => Map::new(),
+            };
+            let diff = Diff::new(&local, &remote);
+            let mut value = diff.to_value(&local, &remote, *reveal)?;
+            value["table"] = json!(table_name);
+            value["file"] = json!(path.display().to_string());
+            if diff.has_drift() {
+                return Ok(Output::Drift(value));
+            }
+            value
+        }
+        Command::Plan { path, prune } => {
+            let plan = plan(vault_client, &Manifest::load(path)?, *prune)?;
+            if plan.has_changes() {
+                return Ok(Output::Drift(plan.to_value()));
+            }
+            plan.to_value()
+        }
+        Command::Apply {
+            path,
+            prune,
+            chunk_size,
+        } => {
+            let manifest = Manifest::load(path)?;
+            let plan = plan(vault_client, &manifest, *prune)?;
+            apply(vault_client, &manifest, &plan, *chunk_size)?;
+            plan.to_value()
+        }
+        Command::Export {
+            table_name,
+            keys,
+            export_format,
+            output,
+            name,
+            namespace,
+        } => {
+            let secrets = match get(vault_client, table_name, keys)? {
+                Value::Object(secrets) => secrets,
+                _ => Map::new(),
+            };
+            let rendered = Secret::new(export::render(
+                table_name,
+                &secrets,
+                *export_format,
+                name.as_deref(),
+                namespace.as_deref(),
+            )?);
+            match output {
+                Some(path) => {
+                    util::write_private(path, rendered.expose().as_bytes())?;
+                    Value::String(format!(
+                        "Exported {} secret(s) from '{}' to {}",
+                        secrets.len(),
+                        table_name,
+                        path.display()
+                    ))
+                }
+                None => return Ok(Output::Text(rendered)),
+            }
+        }
+        // These commands load their own configuration, instead of using the client
+        Command::Copy { .. } | Command::Login { .. } | Command::Logout => {
+            return dispatch(command, profile::selected_name().as_deref())
+        }
+    };
+    Ok(Output::Value(value))
+}
+
+/// Runs a CLI subcommand, with a client for the current configuration when the command needs one.
+///
+/// # Arguments
+/// * `command` - Subcommand parsed from the commandline arguments.
+/// * `profile` - Profile selected with `--profile` or `VAULTAPI_PROFILE`, or `None` for the default profile.
+///
+/// # Returns
+/// * A `Result<Output, VaultError>` with the output of the command.
+pub fn dispatch(command: &Command, profile: Option<&str>) -> Result<Output, VaultError> {
+    match command {
+        // Credentials are not required to be set, before they are stored
+        Command::Login { stdin, store } => login(*stdin, *store, profile),
+        Command::Logout => logout(profile),
+        // Each side of the copy has its own configuration, from the given profile
+        Command::Copy {
+            from,
+            to,
+            keys,
+            create_table,
+            dry_run,
+            prune,
+            chunk_size,
+        } => copy(
+            from,
+            to,
+            keys,
+            *create_table,
+            *dry_run,
+            *prune,
+            *chunk_size,
+            profile,
+        ),
+        command => {
+            let vault_client = VaultClient::new(parser::env_variables_with_store(profile)?)?;
+            let output = execute(command, &vault_client);
+            report_warnings(&vault_client);
+            output
+        }
+    }
+}
+
+/// Imports the secrets into a table, uploading only the secrets that are new or changed.
+///
+/// # Arguments
+/// * `vault_client` - Client object to make requests to the server.
+/// * `table_name` - Table name to import the secrets into.
+/// * `secrets` - Secrets to import.
+/// * `create_table` - Creates the table if it doesn't exist.
+/// * `dry_run` - Reports the changes without uploading the secrets.
+/// * `prune` - Deletes the keys in the table that are not in the secrets.
+/// * `no_overwrite` - Fails without uploading anything, when a key exists in the table with a different value.
+/// * `chunk_size` - Maximum number of secrets to upload with each request.
+///
+/// # Returns
+/// * A `Result<Value, VaultError>` with a summary of the added, overwritten, unchanged and deleted keys,
+///   or a `VaultError::Partial` with the keys that were and weren't changed when a request fails midway.
+#[allow(clippy::too_many_arguments)]
+pub fn import_secrets(
+    vault_client: &VaultClient,
+    table_name: &str,
+    secrets: &BTreeMap<String, String>,
+    create_table: bool,
+    dry_run: bool,
+    prune: bool,
+    no_overwrite: bool,
+    chunk_size: usize,
+) -> Result<Value, VaultError> {
+    let exists = vault_client
+        .list_tables()?
+        .iter()
+        .any(|table| table == table_name);
+    let existing = match exists {
+        true => match vault_client.get_table(table_name)?.expose() {
+            Value::Object(existing) => existing.clone(),
+            _ => Map::new(),
+        },
+        false if create_table => Map::new(),
+        false => {
+            return Err(VaultError::InvalidArgument(format!(
+                "Table '{}' doesn't exist, use --create-table to create it",
+                table_name
+            )))
+        }
+    };
+    let plan = ImportPlan::new(secrets, &existing);
+    if no_overwrite && !plan.overwritten.is_empty() {
+        return Err(VaultError::InvalidArgument(format!(
+            "Keys already exist in '{}' with different values: {}",
+            table_name,
+            plan.overwritten.join(", ")
+        )));
+    }
+    let mut deleted: Vec<String> = match prune {
+        true => existing
+            .keys()
+            .filter(|key| !secrets.contains_key(key.as_str()))
+            .cloned()
+            .collect(),
+        false => Vec::new(),
+    };
+    deleted.sort();
+    if !dry_run {
+        if !exists {
+            vault_client.create_table(table_name)?;
+        }
+        // Changes are listed as they are applied, so a failure reports what is left to retry
+        let mut remaining: Vec<String> = plan
+            .added
+            .iter()
+            .chain(&plan.overwritten)
+            .cloned()
+            .chain(deleted.iter().map(|key| format!("delete {}", key)))
+            .collect();
+        let mut applied = Vec::new();
+        let partial = |error, applied: Vec<String>, remaining: Vec<String>| VaultError::Partial {
+            applied,
+            remaining,
+            error: Box::new(error),
+        };
+        for chunk in plan.chunks(secrets, chunk_size) {
+            if let Err(err) = vault_client.put_secret(&chunk, table_name) {
+                return Err(partial(err, applied, remaining));
+            }
+            applied.extend(remaining.drain(..chunk.len()));
+        }
+        for key in &deleted {
+            if let Err(err) = vault_client.delete_secret(key, table_name) {
+                return Err(partial(err, applied, remaining));
+            }
+            applied.extend(remaining.drain(..1));
+        }
+    }
+    Ok(json!({
+        "table": table_name,
+        "created": !exists,
+        "dry_run": dry_run,
+        "added": plan.added,
+        "overwritten": plan.overwritten,
+        "unchanged": plan.unchanged,
+        "deleted": deleted,
+    }))
+}
+
+/// Creates a client for the server of an endpoint's profile.
+///
+/// The env vars are not applied to the profile, so both the sides of a copy only use their own profile's settings.
+///
+/// # Arguments
+/// * `endpoint` - Endpoint parsed from `profile:table`
+/// * `selected` - Profile selected for the current configuration, used when the endpoint has none.
+///
+/// # Returns
+/// * A `Result<VaultClient, VaultError>` for the profile, or the current configuration without one.
+fn endpoint_client(endpoint: &Endpoint, selected: Option<&str>) -> Result<VaultClient, VaultError> {
+    match &endpoint.profile {
+        Some(name) => {
+            let path = profile::config_path().ok_or_else(|| {
+                VaultError::MissingConfig(
+                    "Unable to locate the config file, set VAULTAPI_CONFIG".to_string(),
+                )
+            })?;
+            let profile = profile::load(&path, Some(name))?;
+            VaultClient::new(parser::env_config_with_store(
+                profile,
+                Precedence::ProfileOnly,
+            )?)
+        }
+        None => VaultClient::new(parser::env_variables_with_store(selected)?),
+    }
+}
+
+/// Copies the secrets from a table to another, decrypting them with the source's credentials
+/// and uploading them with the destination's.
+///
+/// # Arguments
+/// * `from` - Source table, and the profile for its server.
+/// * `to` - Destination table, and the profile for its server.
+/// * `keys` - Secret keys to copy, or all the secrets when empty.
+/// * `create_table` - Creates the destination table if it doesn't exist.
+/// * `dry_run` - Reports the changes without uploading the secrets.
+/// * `prune` - Deletes the keys in the destination that are not in the source.
+/// * `chunk_size` - Maximum number of secrets to upload with each request.
+/// * `selected` - Profile selected for the current configuration, used by the endpoints without one.
+///
+/// # Returns
+/// * A `Result<Output, VaultError>` with a summary of the changes to the destination.
+#[allow(clippy::too_many_arguments)]
+pub fn copy(
+    from: &Endpoint,
+    to: &Endpoint,
+    keys: &[String],
+    create_table: bool,
+    dry_run: bool,
+    prune: bool,
+    chunk_size: usize,
+    selected: Option<&str>,
+) -> Result<Output, VaultError> {
+    let source = endpoint_client(from, selected)?;
+    let destination = match endpoint_client(to, selected) {
+        Ok(destination) => destination,
+        Err(err) => {
+            report_warnings(&source);
+            return Err(err);
+        }
+    };
+    let output = copy_secrets(
+        &source,
+        &destination,
+        from,
+        to,
+        keys,
+        create_table,
+        dry_run,
+        prune,
+        chunk_size,
+    );
+    report_warnings(&source);
+    report_warnings(&destination);
+    output
+}
+
+/// Copies the secrets between the clients of the source and destination.
+///
+/// Arguments are the same as `copy`, along with the clients for both the endpoints.
+///
+/// # Returns
+/// * A `Result<Output, VaultError>` with a summary of the changes to the destination.
+#[allow(clippy::too_many_arguments)]
+fn copy_secrets(
+    source: &VaultClient,
+    destination: &VaultClient,
+    from: &Endpoint,
+    to: &Endpoint,
+    keys: &[String],
+    create_table: bool,
+    dry_run: bool,
+    prune: bool,
+    chunk_size: usize,
+) -> Result<Output, VaultError> {
+    if same_server(
+        &source.env_config.vault_server,
+        &destination.env_config.vault_server,
+    ) && from.table_name == to.table_name
+    {
+        return Err(VaultError::InvalidArgument(format!(
+            "Source and destination are the same table '{}' on {}",
+            from.table_name, source.env_config.vault_server
+        )));
+    }
+    let secrets: BTreeMap<String, String> = match get(source, &from.table_name, keys)? {
+        Value::Object(secrets) => secrets
+            .iter()
+            .map(|(key, value)| (key.to_string(), format::plain(value)))
+            .collect(),
+        _ => BTreeMap::new(),
+    };
+    let mut value = import_secrets(
+        destination,
+        &to.table_name,
+        &secrets,
+        create_table,
+        dry_run,
+        prune,
+        false,
+        chunk_size,
+    )?;
+    if let Value::Object(summary) = &mut value {
+        summary.remove("table");
+    }
+    value["from"] =
+        json!({ "server": source.env_config.vault_server.as_str(), "table": from.table_name });
+    value["to"] =
+        json!({ "server": destination.env_config.vault_server.as_str(), "table": to.table_name });
+    Ok(Output::Value(value))
+}
+
+/// Checks if the URLs are of the same server, ignoring a trailing slash in the path.
+///
+/// # Arguments
+/// * `first` - URL of the first server.
+/// * `second` - URL of the second server.
+///
+/// # Returns
+/// * A boolean flag to indicate whether the URLs point to the same server.
+fn same_server(first: &Url, second: &Url) -> bool {
+    first.origin() == second.origin()
+        && first.path().trim_end_matches('/') == second.path().trim_end_matches('/')
+}
+
+/// Computes the changes to converge the server with a manifest.
+///
+/// # Arguments
+/// * `vault_client` - Client object to make requests to the server.
+/// * `manifest` - Desired state of the tables.
+/// * `prune` - Deletes the tables and keys that are not in the manifest.
+///
+/// # Returns
+/// * A `Result<Plan, VaultError>` with the changes for each table.
+pub fn plan(
+    vault_client: &VaultClient,
+    manifest: &Manifest,
+    prune: bool,
+) -> Result<Plan, VaultError> {
+    let existing = vault_client.list_tables()?;
+    let mut current = BTreeMap::new();
+    for table_name in existing
+        .iter()
+        .filter(|table| manifest.tables.contains_key(table.as_str()))
+    {
+        let secrets = match vault_client.get_table(table_name)?.expose() {
+            Value::Object(secrets) => secrets.clone(),
+            _ => Map::new(),
+        };
+        current.insert(table_name.to_string(), secrets);
+    }
+    Ok(Plan::new(manifest, &existing, &current, prune))
+}
+
+/// Applies the planned changes, creating the tables before uploading the secrets, and deleting last.
+///
+/// # Arguments
+/// * `vault_client` - Client object to make requests to the server.
+/// * `manifest` - Desired state of the tables.
+/// * `plan` - Changes computed with `plan`
+/// * `chunk_size` - Maximum number of secrets to upload with each request.
+///
+/// # Returns
+/// * A `Result` indicating whether all the changes were applied,
+///   or a `VaultError::Partial` with the tables that were and weren't changed when a request fails midway.
+pub fn apply(
+    vault_client: &VaultClient,
+    manifest: &Manifest,
+    plan: &Plan,
+    chunk_size: usize,
+) -> Result<(), VaultError> {
+    let mut remaining: Vec<String> = plan
+        .tables
+        .iter()
+        .filter(|(_, table)| table.action != Action::Unchanged)
+        .map(|(table_name, _)| table_name.to_string())
+        .collect();
+    let mut applied = Vec::new();
+    while let Some(table_name) = remaining.first() {
+        let table = &plan.tables[table_name];
+        let secrets = manifest.tables.get(table_name);
+        if let Err(err) = apply_table(
+            vault_client,
+            table_name,
+            table,
+            secrets,
+            plan.prune,
+            chunk_size,
+        ) {
+            return Err(VaultError::Partial {
+                applied,
+                remaining,
+                error: Box::new(err),
+            });
+        }
+        applied.push(remaining.remove(0));
+    }
+    Ok(())
+}
+
+/// Applies the planned changes to a single table.
+///
+/// # Arguments
+/// * `vault_client` - Client object to make requests to the server.
+/// * `table_name` - Table name to apply the changes to.
+/// * `table` - Changes planned for the table.
+/// * `secrets` - Secrets of the table in the manifest, which is `None` for the tables to delete.
+/// * `prune` - Deletes the keys that are not in the manifest.
+/// * `chunk_size` - Maximum number of secrets to upload with each request.
+///
+/// # Returns
+/// * A `Result` indicating whether the changes were applied.
+fn apply_table(
+    vault_client: &VaultClient,
+    table_name: &str,
+    table: &TablePlan,
+    secrets: Option<&BTreeMap<String, String>>,
+    prune: bool,
+    chunk_size: usize,
+) -> Result<(), VaultError> {
+    match (table.action, secrets) {
+        (Action::Delete, _) => {
+            vault_client.delete_table(table_name)?;
+        }
+        (action, Some(secrets)) => {
+            if action == Action::Create {
+                vault_client.create_table(table_name)?;
+            }
+            for chunk in table.secrets.chunks(secrets, chunk_size) {
+                vault_client.put_secret(&chunk, table_name)?;
+            }
+            if prune {
+                for key in &table.removed {
+                    vault_client.delete_secret(key, table_name)?;
+                }
+            }
+        }
+        (_, None) => {}
+    }
+    Ok(())
+}
+
+/// Stores the credentials for the server, after verifying them against the server.
+///
+/// # Arguments
+/// * `stdin` - Reads the credentials from the standard input instead of the terminal.
+/// * `preferred` - Preferred store for the credentials.
+/// * `name` - Profile selected with `--profile` or `VAULTAPI_PROFILE`, or `None` for the default profile.
+///
+/// # Returns
+/// * A `Result<Output, VaultError>` with a message about where the credentials were stored.
+pub fn login(stdin: bool, preferred: Store, name: Option<&str>) -> Result<Output, VaultError> {
+    let profile = profile::selected(name)?;
+    let precedence = Precedence::selected(name);
+    let vault_server = parser::vault_server(&profile, precedence)?;
+    let stored = credentials::read(stdin)?;
+    let env_config = parser::env_config_with(profile, precedence, stored.clone())?;
+    let vault_client = VaultClient::new(env_config)?;
+    let validated = vault_client.list_tables();
+    report_warnings(&vault_client);
+    validated?;
+    let (store, warning) = credentials::store(vault_server.as_str(), &stored, preferred)?;
+    if let Some(warning) = warning {
+        eprintln!("Warning: {}", warning);
+    }
+    let location = match store {
+        Store::Keyring => "the OS keyring".to_string(),
+        Store::File => credentials::credentials_path()?.display().to_string(),
+    };
+    Ok(Output::Value(Value::String(format!(
+        "Credentials for {} stored in {}",
+        vault_server, location
+    ))))
+}
+
+/// Removes the stored credentials for the server.
+///
+/// # Arguments
+/// * `name` - Profile selected with `--profile` or `VAULTAPI_PROFILE`, or `None` for the default profile.
+///
+/// # Returns
+/// * A `Result<Output, VaultError>` with a message about the removed credentials.
+pub fn logout(name: Option<&str>) -> Result<Output, VaultError> {
+    let vault_server = parser::vault_server(&profile::selected(name)?, Precedence::selected(name))?;
+    let message = if credentials::remove(vault_server.as_str())? {
+        format!("Credentials for {} removed", vault_server)
+    } else {
+        format!("No credentials stored for {}", vault_server)
+    };
+    Ok(Output::Value(Value::String(message)))
+}
+
+/// Retrieves the given secret keys, or the entire table when no keys are given.
+///
+/// # Arguments
+/// * `vault_client` - Client object to make requests to the server.
+/// * `table_name` - Table name where the secrets are stored.
+/// * `keys` - Secret keys to retrieve.
+///
+/// # Returns
+/// * A `Result<Value, VaultError>` with the decrypted secrets.
+fn get(vault_client: &VaultClient, table_name: &str, keys: &[String]) -> Result<Value, VaultError> {
+    match keys {
+        [] => Ok(vault_client.get_table(table_name)?.expose().clone()),
+        [key] => Ok(vault_client.get_secret(key, table_name)?.expose().clone()),
+        _ => {
+            let keys: Vec<&str> = keys.iter().map(String::as_str).collect();
+            let secrets = vault_client.get_secrets(&keys, table_name)?;
+            Ok(Value::Object(
+                secrets
+                    .iter()
+                    .map(|(key, value)| (key.to_string(), value.expose().clone()))
+                    .collect(),
+            ))
+        }
+    }
+}
+
+/// Runs a command with the secrets set as environment variables, and waits for it to finish.
+///
+/// # Arguments
+/// * `secrets` - Decrypted secrets as a JSON object.
+/// * `prefix` - Prefix to add to each environment variable's name.
+/// * `override_env` - Boolean flag to override the environment variables that are already set.
+/// * `program` - Command to run, along with its arguments.
+///
+/// # Returns
+/// * A `Result` with the exit code of the command.
+fn exec(
+    secrets: &Value,
+    prefix: &str,
+    override_env: bool,
+    program: &[String],
+) -> Result<i32, VaultError> {
+    let secrets = match secrets {
+        Value::Object(secrets) => secrets,
+        _ => {
+            return Err(VaultError::MalformedResponse(
+                "Secrets are not a JSON object".to_string(),
+            ))
+        }
+    };
+    let mut process = std::process::Command::new(&program[0]);
+    process.args(&program[1..]);
+    for (key, value) in secrets {
+        let name = format!("{}{}", prefix, key);
+        if !override_env && std::env::var_os(&name).is_some() {
+            continue;
+        }
+        match value {
+            // Secrets without a value are left unset, instead of being passed as "null"
+            Value::Null => continue,
+            Value::String(value) => process.env(name, value),
+            _ => process.env(name, value.to_string()),
+        };
+    }
+    let status = process
+        .status()
+        .map_err(|err| VaultError::Io(format!("Failed to run '{}': {}", program[0], err)))?;
+    Ok(exit_code(status))
+}
+
+/// Converts the exit status of a child process into an exit code, following the shell's convention.
+///
+/// # Arguments
+/// * `status` - Exit status of the child process.
+///
+/// # Returns
+/// * An `i32` with the exit code, or `128 + signal` when the process was terminated by a signal.
+fn exit_code(status: std::process::ExitStatus) -> i32 {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+        if let Some(signal) = status.signal() {
+            return 128 + signal;
+        }
+    }
+    status.code().unwrap_or(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Runs a shell script with `exec`, and returns its exit code.
+    #[cfg(unix)]
+    fn run(secrets: Value, script: &str) -> i32 {
+        let program = ["sh", "-c", script].map(str::to_string);
+        exec(&secrets, "VAULTAPI_EXEC_", true, &program).unwrap()
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn exec_passes_the_secrets_and_exit_code() {
+        let secrets = json!({"PASSWORD": "p@ss word", "PORT": 5432, "EMPTY": null});
+        assert_eq!(
+            run(
+                secrets.clone(),
+                r#"[ "$VAULTAPI_EXEC_PASSWORD" = "p@ss word" ] && [ "$VAULTAPI_EXEC_PORT" = 5432 ]"#
+            ),
+            0
+        );
+        // Null values are not set at all, rather than as the string "null"
+        assert_eq!(
+            run(secrets.clone(), r#"[ -z "${VAULTAPI_EXEC_EMPTY+set}" ]"#),
+            0
+        );
+        assert_eq!(run(secrets.clone(), "exit 3"), 3);
+        // Processes terminated by a signal exit with 128 + the signal number, like a shell
+        assert_eq!(run(secrets, "kill -TERM $$"), 143);
+        assert!(matches!(
+            exec(&json!("text"), "", true, &["true".to_string()]),
+            Err(VaultError::MalformedResponse(_))
+        ));
+    }
+
+    #[test]
+    fn same_server_ignores_the_url_spelling() {
+        let url = |url: &str| Url::parse(url).unwrap();
+        assert!(same_server(
+            &url("https://Vault.example.com:443"),
+            &url("https://vault.example.com/")
+        ));
+        assert!(same_server(
+            &url("http://localhost:8080/api/"),
+            &url("http://localhost:8080/api")
+        ));
+        assert!(!same_server(
+            &url("http://localhost:8080"),
+            &url("http://localhost:8081")
+        ));
+        assert!(!same_server(
+            &url("http://localhost:8080/dev"),
+            &url("http://localhost:8080/prod")
+        ));
+    }
+}
@@ -0,0 +1,442 @@
+use crate::error::{VaultError, Warning};
+use crate::profile;
+use crate::secret::Secret;
+use crate::util;
+use base64::{engine::general_purpose, Engine as _};
+use ring::aead::{self, Aad, LessSafeKey, Nonce, UnboundKey};
+use ring::pbkdf2;
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::{Deserialize, Serialize, Serializer};
+use std::collections::HashMap;
+use std::fs;
+use std::io::IsTerminal;
+use std::num::NonZeroU32;
+use std::path::PathBuf;
+use zeroize::Zeroizing;
+
+/// Service name for the entries in the OS keyring.
+#[cfg(feature = "keyring")]
+const SERVICE: &str = "vaultapi";
+/// Number of PBKDF2 iterations to derive the key from the passphrase.
+#[cfg(not(test))]
+const ITERATIONS: u32 = 600_000;
+/// Fewer iterations in the unit tests, since the count is stored in the file and read back.
+#[cfg(test)]
+const ITERATIONS: u32 = 1_000;
+/// Length of the random salt for PBKDF2.
+const SALT_LEN: usize = 16;
+
+/// Credentials to authenticate a VaultAPI server.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Credentials {
+    #[serde(serialize_with = "serialize_secret")]
+    pub apikey: Secret<String>,
+    #[serde(serialize_with = "serialize_secret")]
+    pub secret: Secret<String>,
+}
+
+/// Serializes a secret value, which is only done to store it in the keyring or the encrypted file.
+fn serialize_secret<S: Serializer>(
+    secret: &Secret<String>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(secret.expose())
+}
+
+/// Enum to represent where the credentials are stored.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Store {
+    /// Secret Service on Linux, Keychain on macOS and Credential Manager on Windows.
+    Keyring,
+    /// File encrypted with a passphrase, for headless machines without a keyring.
+    File,
+}
+
+/// Credentials file, with the credentials of every server encrypted using a passphrase.
+#[derive(Serialize, Deserialize)]
+struct CredentialsFile {
+    iterations: u32,
+    salt: String,
+    nonce: String,
+    ciphertext: String,
+}
+
+/// Decrypted contents of the credentials file, along with the passphrase to save them again.
+struct Unlocked {
+    servers: HashMap<String, Credentials>,
+    passphrase: Secret<String>,
+}
+
+/// Returns the path to the credentials file.
+///
+/// # Returns
+/// * A `Result` with `VAULTAPI_CREDENTIALS` if set, otherwise `~/.config/vaultapi/credentials.json`.
+pub fn credentials_path() -> Result<PathBuf, VaultError> {
+    if let Some(path) = std::env::var_os("VAULTAPI_CREDENTIALS").filter(|path| !path.is_empty()) {
+        return Ok(PathBuf::from(path));
+    }
+    profile::config_path()
+        .and_then(|path| path.parent().map(|dir| dir.join("credentials.json")))
+        .ok_or_else(|| {
+            VaultError::MissingConfig(
+                "Unable to locate the credentials file, set VAULTAPI_CREDENTIALS".to_string(),
+            )
+        })
+}
+
+/// Reads a value from the terminal without echoing it.
+fn prompt(message: &str) -> Result<Secret<String>, VaultError> {
+    rpassword::prompt_password(message)
+        .map(Secret::new)
+        .map_err(|err| VaultError::Io(format!("Failed to read from the terminal: {}", err)))
+}
+
+/// Retrieves the passphrase for the credentials file, from `VAULTAPI_PASSPHRASE` or the terminal.
+///
+/// # Arguments
+/// * `confirm` - Asks for the passphrase twice, when creating a new file.
+///
+/// # Returns
+/// * A `Result` with the passphrase.
+fn passphrase(confirm: bool) -> Result<Secret<String>, VaultError> {
+    if let Ok(passphrase) = std::env::var("VAULTAPI_PASSPHRASE") {
+        return Ok(Secret::new(passphrase));
+    }
+    if !std::io::stdin().is_terminal() {
+        return Err(VaultError::MissingConfig(
+            "Credentials file is encrypted, set VAULTAPI_PASSPHRASE to unlock it".to_string(),
+        ));
+    }
+    let passphrase = prompt("Passphrase for the credentials file: ")?;
+    if confirm && prompt("Confirm the passphrase: ")?.expose() != passphrase.expose() {
+        return Err(VaultError::InvalidArgument(
+            "Passphrases don't match".to_string(),
+        ));
+    }
+    if passphrase.expose().is_empty() {
+        return Err(VaultError::InvalidArgument(
+            "Passphrase can't be empty".to_string(),
+        ));
+    }
+    Ok(passphrase)
+}
+
+/// Derives the AES-256 key from the passphrase.
+fn derive_key(
+    passphrase: &Secret<String>,
+    salt: &[u8],
+    iterations: u32,
+) -> Result<LessSafeKey, VaultError> {
+    let iterations = NonZeroU32::new(iterations).ok_or_else(|| {
+        VaultError::Decryption("Invalid iterations in the credentials file".to_string())
+    })?;
+    let mut key = Zeroizing::new([0u8; 32]);
+    pbkdf2::derive(
+        pbkdf2::PBKDF2_HMAC_SHA256,
+        iterations,
+        salt,
+        passphrase.expose().as_bytes(),
+        key.as_mut(),
+    );
+    let unbound_key = UnboundKey::new(&aead::AES_256_GCM, key.as_ref())
+        .map_err(|_| VaultError::Decryption("Failed to create the AES key".to_string()))?;
+    Ok(LessSafeKey::new(unbound_key))
+}
+
+/// Decodes a base64 field of the credentials file.
+fn decode(value: &str) -> Result<Vec<u8>, VaultError> {
+    general_purpose::STANDARD
+        .decode(value)
+        .map_err(|err| VaultError::Decryption(format!("Invalid credentials file: {}", err)))
+}
+
+/// Reads and decrypts the credentials file.
+///
+/// # Returns
+/// * A `Result` with an `Option` of the credentials of every server, if the file exists.
+fn load_file() -> Result<Option<Unlocked>, VaultError> {
+    let path = credentials_path()?;
+    let contents = match fs::read(&path) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err.into()),
+    };
+    let file: CredentialsFile = serde_json::from_slice(&contents).map_err(|err| {
+        VaultError::Decryption(format!(
+            "Invalid credentials file '{}': {}",
+            path.display(),
+            err
+        ))
+    })?;
+    let passphrase = passphrase(false)?;
+    let key = derive_key(&passphrase, &decode(&file.salt)?, file.iterations)?;
+    let nonce = Nonce::try_assume_unique_for_key(&decode(&file.nonce)?)
+        .map_err(|_| VaultError::Decryption("Invalid nonce".to_string()))?;
+    // The credentials are decrypted in place, so the buffer is wiped once they are parsed
+    let mut ciphertext = Zeroizing::new(decode(&file.ciphertext)?);
+    let plaintext = key
+        .open_in_place(nonce, Aad::empty(), &mut ciphertext)
+        .map_err(|_| {
+            VaultError::Decryption(
+                "Failed to decrypt the credentials file, incorrect passphrase".to_string(),
+            )
+        })?;
+    let servers =
+        serde_json::from_slice(plaintext).map_err(|err| VaultError::Decryption(err.to_string()))?;
+    Ok(Some(Unlocked {
+        servers,
+        passphrase,
+    }))
+}
+
+/// Encrypts and writes the credentials file, readable only by the owner.
+fn save_file(unlocked: &Unlocked) -> Result<(), VaultError> {
+    let rng = SystemRandom::new();
+    let mut salt = [0u8; SALT_LEN];
+    let mut nonce_bytes = [0u8; aead::NONCE_LEN];
+    rng.fill(&mut salt)
+        .and_then(|_| rng.fill(&mut nonce_bytes))
+        .map_err(|_| VaultError::Io("Failed to generate a salt".to_string()))?;
+    let key = derive_key(&unlocked.passphrase, &salt, ITERATIONS)?;
+    // The buffer is wiped when dropped, and has room for the tag so that it is never reallocated
+    let mut buffer = serde_json::to_vec(&unlocked.servers)
+        .map(Zeroizing::new)
+        .map_err(|err| VaultError::Io(format!("Failed to serialize the credentials: {}", err)))?;
+    buffer.reserve_exact(aead::AES_256_GCM.tag_len());
+    key.seal_in_place_append_tag(
+        Nonce::assume_unique_for_key(nonce_bytes),
+        Aad::empty(),
+        &mut *buffer,
+    )
+    .map_err(|_| VaultError::Io("Failed to encrypt the credentials".to_string()))?;
+    let file = CredentialsFile {
+        iterations: ITERATIONS,
+        salt: general_purpose::STANDARD.encode(salt),
+        nonce: general_purpose::STANDARD.encode(nonce_bytes),
+        ciphertext: general_purpose::STANDARD.encode(&*buffer),
+    };
+    let contents = serde_json::to_vec_pretty(&file)
+        .map_err(|err| VaultError::Io(format!("Failed to serialize the credentials: {}", err)))?;
+    util::write_private(&credentials_path()?, &contents)
+}
+
+/// Checks if the keyring is unavailable, as on headless machines without a Secret Service.
+#[cfg(feature = "keyring")]
+fn keyring_unavailable(err: &keyring::Error) -> bool {
+    matches!(
+        err,
+        keyring::Error::PlatformFailure(_) | keyring::Error::NoStorageAccess(_)
+    )
+}
+
+/// Stores the credentials in the OS keyring.
+///
+/// # Returns
+/// * A `Result` with an `Option` of the warning to report, when the keyring is unavailable.
+#[cfg(feature = "keyring")]
+fn store_keyring(server: &str, credentials: &Credentials) -> Result<Option<Warning>, VaultError> {
+    let payload = serde_json::to_string(credentials)
+        .map(Secret::new)
+        .map_err(|err| VaultError::Io(format!("Failed to serialize the credentials: {}", err)))?;
+    match keyring::Entry::new(SERVICE, server)
+        .and_then(|entry| entry.set_password(payload.expose()))
+    {
+        Ok(()) => Ok(None),
+        Err(err) if keyring_unavailable(&err) => {
+            Ok(Some(Warning::KeyringUnavailable(err.to_string())))
+        }
+        Err(err) => Err(VaultError::Io(format!(
+            "Failed to update the keyring: {}",
+            err
+        ))),
+    }
+}
+
+#[cfg(not(feature = "keyring"))]
+fn store_keyring(_server: &str, _credentials: &Credentials) -> Result<Option<Warning>, VaultError> {
+    Ok(Some(Warning::KeyringUnavailable(
+        "built without keyring support".to_string(),
+    )))
+}
+
+/// Stores the credentials for a server, in the OS keyring or the encrypted credentials file.
+///
+/// # Arguments
+/// * `server` - VaultAPI server URL.
+/// * `credentials` - Credentials to store.
+/// * `preferred` - Preferred store, which falls back to the file when the keyring is unavailable.
+///
+/// # Returns
+/// * A `Result` with the store where the credentials were saved, and a warning if the keyring was unavailable.
+pub fn store(
+    server: &str,
+    credentials: &Credentials,
+    preferred: Store,
+) -> Result<(Store, Option<Warning>), VaultError> {
+    let warning = match preferred {
+        Store::Keyring => match store_keyring(server, credentials)? {
+            None => return Ok((Store::Keyring, None)),
+            warning => warning,
+        },
+        Store::File => None,
+    };
+    let mut unlocked = match load_file()? {
+        Some(unlocked) => unlocked,
+        None => Unlocked {
+            servers: HashMap::new(),
+            passphrase: passphrase(true)?,
+        },
+    };
+    unlocked
+        .servers
+        .insert(server.to_string(), credentials.clone());
+    save_file(&unlocked)?;
+    Ok((Store::File, warning))
+}
+
+/// Retrieves the credentials from the OS keyring.
+#[cfg(feature = "keyring")]
+fn lookup_keyring(server: &str) -> Result<Option<Credentials>, VaultError> {
+    match keyring::Entry::new(SERVICE, server).and_then(|entry| entry.get_password()) {
+        Ok(payload) => serde_json::from_str(Secret::new(payload).expose())
+            .map(Some)
+            .map_err(|err| {
+                VaultError::Decryption(format!("Invalid credentials in the keyring: {}", err))
+            }),
+        Err(keyring::Error::NoEntry) => Ok(None),
+        Err(err) if keyring_unavailable(&err) => Ok(None),
+        Err(err) => Err(VaultError::Io(format!(
+            "Failed to read the keyring: {}",
+            err
+        ))),
+    }
+}
+
+#[cfg(not(feature = "keyring"))]
+fn lookup_keyring(_server: &str) -> Result<Option<Credentials>, VaultError> {
+    Ok(None)
+}
+
+/// Retrieves the stored credentials for a server, from the OS keyring or the encrypted credentials file.
+///
+/// # Arguments
+/// * `server` - VaultAPI server URL.
+///
+/// # Returns
+/// * A `Result` with an `Option` of the credentials, if they were stored with `vaultapi login`.
+pub fn lookup(server: &str) -> Result<Option<Credentials>, VaultError> {
+    if let Some(credentials) = lookup_keyring(server)? {
+        return Ok(Some(credentials));
+    }
+    Ok(load_file()?.and_then(|mut unlocked| unlocked.servers.remove(server)))
+}
+
+/// Removes the stored credentials for a server, from both the OS keyring and the credentials file.
+///
+/// # Arguments
+/// * `server` - VaultAPI server URL.
+///
+/// # Returns
+/// * A `Result` with a boolean flag indicating whether any credentials were removed.
+pub fn remove(server: &str) -> Result<bool, VaultError> {
+    #[cfg(feature = "keyring")]
+    let mut removed =
+        match keyring::Entry::new(SERVICE, server).and_then(|entry| entry.delete_credential()) {
+            Ok(()) => true,
+            Err(keyring::Error::NoEntry) => false,
+            Err(err) if keyring_unavailable(&err) => false,
+            Err(err) => {
+                return Err(VaultError::Io(format!(
+                    "Failed to update the keyring: {}",
+                    err
+                )))
+            }
+        };
+    #[cfg(not(feature = "keyring"))]
+    let mut removed = false;
+    if let Some(mut unlocked) = load_file()? {
+        if unlocked.servers.remove(server).is_some() {
+            save_file(&unlocked)?;
+            removed = true;
+        }
+    }
+    Ok(removed)
+}
+
+/// Prompts for the credentials on the terminal, or reads them as two lines from the standard input.
+///
+/// # Arguments
+/// * `stdin` - Reads the credentials from the standard input instead of the terminal.
+///
+/// # Returns
+/// * A `Result` with the credentials.
+pub fn read(stdin: bool) -> Result<Credentials, VaultError> {
+    let (apikey, secret) = if stdin {
+        let mut lines = std::io::stdin().lines();
+        let mut next = |name: &str| match lines.next() {
+            Some(line) => Ok(Secret::new(line?)),
+            None => Err(VaultError::InvalidArgument(format!(
+                "Expected the {} on the standard input",
+                name
+            ))),
+        };
+        (next("APIKEY")?, next("SECRET")?)
+    } else {
+        (prompt("APIKEY: ")?, prompt("SECRET: ")?)
+    };
+    if apikey.expose().is_empty() || secret.expose().is_empty() {
+        return Err(VaultError::InvalidArgument(
+            "APIKEY and SECRET can't be empty".to_string(),
+        ));
+    }
+    Ok(Credentials { apikey, secret })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn credentials_file_round_trip() {
+        let dir = std::env::temp_dir().join(format!("vaultapi-credentials-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let path = dir.join("credentials.json");
+        // Only this test reads these env vars, so they are not shared with other tests
+        std::env::set_var("VAULTAPI_CREDENTIALS", &path);
+        std::env::set_var("VAULTAPI_PASSPHRASE", "correct horse");
+        let server = "http://vaultapi.invalid:8080/";
+        let credentials = Credentials {
+            apikey: "apikey".into(),
+            secret: "secret".into(),
+        };
+        let (store, warning) = store(server, &credentials, Store::File).unwrap();
+        assert_eq!(store, Store::File);
+        assert!(warning.is_none());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        // The secrets are never written to the file in plain text
+        let contents = fs::read_to_string(&path).unwrap();
+        assert!(!contents.contains("apikey") && !contents.contains("secret"));
+
+        let found = lookup(server).unwrap().unwrap();
+        assert_eq!(found.apikey.expose(), "apikey");
+        assert_eq!(found.secret.expose(), "secret");
+        assert!(lookup("http://other.invalid/").unwrap().is_none());
+
+        std::env::set_var("VAULTAPI_PASSPHRASE", "wrong passphrase");
+        assert!(matches!(lookup(server), Err(VaultError::Decryption(_))));
+
+        std::env::set_var("VAULTAPI_PASSPHRASE", "correct horse");
+        assert!(remove(server).unwrap());
+        assert!(lookup(server).unwrap().is_none());
+        assert!(!remove(server).unwrap());
+
+        std::env::remove_var("VAULTAPI_CREDENTIALS");
+        std::env::remove_var("VAULTAPI_PASSPHRASE");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,76 +1,376 @@
+use crate::error::VaultError;
+use crate::secret::{self, Secret};
 use base64::{engine::general_purpose, Engine as _};
 use ring::aead::{self, Aad, LessSafeKey, Nonce, UnboundKey};
 use ring::digest;
+use ring::rand::{SecureRandom, SystemRandom};
 use serde_json::Value;
 use std::time::{SystemTime, UNIX_EPOCH};
+use zeroize::Zeroizing;
+
+/// Maximum number of adjacent epoch buckets to try on either side of the current bucket.
+pub const MAX_BUCKET_TOLERANCE: u64 = 60;
+
+/// Struct to store the decrypted payload along with the epoch bucket that was used to decrypt it.
+#[derive(Debug, Clone)]
+pub struct TransitPayload {
+    /// Decrypted JSON payload.
+    pub value: Value,
+    /// Epoch bucket that successfully decrypted the payload.
+    pub epoch: u64,
+    /// Offset of the successful epoch bucket, relative to the current bucket.
+    pub offset: i64,
+}
+
+/// Computes the current epoch bucket.
+///
+/// # Arguments
+/// * `transit_time_bucket` - Interval for which the transit epoch remains a constant.
+///
+/// # Returns
+/// * A `Result` with the current epoch bucket.
+pub fn current_epoch(transit_time_bucket: u64) -> Result<u64, VaultError> {
+    match SystemTime::now().duration_since(UNIX_EPOCH) {
+        Ok(duration) => Ok(duration.as_secs() / transit_time_bucket.max(1)),
+        Err(_) => Err(VaultError::Decryption(
+            "System time is before the UNIX epoch".to_string(),
+        )),
+    }
+}
+
+/// Derives the AES key for a particular epoch bucket using SHA-256.
+///
+/// # Arguments
+/// * `epoch` - Epoch bucket for which the key has to be derived.
+/// * `apikey` - APIkey to derive the AES key.
+/// * `secret` - Secret to derive the AES key.
+/// * `transit_key_length` - AES key length used for transit encryption.
+///
+/// # Returns
+/// * A `Secret` with the AES key, that is wiped from memory when dropped.
+pub fn derive_key(
+    epoch: u64,
+    apikey: &str,
+    secret: &str,
+    transit_key_length: usize,
+) -> Secret<Vec<u8>> {
+    let hash_input = Secret::concat(&[&epoch.to_string(), ".", apikey, ".", secret]);
+    let mut hash_output = digest::digest(&digest::SHA256, hash_input.expose().as_bytes());
+    let hash_bytes = hash_output.as_ref();
+    let aes_key = Secret::new(hash_bytes[..transit_key_length.min(hash_bytes.len())].to_vec());
+    secret::wipe_digest(&mut hash_output);
+    aes_key
+}
+
+/// Returns the offsets of the epoch buckets to try, starting with the current bucket.
+///
+/// # Arguments
+/// * `transit_bucket_tolerance` - Number of adjacent buckets to try on either side, up to `MAX_BUCKET_TOLERANCE`
+///
+/// # Returns
+/// * A `Vec<i64>` with offsets in the order of `0, -1, 1, -2, 2...`
+fn bucket_offsets(transit_bucket_tolerance: u64) -> Vec<i64> {
+    let mut offsets = vec![0];
+    for distance in 1..=transit_bucket_tolerance.min(MAX_BUCKET_TOLERANCE) as i64 {
+        offsets.push(-distance);
+        offsets.push(distance);
+    }
+    offsets
+}
 
 /// Decrypts a transit-encrypted payload.
 ///
 /// # Arguments
 /// * `apikey` - APIkey to derive the AES key.
+/// * `secret` - Secret to derive the AES key.
 /// * `ciphertext` - A base64-encoded encrypted string.
 /// * `transit_key_length` - AES key length used for transit encryption.
 /// * `transit_time_bucket` - Interval for which the transit epoch remains a constant.
+/// * `transit_bucket_tolerance` - Number of adjacent epoch buckets to try, to tolerate clock skew.
 ///
 /// # Returns
-/// * A `Result<Value, String>` containing the decrypted JSON payload or an error message.
+/// * A `Result<Value, VaultError>` containing the decrypted JSON payload or a decryption error.
 pub fn transit_decrypt(
-    apikey: &String,
-    secret: &String,
-    ciphertext: &String,
+    apikey: &str,
+    secret: &str,
+    ciphertext: &str,
     transit_key_length: usize,
     transit_time_bucket: u64,
-) -> Result<Value, String> {
-    // Compute the current epoch bucket
-    let epoch = match SystemTime::now().duration_since(UNIX_EPOCH) {
-        Ok(duration) => duration.as_secs(),
-        Err(_) => return Err("System time is before the UNIX epoch".into()),
-    };
-    let epoch = epoch / transit_time_bucket;
+    transit_bucket_tolerance: u64,
+) -> Result<Value, VaultError> {
+    transit_decrypt_bucket(
+        apikey,
+        secret,
+        ciphertext,
+        transit_key_length,
+        transit_time_bucket,
+        transit_bucket_tolerance,
+    )
+    .map(|payload| payload.value)
+}
 
-    // Derive the AES key using SHA-256
-    let hash_input = format!("{}.{}.{}", epoch, apikey, secret);
-    let hash_output = digest::digest(&digest::SHA256, hash_input.as_bytes());
-    let aes_key = &hash_output.as_ref()[..transit_key_length];
+/// Decrypts a transit-encrypted payload, and reports the epoch bucket that was used.
+///
+/// The current epoch bucket is tried first, followed by the previous and the next buckets,
+/// up to `transit_bucket_tolerance` buckets on either side.
+///
+/// # Arguments
+/// * `apikey` - APIkey to derive the AES key.
+/// * `secret` - Secret to derive the AES key.
+/// * `ciphertext` - A base64-encoded encrypted string.
+/// * `transit_key_length` - AES key length used for transit encryption.
+/// * `transit_time_bucket` - Interval for which the transit epoch remains a constant.
+/// * `transit_bucket_tolerance` - Number of adjacent epoch buckets to try, to tolerate clock skew.
+///
+/// # Returns
+/// * A `Result<TransitPayload, VaultError>` containing the decrypted JSON payload or a decryption error.
+pub fn transit_decrypt_bucket(
+    apikey: &str,
+    secret: &str,
+    ciphertext: &str,
+    transit_key_length: usize,
+    transit_time_bucket: u64,
+    transit_bucket_tolerance: u64,
+) -> Result<TransitPayload, VaultError> {
+    // Compute the current epoch bucket
+    let current = current_epoch(transit_time_bucket)?;
 
     // Decode the base64-encoded ciphertext
     let ciphertext_bytes = match general_purpose::STANDARD.decode(ciphertext) {
         Ok(bytes) => bytes,
-        Err(_) => return Err("Failed to decode ciphertext".into()),
+        Err(_) => {
+            return Err(VaultError::Decryption(
+                "Failed to decode ciphertext".to_string(),
+            ))
+        }
     };
 
     // Ensure the ciphertext is long enough
     if ciphertext_bytes.len() < 12 {
-        return Err("Ciphertext is too short".into());
+        return Err(VaultError::Decryption(
+            "Ciphertext is too short".to_string(),
+        ));
     }
 
     // Extract the nonce (first 12 bytes) and the actual encrypted data
     let (nonce_bytes, encrypted_data) = ciphertext_bytes.split_at(12);
 
-    // Initialize AES-GCM decryption
-    let unbound_key = match UnboundKey::new(&aead::AES_256_GCM, aes_key) {
+    for offset in bucket_offsets(transit_bucket_tolerance) {
+        let epoch = match current.checked_add_signed(offset) {
+            Some(epoch) => epoch,
+            None => continue,
+        };
+
+        // Initialize AES-GCM decryption
+        let aes_key = derive_key(epoch, apikey, secret, transit_key_length);
+        let unbound_key = match UnboundKey::new(&aead::AES_256_GCM, aes_key.expose()) {
+            Ok(key) => key,
+            Err(_) => {
+                return Err(VaultError::Decryption(
+                    "Failed to create AES key".to_string(),
+                ))
+            }
+        };
+        let key = LessSafeKey::new(unbound_key);
+
+        let nonce = match Nonce::try_assume_unique_for_key(nonce_bytes) {
+            Ok(n) => n,
+            Err(_) => return Err(VaultError::Decryption("Failed to create nonce".to_string())),
+        };
+
+        // Decrypt the data, and move on to the next bucket if the key doesn't match
+        // The plaintext is decrypted in place, so the buffer is wiped once it is parsed
+        let mut binding = Zeroizing::new(encrypted_data.to_vec());
+        let decrypted_data = match key.open_in_place(nonce, Aad::empty(), &mut binding) {
+            Ok(data) => data,
+            Err(_) => continue,
+        };
+
+        // Parse the decrypted data as JSON
+        return match serde_json::from_slice(decrypted_data) {
+            Ok(value) => Ok(TransitPayload {
+                value,
+                epoch,
+                offset,
+            }),
+            Err(_) => Err(VaultError::Decryption(
+                "Failed to parse decrypted data as JSON".to_string(),
+            )),
+        };
+    }
+    Err(VaultError::Decryption(format!(
+        "Failed to decrypt data within {} epoch bucket(s) of the current bucket",
+        transit_bucket_tolerance
+    )))
+}
+
+/// Encrypts a JSON payload in the same format as the transit encryption used by the server.
+///
+/// # Arguments
+/// * `apikey` - APIkey to derive the AES key.
+/// * `secret` - Secret to derive the AES key.
+/// * `value` - JSON payload to encrypt.
+/// * `transit_key_length` - AES key length used for transit encryption.
+/// * `transit_time_bucket` - Interval for which the transit epoch remains a constant.
+///
+/// # Returns
+/// * A `Result<String, VaultError>` containing the base64-encoded `nonce||ciphertext` string.
+pub fn transit_encrypt(
+    apikey: &str,
+    secret: &str,
+    value: &Value,
+    transit_key_length: usize,
+    transit_time_bucket: u64,
+) -> Result<String, VaultError> {
+    let epoch = current_epoch(transit_time_bucket)?;
+    transit_encrypt_epoch(apikey, secret, value, transit_key_length, epoch)
+}
+
+/// Encrypts a JSON payload for a specific epoch bucket.
+///
+/// # Arguments
+/// * `apikey` - APIkey to derive the AES key.
+/// * `secret` - Secret to derive the AES key.
+/// * `value` - JSON payload to encrypt.
+/// * `transit_key_length` - AES key length used for transit encryption.
+/// * `epoch` - Epoch bucket to derive the AES key.
+///
+/// # Returns
+/// * A `Result<String, VaultError>` containing the base64-encoded `nonce||ciphertext` string.
+pub fn transit_encrypt_epoch(
+    apikey: &str,
+    secret: &str,
+    value: &Value,
+    transit_key_length: usize,
+    epoch: u64,
+) -> Result<String, VaultError> {
+    let aes_key = derive_key(epoch, apikey, secret, transit_key_length);
+    let unbound_key = match UnboundKey::new(&aead::AES_256_GCM, aes_key.expose()) {
         Ok(key) => key,
-        Err(_) => return Err("Failed to create AES key".into()),
+        Err(_) => {
+            return Err(VaultError::Encryption(
+                "Failed to create AES key".to_string(),
+            ))
+        }
     };
     let key = LessSafeKey::new(unbound_key);
 
-    let nonce = match Nonce::try_assume_unique_for_key(nonce_bytes) {
-        Ok(n) => n,
-        Err(_) => return Err("Failed to create nonce".into()),
-    };
+    // Generate a random 12 byte nonce
+    let mut nonce_bytes = [0u8; 12];
+    if SystemRandom::new().fill(&mut nonce_bytes).is_err() {
+        return Err(VaultError::Encryption(
+            "Failed to generate nonce".to_string(),
+        ));
+    }
+    let nonce = Nonce::assume_unique_for_key(nonce_bytes);
 
-    // Decrypt the data
-    let mut binding = encrypted_data.to_vec();
-    let decrypted_data = match key.open_in_place(nonce, Aad::empty(), &mut binding) {
-        Ok(data) => data,
-        Err(_) => return Err("Failed to decrypt data".into()),
+    // Encrypt the serialized JSON payload, with the authentication tag appended to it
+    // The buffer is wiped when dropped, and has room for the tag so that it is never reallocated
+    let mut encrypted_data = match serde_json::to_vec(value) {
+        Ok(data) => Zeroizing::new(data),
+        Err(_) => {
+            return Err(VaultError::Encryption(
+                "Failed to serialize payload as JSON".to_string(),
+            ))
+        }
     };
+    encrypted_data.reserve_exact(aead::AES_256_GCM.tag_len());
+    if key
+        .seal_in_place_append_tag(nonce, Aad::empty(), &mut *encrypted_data)
+        .is_err()
+    {
+        return Err(VaultError::Encryption("Failed to encrypt data".to_string()));
+    }
 
-    // Parse the decrypted data as JSON
-    let decrypted_json: Value = match serde_json::from_slice(decrypted_data) {
-        Ok(json) => json,
-        Err(_) => return Err("Failed to parse decrypted data as JSON".into()),
-    };
+    // Prefix the nonce to the encrypted data
+    let mut ciphertext_bytes = nonce_bytes.to_vec();
+    ciphertext_bytes.extend_from_slice(&encrypted_data);
+    Ok(general_purpose::STANDARD.encode(ciphertext_bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
 
-    Ok(decrypted_json)
+    const APIKEY: &str = "apikey";
+    const SECRET: &str = "secret";
+    const KEY_LENGTH: usize = 32;
+    const TIME_BUCKET: u64 = 3600;
+
+    /// Decodes the ciphertext, flips a byte at the index and encodes it back.
+    fn tamper(ciphertext: &str, index: usize) -> String {
+        let mut bytes = general_purpose::STANDARD.decode(ciphertext).unwrap();
+        bytes[index] ^= 0x01;
+        general_purpose::STANDARD.encode(bytes)
+    }
+
+    fn decrypt(
+        secret: &str,
+        ciphertext: &str,
+        tolerance: u64,
+    ) -> Result<TransitPayload, VaultError> {
+        transit_decrypt_bucket(
+            APIKEY,
+            secret,
+            ciphertext,
+            KEY_LENGTH,
+            TIME_BUCKET,
+            tolerance,
+        )
+    }
+
+    #[test]
+    fn encrypted_payload_is_decrypted() {
+        let value = json!({"DB_PASSWORD": "p@ss", "DB_PORT": 5432});
+        let ciphertext = transit_encrypt(APIKEY, SECRET, &value, KEY_LENGTH, TIME_BUCKET).unwrap();
+        assert_eq!(decrypt(SECRET, &ciphertext, 1).unwrap().value, value);
+    }
+
+    #[test]
+    fn adjacent_buckets_are_tolerated() {
+        let value = json!({"key": "value"});
+        let current = current_epoch(TIME_BUCKET).unwrap();
+        for epoch in [current - 1, current + 1] {
+            let ciphertext =
+                transit_encrypt_epoch(APIKEY, SECRET, &value, KEY_LENGTH, epoch).unwrap();
+            let payload = decrypt(SECRET, &ciphertext, 2).unwrap();
+            assert_eq!((payload.value, payload.epoch), (value.clone(), epoch));
+        }
+        for epoch in [current - 2, current + 2] {
+            let ciphertext =
+                transit_encrypt_epoch(APIKEY, SECRET, &value, KEY_LENGTH, epoch).unwrap();
+            assert!(matches!(
+                decrypt(SECRET, &ciphertext, 0),
+                Err(VaultError::Decryption(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn wrong_key_and_tampered_payloads_are_rejected() {
+        let value = json!({"key": "value"});
+        let ciphertext = transit_encrypt(APIKEY, SECRET, &value, KEY_LENGTH, TIME_BUCKET).unwrap();
+        let last = general_purpose::STANDARD.decode(&ciphertext).unwrap().len() - 1;
+        for (secret, ciphertext) in [
+            ("wrong", ciphertext.clone()),
+            (SECRET, tamper(&ciphertext, 0)),
+            (SECRET, tamper(&ciphertext, 12)),
+            (SECRET, tamper(&ciphertext, last)),
+        ] {
+            assert!(matches!(
+                decrypt(secret, &ciphertext, 1),
+                Err(VaultError::Decryption(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn bucket_tolerance_is_capped() {
+        assert_eq!(bucket_offsets(0), vec![0]);
+        assert_eq!(bucket_offsets(2), vec![0, -1, 1, -2, 2]);
+        let offsets = bucket_offsets(u64::MAX);
+        assert_eq!(offsets.len(), 2 * MAX_BUCKET_TOLERANCE as usize + 1);
+        assert_eq!(offsets.last(), Some(&(MAX_BUCKET_TOLERANCE as i64)));
+    }
 }
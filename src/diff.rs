@@ -0,0 +1,186 @@
+use crate::error::VaultError;
+use crate::format;
+use ring::hmac;
+use ring::rand::SystemRandom;
+use serde_json::{json, Map, Value};
+use std::collections::BTreeMap;
+
+/// Placeholder for the masked values, which doesn't reveal the length of the secret.
+const MASK: &str = "********";
+/// Number of hex characters to keep from the HMAC-SHA256 tag of the hashed values.
+const HASH_LENGTH: usize = 12;
+
+/// Enum to represent how the values are displayed in a diff.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Reveal {
+    /// Values are replaced with a fixed placeholder.
+    #[default]
+    Masked,
+    /// Values are displayed as plain text.
+    Show,
+    /// Values are replaced with a truncated HMAC-SHA256 tag, to tell them apart without revealing them.
+    ///
+    /// The HMAC key is random for each diff, so the tags can't be compared across runs or brute-forced offline.
+    Hash,
+}
+
+impl Reveal {
+    /// Generates the key to hash the values with, when they are hashed.
+    ///
+    /// # Returns
+    /// * A `Result` with an `Option` of the random HMAC key.
+    fn hmac_key(&self) -> Result<Option<hmac::Key>, VaultError> {
+        if *self != Reveal::Hash {
+            return Ok(None);
+        }
+        hmac::Key::generate(hmac::HMAC_SHA256, &SystemRandom::new())
+            .map(Some)
+            .map_err(|_| VaultError::Io("Failed to generate a key to hash the values".to_string()))
+    }
+
+    /// Converts a secret's value into the text to display.
+    ///
+    /// # Arguments
+    /// * `value` - Secret value.
+    /// * `key` - HMAC key, which is only set when the values are hashed.
+    ///
+    /// # Returns
+    /// * A `String` with the masked, hashed or plain value.
+    fn display(&self, value: &str, key: Option<&hmac::Key>) -> String {
+        match (self, key) {
+            (Reveal::Show, _) => value.to_string(),
+            (Reveal::Hash, Some(key)) => {
+                let tag = hmac::sign(key, value.as_bytes());
+                let hex: String = tag
+                    .as_ref()
+                    .iter()
+                    .map(|byte| format!("{:02x}", byte))
+                    .collect();
+                format!("hmac:{}", &hex[..HASH_LENGTH])
+            }
+            _ => MASK.to_string(),
+        }
+    }
+}
+
+/// Struct to store the differences between a local secrets file and a table.
+#[derive(Debug, Default)]
+pub struct Diff {
+    /// Keys that are only in the local file.
+    pub added: Vec<String>,
+    /// Keys that are only in the table.
+    pub removed: Vec<String>,
+    /// Keys that are in both, with different values.
+    pub changed: Vec<String>,
+    /// Keys that are in both, with the same value.
+    pub unchanged: Vec<String>,
+}
+
+impl Diff {
+    /// Compares the secrets in a local file against the secrets stored in a table.
+    ///
+    /// # Arguments
+    /// * `local` - Secrets read from the local file.
+    /// * `remote` - Secrets that are currently stored in the table.
+    ///
+    /// # Returns
+    /// * A `Diff` object, with the keys sorted.
+    pub fn new(local: &BTreeMap<String, String>, remote: &Map<String, Value>) -> Diff {
+        let mut diff = Diff::default();
+        for (key, value) in local {
+            match remote.get(key) {
+                None => diff.added.push(key.to_string()),
+                Some(current) if format::plain(current) == *value => {
+                    diff.unchanged.push(key.to_string())
+                }
+                Some(_) => diff.changed.push(key.to_string()),
+            }
+        }
+        diff.removed = remote
+            .keys()
+            .filter(|key| !local.contains_key(key.as_str()))
+            .cloned()
+            .collect();
+        diff.removed.sort();
+        diff
+    }
+
+    /// Checks if the local file has drifted from the table.
+    pub fn has_drift(&self) -> bool {
+        !(self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty())
+    }
+
+    /// Renders the differences as a JSON object.
+    ///
+    /// # Arguments
+    /// * `local` - Secrets read from the local file.
+    /// * `remote` - Secrets that are currently stored in the table.
+    /// * `reveal` - How the values are displayed.
+    ///
+    /// # Returns
+    /// * A `Result` with the added, removed and changed secrets, and the unchanged keys.
+    pub fn to_value(
+        &self,
+        local: &BTreeMap<String, String>,
+        remote: &Map<String, Value>,
+        reveal: Reveal,
+    ) -> Result<Value, VaultError> {
+        let key = reveal.hmac_key()?;
+        let display = |value: &str| reveal.display(value, key.as_ref());
+        let remote_value = |key: &String| display(&format::plain(&remote[key.as_str()]));
+        let added: Map<String, Value> = self
+            .added
+            .iter()
+            .map(|key| (key.to_string(), json!(display(&local[key]))))
+            .collect();
+        let removed: Map<String, Value> = self
+            .removed
+            .iter()
+            .map(|key| (key.to_string(), json!(remote_value(key))))
+            .collect();
+        let changed: Map<String, Value> = self
+            .changed
+            .iter()
+            .map(|key| {
+                let values = json!({ "table": remote_value(key), "file": display(&local[key]) });
+                (key.to_string(), values)
+            })
+            .collect();
+        Ok(json!({
+            "drift": self.has_drift(),
+            "added": added,
+            "removed": removed,
+            "changed": changed,
+            "unchanged": self.unchanged,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hashed_values_use_a_random_key_per_run() {
+        let local = BTreeMap::from([("KEY".to_string(), "password".to_string())]);
+        let remote = Map::from_iter([("KEY".to_string(), json!("changed"))]);
+        let diff = Diff::new(&local, &remote);
+        let first = diff.to_value(&local, &remote, Reveal::Hash).unwrap();
+        let second = diff.to_value(&local, &remote, Reveal::Hash).unwrap();
+        let file = first["changed"]["KEY"]["file"].as_str().unwrap();
+        assert!(file.starts_with("hmac:"));
+        assert_eq!(file.len(), "hmac:".len() + HASH_LENGTH);
+        assert_ne!(file, first["changed"]["KEY"]["table"].as_str().unwrap());
+        // Same value hashes differently in another run, so the hashes can't be looked up
+        assert_ne!(file, second["changed"]["KEY"]["file"].as_str().unwrap());
+    }
+
+    #[test]
+    fn equal_values_hash_the_same_within_a_run() {
+        let key = Reveal::Hash.hmac_key().unwrap();
+        let hash = |value: &str| Reveal::Hash.display(value, key.as_ref());
+        assert_eq!(hash("password"), hash("password"));
+        assert_ne!(hash("password"), hash("Password"));
+        assert_eq!(Reveal::Masked.display("password", None), MASK);
+    }
+}
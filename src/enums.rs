@@ -4,6 +4,7 @@ pub enum EndpointMapping {
     Health,
     GetTable,
     GetSecret,
+    GetSecrets,
     PutSecret,
     ListTables,
     CreateTable,
@@ -18,6 +19,7 @@ impl EndpointMapping {
             EndpointMapping::Health => "/health",
             EndpointMapping::GetTable => "/get-table",
             EndpointMapping::GetSecret => "/get-secret",
+            EndpointMapping::GetSecrets => "/get-secrets",
             EndpointMapping::PutSecret => "/put-secret",
             EndpointMapping::ListTables => "/list-tables",
             EndpointMapping::CreateTable => "/create-table",
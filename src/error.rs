@@ -0,0 +1,128 @@
+use std::fmt;
+
+/// Enum to represent all the errors that can be returned by the client.
+#[derive(Debug)]
+pub enum VaultError {
+    /// Failed to connect to the server, or the connection dropped mid-request.
+    Transport(String),
+    /// Server responded with an unsuccessful status code.
+    Status { code: u16, detail: String },
+    /// Server rejected the credentials.
+    Unauthorized(String),
+    /// Failed to decrypt the transit payload.
+    Decryption(String),
+    /// Failed to encrypt the transit payload.
+    Encryption(String),
+    /// Server response was not in the expected format.
+    MalformedResponse(String),
+    /// Decrypted secrets couldn't be deserialized into the requested type.
+    Deserialize { key: String, message: String },
+    /// Required configuration is either missing or invalid.
+    MissingConfig(String),
+    /// Commandline arguments are either missing or invalid.
+    InvalidArgument(String),
+    /// Failed to read/write a file, or to run a process.
+    Io(String),
+    /// Request failed after some of the changes were applied, such as an import that uploads the secrets in chunks.
+    Partial {
+        /// Changes that were applied before the failure.
+        applied: Vec<String>,
+        /// Changes that were not applied.
+        remaining: Vec<String>,
+        /// Error that stopped the remaining changes.
+        error: Box<VaultError>,
+    },
+}
+
+/// Implements the display trait to print human-readable error messages.
+impl fmt::Display for VaultError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VaultError::Transport(err) => write!(f, "Transport error: {}", err),
+            VaultError::Status { code, detail } => {
+                write!(f, "Server responded with {}: {}", code, detail)
+            }
+            VaultError::Unauthorized(detail) => write!(f, "Unauthorized: {}", detail),
+            VaultError::Decryption(err) => write!(f, "Decryption error: {}", err),
+            VaultError::Encryption(err) => write!(f, "Encryption error: {}", err),
+            VaultError::MalformedResponse(err) => write!(f, "Malformed response: {}", err),
+            VaultError::Deserialize { key, message } => {
+                write!(f, "Failed to deserialize '{}': {}", key, message)
+            }
+            VaultError::MissingConfig(err) => write!(f, "Configuration error: {}", err),
+            VaultError::InvalidArgument(err) => write!(f, "{}", err),
+            VaultError::Io(err) => write!(f, "IO error: {}", err),
+            VaultError::Partial {
+                applied,
+                remaining,
+                error,
+            } => write!(
+                f,
+                "{}\nApplied before the failure: [{}]\nNot applied: [{}]",
+                error,
+                applied.join(", "),
+                remaining.join(", ")
+            ),
+        }
+    }
+}
+
+impl std::error::Error for VaultError {}
+
+/// Converts the errors raised by `reqwest` into transport errors.
+impl From<reqwest::Error> for VaultError {
+    fn from(err: reqwest::Error) -> Self {
+        VaultError::Transport(err.to_string())
+    }
+}
+
+/// Converts the IO errors into `VaultError`.
+impl From<std::io::Error> for VaultError {
+    fn from(err: std::io::Error) -> Self {
+        VaultError::Io(err.to_string())
+    }
+}
+
+/// Enum to represent the warnings about a degraded client, that are reported to the caller instead of failing the request.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Warning {
+    /// TLS certificate validation is disabled.
+    InsecureTls,
+    /// Server is unreachable, and the client continues with the offline cache.
+    Unreachable(String),
+    /// Secrets of a table were served from the offline cache, saved `age` seconds ago.
+    Stale {
+        table_name: String,
+        age: u64,
+        error: String,
+    },
+    /// Offline cache couldn't be updated with the secrets retrieved from the server.
+    CacheUpdate(String),
+    /// OS keyring is unavailable, and the credentials are stored in the encrypted file.
+    KeyringUnavailable(String),
+}
+
+/// Implements the display trait to print human-readable warnings.
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Warning::InsecureTls => write!(f, "TLS certificate validation is disabled"),
+            Warning::Unreachable(err) => write!(f, "{}, continuing with the offline cache", err),
+            Warning::Stale {
+                table_name,
+                age,
+                error,
+            } => write!(
+                f,
+                "{}, serving '{}' from the offline cache saved {} seconds ago",
+                error, table_name, age
+            ),
+            Warning::CacheUpdate(err) => write!(f, "failed to update the offline cache: {}", err),
+            Warning::KeyringUnavailable(err) => write!(
+                f,
+                "OS keyring is unavailable ({}), using the credentials file",
+                err
+            ),
+        }
+    }
+}
@@ -0,0 +1,265 @@
+use crate::error::VaultError;
+use crate::format::{self, Format};
+use base64::{engine::general_purpose, Engine as _};
+use serde_json::{json, Map, Value};
+use std::str::FromStr;
+
+/// Enum to represent the file formats that a table can be exported to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExportFormat {
+    /// `KEY=value` lines for dotenv files.
+    Dotenv,
+    /// Pretty printed JSON object.
+    Json,
+    /// YAML mapping.
+    Yaml,
+    /// Kubernetes `Secret` manifest, with the values base64 encoded in `data`.
+    K8sSecret,
+    /// `KEY=value` lines for `docker run --env-file`, where the values are read literally.
+    DockerEnv,
+}
+
+/// Parses the export format from the commandline argument.
+impl FromStr for ExportFormat {
+    type Err = VaultError;
+
+    fn from_str(format: &str) -> Result<Self, Self::Err> {
+        match format.to_lowercase().as_str() {
+            "dotenv" | "env" => Ok(ExportFormat::Dotenv),
+            "json" => Ok(ExportFormat::Json),
+            "yaml" | "yml" => Ok(ExportFormat::Yaml),
+            "k8s-secret" | "k8s" => Ok(ExportFormat::K8sSecret),
+            "docker-env" | "docker" => Ok(ExportFormat::DockerEnv),
+            _ => Err(VaultError::InvalidArgument(format!(
+                "Unknown export format '{}', expected one of: dotenv, json, yaml, k8s-secret, docker-env",
+                format
+            ))),
+        }
+    }
+}
+
+/// Converts a table name into a valid Kubernetes resource name.
+fn resource_name(table_name: &str) -> String {
+    let name: String = table_name
+        .to_lowercase()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .collect();
+    name.trim_matches('-').to_string()
+}
+
+/// Checks if a name is a valid Kubernetes resource name (RFC 1123 subdomain).
+fn is_resource_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 253
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '.')
+        && name.starts_with(|c: char| c.is_ascii_alphanumeric())
+        && name.ends_with(|c: char| c.is_ascii_alphanumeric())
+}
+
+/// Checks if a name is a valid Kubernetes namespace (RFC 1123 label), which can't contain dots.
+fn is_namespace(name: &str) -> bool {
+    name.len() <= 63 && !name.contains('.') && is_resource_name(name)
+}
+
+/// Renders a Kubernetes `Secret` manifest.
+///
+/// # Arguments
+/// * `secrets` - Decrypted secrets as key-value pairs.
+/// * `name` - Name of the `Secret` resource.
+/// * `namespace` - Namespace of the `Secret` resource, if any.
+///
+/// # Returns
+/// * A `Result` with the YAML manifest.
+fn k8s_secret(
+    secrets: &Map<String, Value>,
+    name: &str,
+    namespace: Option<&str>,
+) -> Result<String, VaultError> {
+    if !is_resource_name(name) {
+        return Err(VaultError::InvalidArgument(format!(
+            "'{}' is not a valid Kubernetes name, expected lowercase letters, digits, '-' and '.'",
+            name
+        )));
+    }
+    if let Some(namespace) = namespace.filter(|namespace| !is_namespace(namespace)) {
+        return Err(VaultError::InvalidArgument(format!(
+            "'{}' is not a valid Kubernetes namespace, expected up to 63 lowercase letters, digits and '-'",
+            namespace
+        )));
+    }
+    let mut metadata = json!({ "name": name });
+    if let Some(namespace) = namespace {
+        metadata["namespace"] = json!(namespace);
+    }
+    let data: Map<String, Value> = secrets
+        .iter()
+        .map(|(key, value)| {
+            let encoded = general_purpose::STANDARD.encode(format::plain(value));
+            (key.to_string(), Value::String(encoded))
+        })
+        .collect();
+    let manifest = json!({
+        "apiVersion": "v1",
+        "kind": "Secret",
+        "metadata": metadata,
+        "type": "Opaque",
+        "data": data,
+    });
+    format::render(&manifest, Format::Yaml)
+}
+
+/// Renders the `KEY=value` lines for `docker run --env-file`.
+///
+/// Docker reads the values literally until the end of the line, so they can't be quoted or span multiple lines.
+fn docker_env(secrets: &Map<String, Value>) -> Result<String, VaultError> {
+    let mut lines = Vec::new();
+    for (key, value) in secrets {
+        let value = format::plain(value);
+        if value.contains('\n') || value.contains('\r') {
+            return Err(VaultError::InvalidArgument(format!(
+                "'{}' spans multiple lines, which is not supported by docker env files",
+                key
+            )));
+        }
+        lines.push(format!("{}={}", key, value));
+    }
+    Ok(lines.join("\n"))
+}
+
+/// Renders the secrets of a table in the requested export format.
+///
+/// # Arguments
+/// * `table_name` - Table name, used as the default name of the Kubernetes `Secret`.
+/// * `secrets` - Decrypted secrets as key-value pairs.
+/// * `export_format` - Export format.
+/// * `name` - Name of the Kubernetes `Secret`, that overrides the default.
+/// * `namespace` - Namespace of the Kubernetes `Secret`.
+///
+/// # Returns
+/// * A `Result` with the rendered text, ending with a newline.
+pub fn render(
+    table_name: &str,
+    secrets: &Map<String, Value>,
+    export_format: ExportFormat,
+    name: Option<&str>,
+    namespace: Option<&str>,
+) -> Result<String, VaultError> {
+    let value = Value::Object(secrets.clone());
+    let rendered = match export_format {
+        ExportFormat::Dotenv => format::render(&value, Format::Dotenv)?,
+        ExportFormat::Json => format::render(&value, Format::Json)?,
+        ExportFormat::Yaml => format::render(&value, Format::Yaml)?,
+        ExportFormat::DockerEnv => docker_env(secrets)?,
+        ExportFormat::K8sSecret => {
+            let default_name = resource_name(table_name);
+            k8s_secret(secrets, name.unwrap_or(&default_name), namespace)?
+        }
+    };
+    Ok(format!("{}\n", rendered))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Secrets of a table, as returned by the server.
+    fn secrets() -> Map<String, Value> {
+        json!({"DB_PASSWORD": "p@ss word", "DB_PORT": 5432})
+            .as_object()
+            .cloned()
+            .unwrap()
+    }
+
+    #[test]
+    fn k8s_secrets_are_base64_encoded() {
+        let rendered = render(
+            "Billing_Service",
+            &secrets(),
+            ExportFormat::K8sSecret,
+            None,
+            Some("payments"),
+        )
+        .unwrap();
+        let manifest: Value = serde_yaml::from_str(&rendered).unwrap();
+        assert_eq!(manifest["kind"], "Secret");
+        assert_eq!(manifest["metadata"]["name"], "billing-service");
+        assert_eq!(manifest["metadata"]["namespace"], "payments");
+        assert_eq!(manifest["data"]["DB_PASSWORD"], "cEBzcyB3b3Jk");
+        assert_eq!(manifest["data"]["DB_PORT"], "NTQzMg==");
+
+        let rendered = render(
+            "production",
+            &secrets(),
+            ExportFormat::K8sSecret,
+            Some("db.credentials"),
+            None,
+        )
+        .unwrap();
+        let manifest: Value = serde_yaml::from_str(&rendered).unwrap();
+        assert_eq!(manifest["metadata"]["name"], "db.credentials");
+        assert!(manifest["metadata"].get("namespace").is_none());
+    }
+
+    #[test]
+    fn k8s_names_are_validated() {
+        let render = |name: Option<&str>, namespace: Option<&str>| {
+            render(
+                "production",
+                &secrets(),
+                ExportFormat::K8sSecret,
+                name,
+                namespace,
+            )
+        };
+        assert!(render(Some("Invalid_Name"), None).is_err());
+        assert!(render(Some("-leading"), None).is_err());
+        // Namespaces are labels, so they can't contain dots like the resource names
+        assert!(render(None, Some("team.payments")).is_err());
+        assert!(render(None, Some(&"a".repeat(64))).is_err());
+        assert!(render(None, Some(&"a".repeat(63))).is_ok());
+    }
+
+    #[test]
+    fn docker_env_rejects_multiline_values() {
+        let rendered = render(
+            "production",
+            &secrets(),
+            ExportFormat::DockerEnv,
+            None,
+            None,
+        )
+        .unwrap();
+        assert_eq!(rendered, "DB_PASSWORD=p@ss word\nDB_PORT=5432\n");
+        for value in ["first\nsecond", "first\r\nsecond"] {
+            let secrets = json!({ "CERT": value }).as_object().cloned().unwrap();
+            match render("production", &secrets, ExportFormat::DockerEnv, None, None) {
+                Err(VaultError::InvalidArgument(message)) => assert!(message.contains("'CERT'")),
+                other => panic!(
+                    "expected the multiline value to be rejected, got {:?}",
+                    other
+                ),
+            }
+        }
+    }
+
+    #[test]
+    fn tables_are_exported_as_json_yaml_and_dotenv() {
+        let secrets = secrets();
+        let rendered = render("production", &secrets, ExportFormat::Json, None, None).unwrap();
+        assert_eq!(
+            serde_json::from_str::<Value>(&rendered).unwrap(),
+            Value::Object(secrets.clone())
+        );
+        let rendered = render("production", &secrets, ExportFormat::Yaml, None, None).unwrap();
+        assert_eq!(
+            serde_yaml::from_str::<Value>(&rendered).unwrap(),
+            Value::Object(secrets.clone())
+        );
+        let rendered = render("production", &secrets, ExportFormat::Dotenv, None, None).unwrap();
+        assert_eq!(rendered, "DB_PASSWORD='p@ss word'\nDB_PORT=5432\n");
+        assert!(rendered.ends_with('\n'));
+    }
+}
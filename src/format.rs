@@ -0,0 +1,279 @@
+use crate::error::VaultError;
+use serde_json::{Map, Value};
+use std::str::FromStr;
+
+/// Enum to represent the output formats supported by the CLI.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Format {
+    /// Compact JSON, with plain text for messages.
+    #[default]
+    Compact,
+    /// Pretty printed JSON.
+    Json,
+    /// `KEY=value` lines for dotenv files.
+    Dotenv,
+    /// `export KEY='value'` lines that can be evaluated by a shell.
+    Shell,
+    /// YAML document.
+    Yaml,
+    /// Bare value of a single secret.
+    Raw,
+}
+
+/// Parses the output format from the commandline argument.
+impl FromStr for Format {
+    type Err = VaultError;
+
+    fn from_str(format: &str) -> Result<Self, Self::Err> {
+        match format.to_lowercase().as_str() {
+            "compact" => Ok(Format::Compact),
+            "json" => Ok(Format::Json),
+            "dotenv" | "env" => Ok(Format::Dotenv),
+            "shell" | "export" => Ok(Format::Shell),
+            "yaml" | "yml" => Ok(Format::Yaml),
+            "raw" => Ok(Format::Raw),
+            _ => Err(VaultError::InvalidArgument(format!(
+                "Unknown format '{}', expected one of: json, dotenv, shell, yaml, raw",
+                format
+            ))),
+        }
+    }
+}
+
+/// Renders a value in the requested output format.
+///
+/// # Arguments
+/// * `value` - Value to render.
+/// * `format` - Output format.
+///
+/// # Returns
+/// * A `Result` with the rendered text.
+pub fn render(value: &Value, format: Format) -> Result<String, VaultError> {
+    match (format, value) {
+        // Messages from the server are printed as-is, unless a structured format is requested
+        (Format::Compact | Format::Dotenv | Format::Shell | Format::Raw, Value::String(text)) => {
+            Ok(text.to_string())
+        }
+        (Format::Compact, _) => Ok(value.to_string()),
+        (Format::Json, _) => serde_json::to_string_pretty(value)
+            .map_err(|err| VaultError::MalformedResponse(err.to_string())),
+        (Format::Yaml, _) => serde_yaml::to_string(value)
+            .map(|yaml| yaml.trim_end().to_string())
+            .map_err(|err| VaultError::MalformedResponse(err.to_string())),
+        (Format::Dotenv, Value::Object(secrets)) => Ok(lines(secrets, dotenv_line)),
+        (Format::Shell, Value::Object(secrets)) => {
+            for key in secrets.keys() {
+                if !is_identifier(key) {
+                    return Err(VaultError::InvalidArgument(format!(
+                        "'{}' is not a valid shell variable name",
+                        key
+                    )));
+                }
+            }
+            Ok(lines(secrets, shell_line))
+        }
+        (Format::Raw, Value::Object(secrets)) if secrets.len() == 1 => {
+            Ok(plain(secrets.values().next().unwrap_or(&Value::Null)))
+        }
+        (Format::Raw, Value::Array(values)) => {
+            Ok(values.iter().map(plain).collect::<Vec<String>>().join("\n"))
+        }
+        (Format::Raw, _) => Err(VaultError::InvalidArgument(
+            "raw format requires a single secret".to_string(),
+        )),
+        (Format::Dotenv | Format::Shell, _) => Err(VaultError::InvalidArgument(format!(
+            "{:?} format requires secrets as key-value pairs",
+            format
+        ))),
+    }
+}
+
+/// Converts a JSON value into plain text, without quoting the strings.
+///
+/// # Arguments
+/// * `value` - Value to convert.
+///
+/// # Returns
+/// * A `String` with the plain text.
+pub fn plain(value: &Value) -> String {
+    match value {
+        Value::String(text) => text.to_string(),
+        _ => value.to_string(),
+    }
+}
+
+/// Renders each secret as a line, sorted by the key.
+fn lines(secrets: &Map<String, Value>, line: fn(&str, &str) -> String) -> String {
+    let mut keys: Vec<&String> = secrets.keys().collect();
+    keys.sort();
+    keys.iter()
+        .map(|key| line(key, &plain(&secrets[key.as_str()])))
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+/// Checks if the key is a valid shell variable name.
+///
+/// # Arguments
+/// * `key` - Secret key.
+///
+/// # Returns
+/// * A boolean flag indicating whether the key is a valid identifier.
+pub fn is_identifier(key: &str) -> bool {
+    let mut chars = key.chars();
+    match chars.next() {
+        Some(first) if first.is_ascii_alphabetic() || first == '_' => {
+            chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        }
+        _ => false,
+    }
+}
+
+/// Renders a `KEY=value` line for dotenv files.
+///
+/// Values with special characters are single quoted, so that they are read literally,
+/// and fall back to double quotes with escape sequences when single quotes can't be used.
+///
+/// # Arguments
+/// * `key` - Secret key.
+/// * `value` - Secret value.
+///
+/// # Returns
+/// * A `String` with the dotenv line.
+pub fn dotenv_line(key: &str, value: &str) -> String {
+    let safe = value
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || "_-./:@,+%".contains(c));
+    if safe {
+        return format!("{}={}", key, value);
+    }
+    if !value.contains('\'') && !value.contains('\n') {
+        return format!("{}='{}'", key, value);
+    }
+    let escaped = value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('$', "\\$")
+        .replace('\n', "\\n");
+    format!("{}=\"{}\"", key, escaped)
+}
+
+/// Renders an `export KEY='value'` line that can be evaluated by a shell.
+///
+/// # Arguments
+/// * `key` - Secret key.
+/// * `value` - Secret value.
+///
+/// # Returns
+/// * A `String` with the shell line.
+pub fn shell_line(key: &str, value: &str) -> String {
+    format!("export {}='{}'", key, value.replace('\'', "'\\''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn shell_lines_are_single_quoted() {
+        assert_eq!(shell_line("KEY", "value"), "export KEY='value'");
+        assert_eq!(shell_line("KEY", "$HOME `id`"), "export KEY='$HOME `id`'");
+        assert_eq!(shell_line("KEY", "it's"), "export KEY='it'\\''s'");
+        assert_eq!(
+            shell_line("KEY", "first\nsecond"),
+            "export KEY='first\nsecond'"
+        );
+    }
+
+    #[test]
+    fn dotenv_lines_are_quoted_when_needed() {
+        assert_eq!(
+            dotenv_line("KEY", "postgres://db:5432"),
+            "KEY=postgres://db:5432"
+        );
+        assert_eq!(dotenv_line("KEY", "p@ss word"), "KEY='p@ss word'");
+        assert_eq!(dotenv_line("KEY", "$HOME"), "KEY='$HOME'");
+        // Single quotes can't hold a quote or a newline, so double quotes are used with escapes
+        assert_eq!(dotenv_line("KEY", "it's"), "KEY=\"it's\"");
+        assert_eq!(
+            dotenv_line("KEY", "first\nsecond"),
+            "KEY=\"first\\nsecond\""
+        );
+        assert_eq!(
+            dotenv_line("KEY", "it's \"$5\" \\ more"),
+            "KEY=\"it's \\\"\\$5\\\" \\\\ more\""
+        );
+    }
+
+    #[test]
+    fn shell_format_rejects_invalid_identifiers() {
+        assert!(is_identifier("DB_PASSWORD"));
+        assert!(is_identifier("_private"));
+        for key in [
+            "",
+            "1PASSWORD",
+            "DB-PASSWORD",
+            "DB PASSWORD",
+            "DB.PASSWORD",
+            "ÜBER",
+        ] {
+            assert!(!is_identifier(key), "{}", key);
+        }
+        match render(&json!({"DB-PASSWORD": "value"}), Format::Shell) {
+            Err(VaultError::InvalidArgument(message)) => assert!(message.contains("DB-PASSWORD")),
+            other => panic!("expected an invalid identifier error, got {:?}", other),
+        }
+        assert_eq!(
+            render(&json!({"B": "2", "A": "it's"}), Format::Shell).unwrap(),
+            "export A='it'\\''s'\nexport B='2'"
+        );
+    }
+
+    #[test]
+    fn raw_format_requires_a_single_secret() {
+        assert_eq!(
+            render(&json!({"KEY": "value"}), Format::Raw).unwrap(),
+            "value"
+        );
+        assert_eq!(render(&json!({"PORT": 5432}), Format::Raw).unwrap(), "5432");
+        assert_eq!(
+            render(&json!(["first", 2]), Format::Raw).unwrap(),
+            "first\n2"
+        );
+        assert_eq!(render(&json!("message"), Format::Raw).unwrap(), "message");
+        for value in [
+            json!({"A": "1", "B": "2"}),
+            json!({}),
+            json!(5432),
+            Value::Null,
+        ] {
+            assert!(matches!(
+                render(&value, Format::Raw),
+                Err(VaultError::InvalidArgument(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn structured_formats_are_rendered() {
+        let secrets = json!({"DB_PASSWORD": "p@ss", "DB_PORT": 5432});
+        assert_eq!(
+            render(&secrets, Format::Yaml).unwrap(),
+            "DB_PASSWORD: p@ss\nDB_PORT: 5432"
+        );
+        assert_eq!(
+            render(&secrets, Format::Compact).unwrap(),
+            r#"{"DB_PASSWORD":"p@ss","DB_PORT":5432}"#
+        );
+        assert_eq!(
+            serde_json::from_str::<Value>(&render(&secrets, Format::Json).unwrap()).unwrap(),
+            secrets
+        );
+        assert_eq!(
+            render(&secrets, Format::Dotenv).unwrap(),
+            "DB_PASSWORD=p@ss\nDB_PORT=5432"
+        );
+        assert!(render(&json!(["value"]), Format::Dotenv).is_err());
+    }
+}
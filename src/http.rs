@@ -0,0 +1,91 @@
+use crate::error::VaultError;
+use crate::parser::EnvConfig;
+use crate::tls;
+use reqwest::{NoProxy, Proxy};
+
+/// Constructs the proxy configuration.
+///
+/// # Arguments
+/// * `env_config` - Environment variables' configuration.
+///
+/// # Returns
+/// * A `Result` with an `Option` of the proxy, if one is configured.
+fn proxy(env_config: &EnvConfig) -> Result<Option<Proxy>, VaultError> {
+    let proxy_url = match &env_config.proxy {
+        Some(proxy_url) => proxy_url,
+        None => return Ok(None),
+    };
+    let proxy = Proxy::all(proxy_url.as_str()).map_err(|err| {
+        VaultError::MissingConfig(format!("Invalid proxy '{}': {}", proxy_url, err))
+    })?;
+    let no_proxy = match &env_config.no_proxy {
+        Some(no_proxy) => NoProxy::from_string(no_proxy),
+        None => NoProxy::from_env(),
+    };
+    Ok(Some(proxy.no_proxy(no_proxy)))
+}
+
+/// Applies the timeouts, proxy and TLS settings to a client builder.
+///
+/// Blocking and async builders have different types with the same methods, so this is a macro.
+macro_rules! configure {
+    ($builder:expr, $env_config:expr) => {{
+        let env_config: &EnvConfig = $env_config;
+        let mut builder = $builder;
+        if let Some(timeout) = env_config.timeout {
+            builder = builder.timeout(timeout);
+        }
+        if let Some(connect_timeout) = env_config.connect_timeout {
+            builder = builder.connect_timeout(connect_timeout);
+        }
+        // "*" bypasses all the proxies, including the ones set in the environment
+        if env_config.no_proxy.as_deref() == Some("*") {
+            builder = builder.no_proxy();
+        } else if let Some(proxy) = proxy(env_config)? {
+            builder = builder.proxy(proxy);
+        }
+        if let Some(tls_config) = tls::pinned_config(&env_config.tls)? {
+            builder = builder.use_preconfigured_tls(tls_config);
+        } else {
+            for certificate in tls::root_certificates(&env_config.tls)? {
+                builder = builder.add_root_certificate(certificate);
+            }
+            if let Some(identity) = tls::identity(&env_config.tls)? {
+                builder = builder.identity(identity);
+            }
+            if env_config.tls.insecure {
+                builder = builder
+                    .danger_accept_invalid_certs(true)
+                    .danger_accept_invalid_hostnames(true);
+            }
+        }
+        builder.build().map_err(|err| {
+            VaultError::MissingConfig(format!("Failed to build the HTTP client: {}", err))
+        })
+    }};
+}
+
+/// Creates the HTTP client shared by all the requests of a `VaultClient`.
+///
+/// # Arguments
+/// * `env_config` - Environment variables' configuration.
+///
+/// # Returns
+/// * A `Result` with the blocking HTTP client.
+pub(crate) fn blocking_client(
+    env_config: &EnvConfig,
+) -> Result<reqwest::blocking::Client, VaultError> {
+    configure!(reqwest::blocking::Client::builder(), env_config)
+}
+
+/// Creates the HTTP client shared by all the requests of an `AsyncVaultClient`.
+///
+/// # Arguments
+/// * `env_config` - Environment variables' configuration.
+///
+/// # Returns
+/// * A `Result` with the async HTTP client.
+#[cfg(feature = "async")]
+pub(crate) fn async_client(env_config: &EnvConfig) -> Result<reqwest::Client, VaultError> {
+    configure!(reqwest::Client::builder(), env_config)
+}
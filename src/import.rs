@@ -0,0 +1,392 @@
+use crate::error::VaultError;
+use crate::format;
+use crate::secret::Secret;
+use serde_json::{Map, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+/// Enum to represent the file formats that can be imported.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FileFormat {
+    /// `KEY=value` lines, with the same quoting rules as the env files.
+    Dotenv,
+    /// JSON object.
+    Json,
+    /// YAML mapping.
+    Yaml,
+    /// TOML table.
+    Toml,
+}
+
+/// Parses the file format from the commandline argument.
+impl FromStr for FileFormat {
+    type Err = VaultError;
+
+    fn from_str(format: &str) -> Result<Self, Self::Err> {
+        match format.to_lowercase().as_str() {
+            "dotenv" | "env" => Ok(FileFormat::Dotenv),
+            "json" => Ok(FileFormat::Json),
+            "yaml" | "yml" => Ok(FileFormat::Yaml),
+            "toml" => Ok(FileFormat::Toml),
+            _ => Err(VaultError::InvalidArgument(format!(
+                "Unknown file format '{}', expected one of: dotenv, json, yaml, toml",
+                format
+            ))),
+        }
+    }
+}
+
+impl FileFormat {
+    /// Detects the file format from the file's extension.
+    ///
+    /// # Arguments
+    /// * `path` - Path to the file.
+    ///
+    /// # Returns
+    /// * The detected `FileFormat`, which defaults to dotenv for unknown extensions.
+    pub fn from_path(path: &Path) -> FileFormat {
+        path.extension()
+            .and_then(|extension| extension.to_str())
+            .and_then(|extension| extension.parse::<FileFormat>().ok())
+            .unwrap_or(FileFormat::Dotenv)
+    }
+}
+
+/// Converts a value from a structured file into a secret's value.
+fn scalar(key: &str, value: Value) -> Result<String, VaultError> {
+    match value {
+        Value::String(text) => Ok(text),
+        Value::Number(_) | Value::Bool(_) => Ok(value.to_string()),
+        _ => Err(VaultError::InvalidArgument(format!(
+            "Value of '{}' must be a string, number or boolean",
+            key
+        ))),
+    }
+}
+
+/// Builds the error for a file that couldn't be parsed.
+fn invalid(path: &Path, err: impl std::fmt::Display) -> VaultError {
+    VaultError::InvalidArgument(format!("Failed to parse '{}': {}", path.display(), err))
+}
+
+/// Parses a JSON, YAML or TOML file.
+///
+/// # Arguments
+/// * `path` - Path to the file.
+/// * `file_format` - Format of the file, which can't be dotenv.
+///
+/// # Returns
+/// * A `Result` with the parsed value.
+pub(crate) fn parse_file(path: &Path, file_format: FileFormat) -> Result<Value, VaultError> {
+    let contents = Secret::new(fs::read_to_string(path)?);
+    match file_format {
+        FileFormat::Json => {
+            serde_json::from_str(contents.expose()).map_err(|err| invalid(path, err))
+        }
+        FileFormat::Yaml => {
+            serde_yaml::from_str(contents.expose()).map_err(|err| invalid(path, err))
+        }
+        FileFormat::Toml => toml::from_str(contents.expose()).map_err(|err| invalid(path, err)),
+        FileFormat::Dotenv => Err(invalid(path, "dotenv files can't be nested")),
+    }
+}
+
+/// Converts the key-value pairs from a structured file into secrets.
+///
+/// # Arguments
+/// * `path` - Path to the file, for the error messages.
+/// * `value` - Parsed key-value pairs.
+///
+/// # Returns
+/// * A `Result` with the secrets, sorted by their keys.
+pub(crate) fn secrets_from(
+    path: &Path,
+    value: Value,
+) -> Result<BTreeMap<String, String>, VaultError> {
+    let object = match value {
+        Value::Object(object) => object,
+        _ => return Err(invalid(path, "expected key-value pairs")),
+    };
+    let mut secrets = BTreeMap::new();
+    for (key, value) in object {
+        let value = scalar(&key, value)?;
+        secrets.insert(key, value);
+    }
+    check_keys(&secrets)?;
+    Ok(secrets)
+}
+
+/// Checks that the keys can be used as environment variable names.
+fn check_keys(secrets: &BTreeMap<String, String>) -> Result<(), VaultError> {
+    for key in secrets.keys() {
+        if !format::is_identifier(key) {
+            return Err(VaultError::InvalidArgument(format!(
+                "'{}' is not a valid key, expected letters, digits and underscores",
+                key
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Parses the `KEY=value` lines of a dotenv file literally, without expanding the `$VAR` references.
+///
+/// Lines can start with `export`, and `#` starts a comment at the start of a line or after a whitespace.
+/// Single quoted values are read as-is, double quoted values support the `\n`, `\r`, `\t`, `\\`, `\"` and `\$`
+/// escape sequences, and quoted values can span multiple lines.
+///
+/// # Arguments
+/// * `contents` - Contents of the dotenv file.
+///
+/// # Returns
+/// * A `Result` with the key-value pairs in the order of the file, or a message about the invalid line.
+fn parse_dotenv(contents: &str) -> Result<Vec<(String, String)>, String> {
+    let mut pairs = Vec::new();
+    let mut chars = contents.chars().peekable();
+    let mut line = 1;
+    // Consumes the rest of the current line, without the line break
+    let rest_of_line = |chars: &mut std::iter::Peekable<std::str::Chars>| {
+        let mut rest = String::new();
+        while let Some(c) = chars.next_if(|c| *c != '\n') {
+            rest.push(c);
+        }
+        rest
+    };
+    loop {
+        while let Some(c) = chars.next_if(|c| c.is_whitespace()) {
+            if c == '\n' {
+                line += 1;
+            }
+        }
+        match chars.peek() {
+            None => break,
+            Some('#') => {
+                rest_of_line(&mut chars);
+                continue;
+            }
+            Some(_) => {}
+        }
+        let mut key = String::new();
+        while let Some(c) = chars.next_if(|c| *c != '=' && *c != '\n') {
+            key.push(c);
+        }
+        if chars.next() != Some('=') {
+            return Err(format!("line {}: expected KEY=value", line));
+        }
+        let key = match key.trim().strip_prefix("export") {
+            Some(exported) if exported.starts_with(char::is_whitespace) => exported.trim_start(),
+            _ => key.trim(),
+        };
+        if key.is_empty() {
+            return Err(format!("line {}: missing key", line));
+        }
+        while chars.next_if(|c| *c == ' ' || *c == '\t').is_some() {}
+        let start = line;
+        let value = match chars.peek().copied() {
+            Some(quote @ ('\'' | '"')) => {
+                chars.next();
+                let mut value = String::new();
+                loop {
+                    let c = match chars.next() {
+                        Some(c) => c,
+                        None => return Err(format!("line {}: unterminated quoted value", start)),
+                    };
+                    if c == quote {
+                        break;
+                    }
+                    if c == '\\' && quote == '"' {
+                        match chars.next() {
+                            Some('n') => value.push('\n'),
+                            Some('r') => value.push('\r'),
+                            Some('t') => value.push('\t'),
+                            Some(escaped @ ('\\' | '"' | '$')) => value.push(escaped),
+                            Some(other) => {
+                                line += usize::from(other == '\n');
+                                value.push('\\');
+                                value.push(other);
+                            }
+                            None => {
+                                return Err(format!("line {}: unterminated quoted value", start))
+                            }
+                        }
+                        continue;
+                    }
+                    line += usize::from(c == '\n');
+                    value.push(c);
+                }
+                let rest = rest_of_line(&mut chars);
+                let rest = rest.trim();
+                if !rest.is_empty() && !rest.starts_with('#') {
+                    return Err(format!(
+                        "line {}: unexpected characters after the quoted value",
+                        line
+                    ));
+                }
+                value
+            }
+            _ => {
+                let value = rest_of_line(&mut chars);
+                let end = value
+                    .char_indices()
+                    .find(|(index, c)| {
+                        c.is_whitespace() && value[*index..].trim_start().starts_with('#')
+                    })
+                    .map(|(index, _)| index)
+                    .unwrap_or(value.len());
+                value[..end].trim_end().to_string()
+            }
+        };
+        pairs.push((key.to_string(), value));
+    }
+    Ok(pairs)
+}
+
+/// Reads the secrets from a file, as key-value pairs.
+///
+/// # Arguments
+/// * `path` - Path to the file.
+/// * `file_format` - Format of the file.
+///
+/// # Returns
+/// * A `Result` with the secrets, sorted by their keys.
+pub fn read_file(
+    path: &Path,
+    file_format: FileFormat,
+) -> Result<BTreeMap<String, String>, VaultError> {
+    if file_format != FileFormat::Dotenv {
+        return secrets_from(path, parse_file(path, file_format)?);
+    }
+    let contents = Secret::new(fs::read_to_string(path)?);
+    let mut secrets = BTreeMap::new();
+    for (key, value) in parse_dotenv(contents.expose()).map_err(|err| invalid(path, err))? {
+        if secrets.insert(key.to_string(), value).is_some() {
+            return Err(invalid(path, format!("'{}' is set more than once", key)));
+        }
+    }
+    check_keys(&secrets)?;
+    Ok(secrets)
+}
+
+/// Struct to store the changes that an import makes to a table.
+#[derive(Debug, Default)]
+pub struct ImportPlan {
+    /// Keys that don't exist in the table.
+    pub added: Vec<String>,
+    /// Keys that exist in the table with a different value.
+    pub overwritten: Vec<String>,
+    /// Keys that exist in the table with the same value, which are not uploaded.
+    pub unchanged: Vec<String>,
+}
+
+impl ImportPlan {
+    /// Compares the secrets to import against the existing secrets in the table.
+    ///
+    /// # Arguments
+    /// * `secrets` - Secrets to import.
+    /// * `existing` - Secrets that are currently stored in the table.
+    ///
+    /// # Returns
+    /// * An `ImportPlan` object.
+    pub fn new(secrets: &BTreeMap<String, String>, existing: &Map<String, Value>) -> ImportPlan {
+        let mut plan = ImportPlan::default();
+        for (key, value) in secrets {
+            match existing.get(key) {
+                None => plan.added.push(key.to_string()),
+                Some(current) if format::plain(current) == *value => {
+                    plan.unchanged.push(key.to_string())
+                }
+                Some(_) => plan.overwritten.push(key.to_string()),
+            }
+        }
+        plan
+    }
+
+    /// Returns the secrets that have to be uploaded, in chunks.
+    ///
+    /// # Arguments
+    /// * `secrets` - Secrets to import.
+    /// * `chunk_size` - Maximum number of secrets in each chunk.
+    ///
+    /// # Returns
+    /// * A `Vec` of chunks, that can be passed to `put_secret`.
+    pub fn chunks(
+        &self,
+        secrets: &BTreeMap<String, String>,
+        chunk_size: usize,
+    ) -> Vec<HashMap<String, String>> {
+        let keys: Vec<&String> = self.added.iter().chain(&self.overwritten).collect();
+        keys.chunks(chunk_size.max(1))
+            .map(|chunk| {
+                chunk
+                    .iter()
+                    .map(|key| (key.to_string(), secrets[*key].to_string()))
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Parses the contents of a dotenv file into a map.
+    fn parse(contents: &str) -> BTreeMap<String, String> {
+        parse_dotenv(contents).unwrap().into_iter().collect()
+    }
+
+    #[test]
+    fn dollar_signs_are_read_literally() {
+        let secrets =
+            parse("PASSWORD=pa$$word\nHOME_DIR=abc$HOME\nBRACED=${USER}x\nQUOTED=\"$PATH\"\n");
+        assert_eq!(secrets["PASSWORD"], "pa$$word");
+        assert_eq!(secrets["HOME_DIR"], "abc$HOME");
+        assert_eq!(secrets["BRACED"], "${USER}x");
+        assert_eq!(secrets["QUOTED"], "$PATH");
+    }
+
+    #[test]
+    fn quotes_comments_and_exports() {
+        let contents = "# comment\n\nexport TOKEN=abc # trailing\nSINGLE='a \\n b # c'\n\
+                        DOUBLE=\"line\\nnext \\\"quoted\\\"\"  # comment\nURL=http://host/#anchor\n\
+                        MULTI=\"first\nsecond\"\nEMPTY=\nWINDOWS=value\r\n";
+        let secrets = parse(contents);
+        assert_eq!(secrets["TOKEN"], "abc");
+        assert_eq!(secrets["SINGLE"], "a \\n b # c");
+        assert_eq!(secrets["DOUBLE"], "line\nnext \"quoted\"");
+        assert_eq!(secrets["URL"], "http://host/#anchor");
+        assert_eq!(secrets["MULTI"], "first\nsecond");
+        assert_eq!(secrets["EMPTY"], "");
+        assert_eq!(secrets["WINDOWS"], "value");
+        assert_eq!(secrets.len(), 7);
+    }
+
+    #[test]
+    fn exported_lines_are_parsed_back() {
+        let values = [
+            "plain",
+            "with space",
+            "it's $HOME",
+            "multi\nline \"quoted\" \\",
+        ];
+        let contents: Vec<String> = values
+            .iter()
+            .enumerate()
+            .map(|(index, value)| format::dotenv_line(&format!("KEY_{}", index), value))
+            .collect();
+        let secrets = parse(&contents.join("\n"));
+        for (index, value) in values.iter().enumerate() {
+            assert_eq!(secrets[&format!("KEY_{}", index)], *value);
+        }
+    }
+
+    #[test]
+    fn invalid_lines_are_rejected() {
+        assert!(parse_dotenv("MISSING_VALUE\n").is_err());
+        assert!(parse_dotenv("=value\n").is_err());
+        assert!(parse_dotenv("OPEN='never closed\n").is_err());
+        assert!(parse_dotenv("TRAILING=\"value\" extra\n").is_err());
+    }
+}
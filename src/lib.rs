@@ -1,32 +1,75 @@
 #![allow(rustdoc::bare_urls)]
 #![doc = include_str!("../README.md")]
+pub mod cache;
+pub mod client;
+pub mod commands;
 pub mod constant;
+pub mod credentials;
 pub mod decipher;
+pub mod diff;
 mod enums;
+pub mod error;
+pub mod export;
+pub mod format;
+mod http;
+pub mod import;
+pub mod manifest;
+#[cfg(feature = "async")]
+pub mod nonblocking;
+pub mod offline;
 pub mod parser;
+pub mod profile;
 pub mod request;
+pub mod retry;
 pub mod routes;
+pub mod secret;
+mod state;
+#[cfg(feature = "testing")]
+pub mod testing;
+pub mod tls;
+pub mod typed;
 mod util;
 
+#[cfg(feature = "derive")]
+pub use vaultapi_derive::VaultSecrets;
+
 use serde_json::Value;
 
+/// Runs the CLI, either as a subcommand or with the flags to retrieve/decrypt secrets.
+///
+/// # Arguments
+/// * `arg_config` - Config object to retrieve CLI arguments.
+///
+/// # Returns
+/// * A `Result<Output, VaultError>` containing the command's output.
+pub fn run(arg_config: parser::ArgConfig) -> Result<commands::Output, error::VaultError> {
+    match &arg_config.command {
+        Some(command) => commands::dispatch(command, arg_config.profile.as_deref()),
+        None => decrypt_vault_secret(arg_config).map(commands::Output::Value),
+    }
+}
+
 /// Decrypts the ciphered text into JSON object.
 ///
 /// # Arguments
 /// * `arg_config` - Config object to retrieve CLI arguments.
 ///
 /// # Returns
-/// * A `Result<Value, String>` containing deciphered content.
-pub fn decrypt_vault_secret(arg_config: parser::ArgConfig) -> Result<Value, String> {
-    let env_config = parser::env_variables();
+/// * A `Result<Value, VaultError>` containing deciphered content.
+pub fn decrypt_vault_secret(arg_config: parser::ArgConfig) -> Result<Value, error::VaultError> {
+    let env_config = parser::env_variables_with_store(arg_config.profile.as_deref())?;
     if arg_config.cipher.is_empty() {
-        return request::server_connection(&arg_config, &env_config);
+        let vault_client = client::VaultClient::new(env_config)?;
+        let secrets = request::server_connection(&arg_config, &vault_client);
+        commands::report_warnings(&vault_client);
+        return secrets;
     }
     decipher::transit_decrypt(
-        &env_config.apikey,
-        &env_config.secret,
+        env_config.apikey.expose(),
+        env_config.secret.expose(),
         &arg_config.cipher,
         env_config.transit_key_length,
         env_config.transit_time_bucket,
+        env_config.transit_bucket_tolerance,
     )
 }
@@ -1,12 +1,55 @@
+use vaultapi::commands::Output;
+use vaultapi::error::VaultError;
+use vaultapi::format;
+
+/// Maps the errors to process exit codes.
+///
+/// # Arguments
+/// * `err` - Error returned by the library.
+///
+/// # Returns
+/// * An `i32` exit code.
+fn exit_code(err: &VaultError) -> i32 {
+    match err {
+        VaultError::InvalidArgument(_) => 2,
+        VaultError::MissingConfig(_) => 3,
+        VaultError::Transport(_) => 4,
+        VaultError::Unauthorized(_) => 5,
+        VaultError::Status { .. } => 6,
+        VaultError::Decryption(_)
+        | VaultError::Encryption(_)
+        | VaultError::MalformedResponse(_)
+        | VaultError::Deserialize { .. } => 7,
+        VaultError::Io(_) => 8,
+        VaultError::Partial { error, .. } => exit_code(error),
+    }
+}
+
 fn main() {
     let metadata = vaultapi::constant::build_info();
-    let config = vaultapi::parser::arguments(&metadata);
-    match vaultapi::decrypt_vault_secret(config) {
-        Ok(value) => {
-            println!("{}", value);
+    let result = vaultapi::parser::arguments(&metadata).and_then(|config| {
+        let format = config.format;
+        match vaultapi::run(config)? {
+            Output::Value(value) => format::render(&value, format).map(|text| {
+                println!("{}", text);
+                0
+            }),
+            Output::Drift(value) => format::render(&value, format).map(|text| {
+                println!("{}", text);
+                1
+            }),
+            Output::Text(text) => {
+                print!("{}", text.expose());
+                Ok(0)
+            }
+            Output::ExitCode(code) => Ok(code),
         }
+    });
+    match result {
+        Ok(code) => std::process::exit(code),
         Err(err) => {
-            println!("{}", err);
+            eprintln!("{}", err);
+            std::process::exit(exit_code(&err))
         }
     };
 }
@@ -0,0 +1,368 @@
+use crate::error::VaultError;
+use crate::import::{self, FileFormat, ImportPlan};
+use serde_json::{json, Map, Value};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// Desired state of the tables, loaded from a manifest file.
+///
+/// ```toml
+/// [tables.production]
+/// DB_PASSWORD = "..."
+/// DB_PORT = 5432
+/// ```
+#[derive(Debug, Default)]
+pub struct Manifest {
+    /// Secrets of each table, sorted by the table name.
+    pub tables: BTreeMap<String, BTreeMap<String, String>>,
+}
+
+impl Manifest {
+    /// Loads a manifest from a TOML, YAML or JSON file.
+    ///
+    /// # Arguments
+    /// * `path` - Path to the manifest, which is read as TOML unless the extension is `.yaml`, `.yml` or `.json`
+    ///
+    /// # Returns
+    /// * A `Result` with the `Manifest` object.
+    pub fn load(path: &Path) -> Result<Manifest, VaultError> {
+        let file_format = match FileFormat::from_path(path) {
+            FileFormat::Dotenv => FileFormat::Toml,
+            file_format => file_format,
+        };
+        let invalid = |err: &str| {
+            VaultError::InvalidArgument(format!("Invalid manifest '{}': {}", path.display(), err))
+        };
+        let tables = match import::parse_file(path, file_format)? {
+            Value::Object(mut manifest) => {
+                if let Some(key) = manifest.keys().find(|key| *key != "tables") {
+                    return Err(invalid(&format!("unknown field '{}'", key)));
+                }
+                match manifest.remove("tables") {
+                    Some(Value::Object(tables)) => tables,
+                    // A manifest without any tables is left empty in YAML
+                    Some(Value::Null) | None => Map::new(),
+                    Some(_) => return Err(invalid("'tables' must be a mapping of table names")),
+                }
+            }
+            _ => return Err(invalid("expected a mapping with 'tables'")),
+        };
+        let mut manifest = Manifest::default();
+        for (table_name, secrets) in tables {
+            let secrets = match secrets {
+                // Tables without any secrets are left empty in YAML
+                Value::Null => BTreeMap::new(),
+                secrets => import::secrets_from(path, secrets)?,
+            };
+            manifest.tables.insert(table_name, secrets);
+        }
+        Ok(manifest)
+    }
+}
+
+/// Enum to represent the change that is planned for a table.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Action {
+    /// Table doesn't exist, and is created with all its secrets.
+    Create,
+    /// Table exists, and some of its secrets are uploaded or deleted.
+    Update,
+    /// Table is not in the manifest, and is deleted with `--prune`
+    Delete,
+    /// Table is already in the desired state.
+    Unchanged,
+}
+
+impl Action {
+    /// Returns the action's name, as displayed in the plan.
+    fn as_str(&self) -> &'static str {
+        match self {
+            Action::Create => "create",
+            Action::Update => "update",
+            Action::Delete => "delete",
+            Action::Unchanged => "unchanged",
+        }
+    }
+}
+
+/// Struct to store the changes that are planned for a table.
+#[derive(Debug)]
+pub struct TablePlan {
+    pub action: Action,
+    /// Secrets that are added or overwritten, and the ones that are already up-to-date.
+    pub secrets: ImportPlan,
+    /// Keys that are in the table but not in the manifest, which are deleted with `--prune`
+    pub removed: Vec<String>,
+}
+
+/// Struct to store the changes to converge the server with a manifest.
+#[derive(Debug)]
+pub struct Plan {
+    /// Deletes the tables and keys that are not in the manifest.
+    pub prune: bool,
+    /// Changes for each table, sorted by the table name.
+    pub tables: BTreeMap<String, TablePlan>,
+    /// Tables that are not in the manifest, which are left as-is without `--prune`
+    pub unmanaged: Vec<String>,
+}
+
+impl Plan {
+    /// Compares the manifest against the tables on the server.
+    ///
+    /// # Arguments
+    /// * `manifest` - Desired state of the tables.
+    /// * `existing` - Names of all the tables on the server.
+    /// * `current` - Secrets of the existing tables that are in the manifest.
+    /// * `prune` - Deletes the tables and keys that are not in the manifest.
+    ///
+    /// # Returns
+    /// * A `Plan` object.
+    pub fn new(
+        manifest: &Manifest,
+        existing: &[String],
+        current: &BTreeMap<String, Map<String, Value>>,
+        prune: bool,
+    ) -> Plan {
+        let mut plan = Plan {
+            prune,
+            tables: BTreeMap::new(),
+            unmanaged: Vec::new(),
+        };
+        for (table_name, secrets) in &manifest.tables {
+            let table = current.get(table_name);
+            let table_plan = ImportPlan::new(secrets, table.unwrap_or(&Map::new()));
+            let removed: Vec<String> = table
+                .map(|table| {
+                    table
+                        .keys()
+                        .filter(|key| !secrets.contains_key(key.as_str()))
+                        .cloned()
+                        .collect()
+                })
+                .unwrap_or_default();
+            let uploads = !(table_plan.added.is_empty() && table_plan.overwritten.is_empty());
+            let action = match table {
+                None => Action::Create,
+                Some(_) if uploads || (prune && !removed.is_empty()) => Action::Update,
+                Some(_) => Action::Unchanged,
+            };
+            plan.tables.insert(
+                table_name.to_string(),
+                TablePlan {
+                    action,
+                    secrets: table_plan,
+                    removed,
+                },
+            );
+        }
+        for table_name in existing {
+            if manifest.tables.contains_key(table_name) {
+                continue;
+            }
+            if prune {
+                plan.tables.insert(
+                    table_name.to_string(),
+                    TablePlan {
+                        action: Action::Delete,
+                        secrets: ImportPlan::default(),
+                        removed: Vec::new(),
+                    },
+                );
+            } else {
+                plan.unmanaged.push(table_name.to_string());
+            }
+        }
+        plan.unmanaged.sort();
+        plan
+    }
+
+    /// Checks if any of the tables has to be changed.
+    pub fn has_changes(&self) -> bool {
+        self.tables
+            .values()
+            .any(|table| table.action != Action::Unchanged)
+    }
+
+    /// Renders the plan as a JSON object, with the keys but not the values.
+    ///
+    /// # Returns
+    /// * A `Value` with the action and the keys to add, change and delete for each table.
+    pub fn to_value(&self) -> Value {
+        let tables: Map<String, Value> = self
+            .tables
+            .iter()
+            .map(|(table_name, table)| {
+                let (delete, unmanaged) = match self.prune {
+                    true => (table.removed.as_slice(), &[] as &[String]),
+                    false => (&[] as &[String], table.removed.as_slice()),
+                };
+                let value = json!({
+                    "action": table.action.as_str(),
+                    "add": table.secrets.added,
+                    "change": table.secrets.overwritten,
+                    "delete": delete,
+                    "unchanged": table.secrets.unchanged,
+                    "unmanaged": unmanaged,
+                });
+                (table_name.to_string(), value)
+            })
+            .collect();
+        json!({
+            "prune": self.prune,
+            "changes": self.has_changes(),
+            "tables": tables,
+            "unmanaged": self.unmanaged,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    /// Writes a manifest to a temporary file and loads it, with a file name that is unique to each test.
+    fn load(name: &str, contents: &str) -> Result<Manifest, VaultError> {
+        let dir = std::env::temp_dir().join(format!("vaultapi-manifest-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(name);
+        fs::write(&path, contents).unwrap();
+        let manifest = Manifest::load(&path);
+        fs::remove_file(&path).unwrap();
+        manifest
+    }
+
+    /// Converts the key-value pairs into a table of secrets.
+    fn table(secrets: &[(&str, &str)]) -> BTreeMap<String, String> {
+        secrets
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    /// Creates a manifest, and the tables on the server, for the planning tests.
+    fn fixture() -> (Manifest, Vec<String>, BTreeMap<String, Map<String, Value>>) {
+        let mut manifest = Manifest::default();
+        manifest
+            .tables
+            .insert("new".to_string(), table(&[("KEY", "value")]));
+        manifest.tables.insert(
+            "changed".to_string(),
+            table(&[("SAME", "1"), ("DIFFERENT", "new"), ("ADDED", "3")]),
+        );
+        manifest
+            .tables
+            .insert("same".to_string(), table(&[("KEY", "value")]));
+        manifest
+            .tables
+            .insert("extra".to_string(), table(&[("KEY", "value")]));
+        let current = BTreeMap::from([
+            (
+                "changed".to_string(),
+                json!({"SAME": "1", "DIFFERENT": "old"})
+                    .as_object()
+                    .cloned()
+                    .unwrap(),
+            ),
+            (
+                "same".to_string(),
+                json!({"KEY": "value"}).as_object().cloned().unwrap(),
+            ),
+            (
+                "extra".to_string(),
+                json!({"KEY": "value", "STALE": "x"})
+                    .as_object()
+                    .cloned()
+                    .unwrap(),
+            ),
+        ]);
+        let existing = vec![
+            "changed".to_string(),
+            "same".to_string(),
+            "extra".to_string(),
+            "orphan".to_string(),
+        ];
+        (manifest, existing, current)
+    }
+
+    #[test]
+    fn plan_lists_the_action_for_each_table() {
+        let (manifest, existing, current) = fixture();
+        let plan = Plan::new(&manifest, &existing, &current, true);
+        let action = |table_name: &str| plan.tables[table_name].action;
+        assert_eq!(action("new"), Action::Create);
+        assert_eq!(action("changed"), Action::Update);
+        assert_eq!(action("same"), Action::Unchanged);
+        assert_eq!(action("extra"), Action::Update);
+        assert_eq!(action("orphan"), Action::Delete);
+        assert!(plan.unmanaged.is_empty());
+        assert!(plan.has_changes());
+
+        let changed = &plan.tables["changed"].secrets;
+        assert_eq!(changed.added, vec!["ADDED"]);
+        assert_eq!(changed.overwritten, vec!["DIFFERENT"]);
+        assert_eq!(changed.unchanged, vec!["SAME"]);
+        let value = plan.to_value();
+        assert_eq!(value["tables"]["extra"]["delete"], json!(["STALE"]));
+        assert_eq!(value["tables"]["extra"]["unmanaged"], json!([]));
+    }
+
+    #[test]
+    fn unmanaged_tables_and_keys_are_kept_without_prune() {
+        let (manifest, existing, current) = fixture();
+        let plan = Plan::new(&manifest, &existing, &current, false);
+        assert!(!plan.tables.contains_key("orphan"));
+        assert_eq!(plan.unmanaged, vec!["orphan"]);
+        // Extra keys alone don't change a table, unless they are pruned
+        assert_eq!(plan.tables["extra"].action, Action::Unchanged);
+        let value = plan.to_value();
+        assert_eq!(value["tables"]["extra"]["delete"], json!([]));
+        assert_eq!(value["tables"]["extra"]["unmanaged"], json!(["STALE"]));
+        assert_eq!(value["unmanaged"], json!(["orphan"]));
+
+        let mut manifest = Manifest::default();
+        manifest
+            .tables
+            .insert("same".to_string(), table(&[("KEY", "value")]));
+        let plan = Plan::new(&manifest, &["same".to_string()], &current, false);
+        assert!(!plan.has_changes());
+    }
+
+    #[test]
+    fn manifests_are_loaded_from_toml_and_yaml() {
+        let manifest = load(
+            "manifest.toml",
+            "[tables.production]\nDB_PASSWORD = \"p@ss\"\nDB_PORT = 5432\n",
+        )
+        .unwrap();
+        assert_eq!(
+            manifest.tables["production"],
+            table(&[("DB_PASSWORD", "p@ss"), ("DB_PORT", "5432")])
+        );
+        // Tables without any secrets are null in YAML
+        let manifest = load(
+            "manifest.yaml",
+            "tables:\n  empty:\n  staging:\n    KEY: value\n",
+        )
+        .unwrap();
+        assert!(manifest.tables["empty"].is_empty());
+        assert_eq!(manifest.tables["staging"], table(&[("KEY", "value")]));
+        assert!(load("manifest.yaml", "tables:\n")
+            .unwrap()
+            .tables
+            .is_empty());
+    }
+
+    #[test]
+    fn unknown_fields_are_rejected() {
+        match load(
+            "unknown.toml",
+            "prune = true\n[tables.production]\nKEY = \"value\"\n",
+        ) {
+            Err(VaultError::InvalidArgument(message)) => assert!(message.contains("'prune'")),
+            other => panic!("expected an unknown field error, got {:?}", other),
+        }
+        assert!(load("unknown.yaml", "tables: [production]\n").is_err());
+        assert!(load("unknown.yaml", "- production\n").is_err());
+    }
+}
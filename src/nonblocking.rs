@@ -0,0 +1,323 @@
+use crate::cache::CacheStats;
+use crate::enums::EndpointMapping;
+use crate::error::{VaultError, Warning};
+use crate::offline::OfflineCache;
+use crate::parser::EnvConfig;
+use crate::secret::Secret;
+use crate::state::ClientState;
+use crate::{http, parser, request, routes, typed};
+use reqwest::Client;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Reusable async client for the VaultAPI server.
+///
+/// Async counterpart of `VaultClient`, that can be used within a Tokio runtime.
+pub struct AsyncVaultClient {
+    pub env_config: EnvConfig,
+    client: Client,
+    state: ClientState,
+}
+
+impl AsyncVaultClient {
+    /// Creates a new async client and validates the health of the server.
+    ///
+    /// # Arguments
+    /// * `env_config` - Environment variables' configuration.
+    ///
+    /// # Returns
+    /// * A `Result` with the `AsyncVaultClient` object.
+    pub async fn new(env_config: EnvConfig) -> Result<AsyncVaultClient, VaultError> {
+        let state = ClientState::new(&env_config);
+        let client = http::async_client(&env_config)?;
+        let vault_client = AsyncVaultClient {
+            env_config,
+            client,
+            state,
+        };
+        if let Err(err) = vault_client.health_check().await {
+            vault_client.state.offline.tolerate(err)?;
+        }
+        Ok(vault_client)
+    }
+
+    /// Creates a new async client using the configuration loaded from the environment.
+    ///
+    /// # Returns
+    /// * A `Result` with the `AsyncVaultClient` object.
+    pub async fn from_env() -> Result<AsyncVaultClient, VaultError> {
+        AsyncVaultClient::new(parser::env_variables()?).await
+    }
+
+    /// Returns the underlying HTTP client.
+    pub fn http_client(&self) -> &Client {
+        &self.client
+    }
+
+    /// Returns the number of cache hits and misses for the secrets retrieved with this client.
+    pub fn cache_stats(&self) -> CacheStats {
+        self.state.cache_stats()
+    }
+
+    /// Returns the warnings about the degraded requests since the last call, and clears them.
+    ///
+    /// Secrets served from the offline cache, failed cache updates and an insecure TLS setting
+    /// don't fail the requests, so the caller decides how to report them.
+    pub fn take_warnings(&self) -> Vec<Warning> {
+        self.state.take_warnings(&self.env_config)
+    }
+
+    /// Removes all the cached secrets, so that the next requests are served by the server.
+    pub fn clear_cache(&self) {
+        self.state.clear_cache()
+    }
+
+    /// Runs an operation on the offline cache in the blocking thread pool,
+    /// so that reading and writing the cache file doesn't stall the async runtime.
+    ///
+    /// # Arguments
+    /// * `operation` - Operation on the offline cache.
+    ///
+    /// # Returns
+    /// * The output of the operation.
+    async fn offline<T: Send + 'static>(
+        &self,
+        operation: impl FnOnce(&OfflineCache) -> T + Send + 'static,
+    ) -> T {
+        let offline = Arc::clone(&self.state.offline);
+        if !offline.is_enabled() {
+            return operation(&offline);
+        }
+        match tokio::task::spawn_blocking(move || operation(&offline)).await {
+            Ok(output) => output,
+            Err(err) => std::panic::resume_unwind(err.into_panic()),
+        }
+    }
+
+    /// Function to validate the health check of the server endpoint.
+    ///
+    /// # Returns
+    /// * An empty `Result` if the server is healthy.
+    pub async fn health_check(&self) -> Result<(), VaultError> {
+        let url = routes::endpoint_url(&self.env_config, EndpointMapping::Health);
+        let response = self.client.get(url).send().await?;
+        let status = response.status();
+        if !status.is_success() {
+            return Err(request::status_error(
+                status,
+                response.json::<Value>().await.ok(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Retrieve secret(s) from the server.
+    ///
+    /// # Arguments
+    /// * `key` - Secret key for the which the value has to be retrieved.
+    /// * `table_name` - Table name where the secret is stored.
+    ///
+    /// # Returns
+    /// * A `Result` with the decrypted payload, that is wiped from memory when dropped.
+    pub async fn get_secret(
+        &self,
+        key: &str,
+        table_name: &str,
+    ) -> Result<Secret<Value>, VaultError> {
+        if let Some(secret) = self.state.cached_secret(table_name, key) {
+            return Ok(secret);
+        }
+        let request = routes::get_secret_request(&self.env_config, key, table_name);
+        let response =
+            request::make_request_async(&self.client, request, &self.env_config.retry_policy).await;
+        let secret = self
+            .state
+            .secrets_fetched(&self.env_config, table_name, response);
+        let (table_name, key) = (table_name.to_string(), key.to_string());
+        self.offline(move |offline| offline.resolve_secrets(&table_name, &[&key], secret))
+            .await
+            .map(Secret::json)
+    }
+
+    /// Retrieve multiple secrets from the server in a single request.
+    ///
+    /// # Arguments
+    /// * `keys` - Secret keys for the which the values have to be retrieved.
+    /// * `table_name` - Table name where the secrets are stored.
+    ///
+    /// # Returns
+    /// * A `Result` with a `HashMap` of the decrypted secrets, that are wiped from memory when dropped.
+    pub async fn get_secrets(
+        &self,
+        keys: &[&str],
+        table_name: &str,
+    ) -> Result<HashMap<String, Secret<Value>>, VaultError> {
+        let (mut secrets, missing) = self.state.cache.get_many(table_name, keys);
+        if missing.is_empty() {
+            return Ok(secrets);
+        }
+        let request = routes::get_secrets_request(&self.env_config, &missing, table_name);
+        let response =
+            request::make_request_async(&self.client, request, &self.env_config.retry_policy).await;
+        let fetched = self
+            .state
+            .secrets_fetched(&self.env_config, table_name, response);
+        let table = table_name.to_string();
+        let missing: Vec<String> = missing.iter().map(|key| key.to_string()).collect();
+        let fetched = self
+            .offline(move |offline| {
+                let missing: Vec<&str> = missing.iter().map(String::as_str).collect();
+                offline.resolve_secrets(&table, &missing, fetched)
+            })
+            .await?;
+        secrets.extend(routes::secrets_map(fetched)?);
+        Ok(secrets)
+    }
+
+    /// Retrieve ALL the secrets stored in a particular table from the server.
+    ///
+    /// # Arguments
+    /// * `table_name` - Table name where the secrets are stored.
+    ///
+    /// # Returns
+    /// * A `Result` with the decrypted payload, that is wiped from memory when dropped.
+    pub async fn get_table(&self, table_name: &str) -> Result<Secret<Value>, VaultError> {
+        if let Some(table) = self.state.cache.get_table(table_name) {
+            return Ok(table);
+        }
+        let request = routes::get_table_request(&self.env_config, table_name);
+        let response =
+            request::make_request_async(&self.client, request, &self.env_config.retry_policy).await;
+        let table = self
+            .state
+            .table_fetched(&self.env_config, table_name, response);
+        let table_name = table_name.to_string();
+        self.offline(move |offline| offline.resolve_table(&table_name, table))
+            .await
+            .map(Secret::json)
+    }
+
+    /// Retrieve a secret from the server, and deserialize its value into the requested type.
+    ///
+    /// # Arguments
+    /// * `key` - Secret key for the which the value has to be retrieved.
+    /// * `table_name` - Table name where the secret is stored.
+    ///
+    /// # Returns
+    /// * A `Result` with the deserialized secret value.
+    pub async fn get_secret_as<T: DeserializeOwned>(
+        &self,
+        key: &str,
+        table_name: &str,
+    ) -> Result<T, VaultError> {
+        typed::secret_value(
+            key,
+            self.get_secret(key, table_name).await?.expose().clone(),
+        )
+    }
+
+    /// Retrieve ALL the secrets stored in a table, and deserialize them into the requested type.
+    ///
+    /// # Arguments
+    /// * `table_name` - Table name where the secrets are stored.
+    ///
+    /// # Returns
+    /// * A `Result` with the deserialized table.
+    pub async fn get_table_as<T: DeserializeOwned>(
+        &self,
+        table_name: &str,
+    ) -> Result<T, VaultError> {
+        typed::from_value(self.get_table(table_name).await?.expose().clone())
+    }
+
+    /// List all available table names in the server.
+    ///
+    /// # Returns
+    /// * A `Result` with a `Vec<String>` of all the table names.
+    pub async fn list_tables(&self) -> Result<Vec<String>, VaultError> {
+        let request = routes::list_tables_request(&self.env_config);
+        let response =
+            request::make_request_async(&self.client, request, &self.env_config.retry_policy)
+                .await?;
+        routes::table_names(response)
+    }
+
+    /// Creates or updates a secret value stored in a table.
+    ///
+    /// # Arguments
+    /// * `secrets` - HashMap of secrets as key-value pairs.
+    /// * `table_name` - Table name where the secret has to be added/updated.
+    ///
+    /// # Returns
+    /// * A `Result` with the `Value` object from the server's response.
+    pub async fn put_secret(
+        &self,
+        secrets: &HashMap<String, String>,
+        table_name: &str,
+    ) -> Result<Value, VaultError> {
+        let request = routes::put_secret_request(&self.env_config, secrets, table_name)?;
+        let response =
+            request::make_request_async(&self.client, request, &self.env_config.retry_policy).await;
+        let keys: Vec<&str> = secrets.keys().map(String::as_str).collect();
+        self.state.invalidate(table_name, &keys);
+        let table_name = table_name.to_string();
+        let keys: Vec<String> = secrets.keys().cloned().collect();
+        self.offline(move |offline| {
+            let keys: Vec<&str> = keys.iter().map(String::as_str).collect();
+            offline.remove_secrets(&table_name, &keys)
+        })
+        .await;
+        response
+    }
+
+    /// Deletes a secret stored in a table.
+    ///
+    /// # Arguments
+    /// * `key` - Secret key that has to be deleted.
+    /// * `table_name` - Table name where the secret exists.
+    ///
+    /// # Returns
+    /// * A `Result` with the `Value` object from the server's response.
+    pub async fn delete_secret(&self, key: &str, table_name: &str) -> Result<Value, VaultError> {
+        let request = routes::delete_secret_request(&self.env_config, key, table_name);
+        let response =
+            request::make_request_async(&self.client, request, &self.env_config.retry_policy).await;
+        self.state.invalidate(table_name, &[key]);
+        let (table_name, key) = (table_name.to_string(), key.to_string());
+        self.offline(move |offline| offline.remove_secrets(&table_name, &[&key]))
+            .await;
+        response
+    }
+
+    /// Creates a new table.
+    ///
+    /// # Arguments
+    /// * `table_name` - Table name that has to be created.
+    ///
+    /// # Returns
+    /// * A `Result` with the `Value` object from the server's response.
+    pub async fn create_table(&self, table_name: &str) -> Result<Value, VaultError> {
+        let request = routes::create_table_request(&self.env_config, table_name);
+        request::make_request_async(&self.client, request, &self.env_config.retry_policy).await
+    }
+
+    /// Deletes an existing table.
+    ///
+    /// # Arguments
+    /// * `table_name` - Table name that has to be deleted.
+    ///
+    /// # Returns
+    /// * A `Result` with the `Value` object from the server's response.
+    pub async fn delete_table(&self, table_name: &str) -> Result<Value, VaultError> {
+        let request = routes::delete_table_request(&self.env_config, table_name);
+        let response =
+            request::make_request_async(&self.client, request, &self.env_config.retry_policy).await;
+        self.state.cache.invalidate_table(table_name);
+        let table_name = table_name.to_string();
+        self.offline(move |offline| offline.remove_table(&table_name))
+            .await;
+        response
+    }
+}
@@ -0,0 +1,563 @@
+use crate::error::{VaultError, Warning};
+use crate::parser::EnvConfig;
+use crate::secret::{self, Secret};
+use crate::util;
+use ring::aead::{self, Aad, LessSafeKey, Nonce, UnboundKey};
+use ring::digest;
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use std::{fs, io, thread};
+use zeroize::Zeroizing;
+
+/// Secret value stored in the offline cache, along with the time it was saved.
+#[derive(Serialize, Deserialize)]
+struct StoredSecret {
+    value: Value,
+    saved_at: u64,
+}
+
+/// Wipes the decrypted value, when the cache file is dropped after each read or update.
+impl Drop for StoredSecret {
+    fn drop(&mut self) {
+        secret::wipe_value(&mut self.value);
+    }
+}
+
+/// Secrets of a single table stored in the offline cache.
+#[derive(Default, Serialize, Deserialize)]
+struct StoredTable {
+    /// Time when the entire table was saved, if it still holds every secret in the table.
+    loaded_at: Option<u64>,
+    secrets: HashMap<String, StoredSecret>,
+}
+
+/// Contents of the offline cache file, before encryption.
+#[derive(Default, Serialize, Deserialize)]
+struct CacheFile {
+    tables: HashMap<String, StoredTable>,
+}
+
+/// Checks if an error was caused by the server being unreachable.
+///
+/// # Arguments
+/// * `err` - Error returned by the request.
+///
+/// # Returns
+/// * A boolean flag indicating whether the offline cache can be used instead.
+pub fn is_unreachable(err: &VaultError) -> bool {
+    matches!(
+        err,
+        VaultError::Transport(_)
+            | VaultError::Status {
+                code: 502..=504,
+                ..
+            }
+    )
+}
+
+/// Interval in seconds after which an unchanged entry is saved again, to keep its age current.
+const REFRESH_INTERVAL: u64 = 60;
+/// Maximum time to wait for the lock on the cache file, before giving up on the update.
+const LOCK_TIMEOUT: Duration = Duration::from_secs(5);
+/// Age after which a lock file is considered to be left behind by a crashed process.
+const LOCK_STALE: Duration = Duration::from_secs(30);
+/// Delay between the attempts to acquire the lock on the cache file.
+const LOCK_POLL: Duration = Duration::from_millis(20);
+
+/// Lock file that serializes the updates to the cache file across threads and processes.
+///
+/// The lock is released when the object is dropped.
+struct UpdateLock {
+    path: PathBuf,
+}
+
+impl UpdateLock {
+    /// Acquires the lock next to the cache file, waiting for the other updates to finish.
+    ///
+    /// # Arguments
+    /// * `cache_path` - Path to the cache file.
+    ///
+    /// # Returns
+    /// * A `Result` with the `UpdateLock` object.
+    fn acquire(cache_path: &Path) -> Result<UpdateLock, VaultError> {
+        let mut file_name = cache_path.file_name().unwrap_or_default().to_os_string();
+        file_name.push(".lock");
+        let path = cache_path.with_file_name(file_name);
+        if let Some(parent) = path
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+        {
+            fs::create_dir_all(parent)?;
+        }
+        let deadline = Instant::now() + LOCK_TIMEOUT;
+        loop {
+            match fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&path)
+            {
+                Ok(_) => return Ok(UpdateLock { path }),
+                Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {
+                    let stale = fs::metadata(&path)
+                        .and_then(|metadata| metadata.modified())
+                        .ok()
+                        .and_then(|modified| modified.elapsed().ok())
+                        .is_some_and(|age| age > LOCK_STALE);
+                    if stale {
+                        let _ = fs::remove_file(&path);
+                        continue;
+                    }
+                    if Instant::now() >= deadline {
+                        return Err(VaultError::Io(format!(
+                            "Timed out waiting for the lock '{}'",
+                            path.display()
+                        )));
+                    }
+                    thread::sleep(LOCK_POLL);
+                }
+                Err(err) => return Err(err.into()),
+            }
+        }
+    }
+}
+
+impl Drop for UpdateLock {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Returns the current time in seconds since the UNIX epoch.
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
+
+/// Encrypted on-disk cache with the last-known-good secrets, that is served when the server is unreachable.
+///
+/// The file is encrypted with AES-256-GCM, using a key derived from the APIkey and secret,
+/// and is bound to the server URL so that it can't be served for a different server.
+/// Stale secrets and failed updates never fail the request, and are reported with `take_warnings` instead.
+pub struct OfflineCache {
+    path: Option<PathBuf>,
+    max_age: u64,
+    key: LessSafeKey,
+    server: String,
+    warnings: Mutex<Vec<Warning>>,
+}
+
+impl OfflineCache {
+    /// Creates the offline cache from the environment configuration.
+    ///
+    /// # Arguments
+    /// * `env_config` - Environment variables' configuration.
+    ///
+    /// # Returns
+    /// * An `OfflineCache` object, that is disabled when no cache file is configured.
+    pub fn new(env_config: &EnvConfig) -> OfflineCache {
+        let hash_input = Secret::concat(&[
+            "offline.",
+            env_config.apikey.expose(),
+            ".",
+            env_config.secret.expose(),
+        ]);
+        let mut hash_output = digest::digest(&digest::SHA256, hash_input.expose().as_bytes());
+        // SHA-256 output is always a valid AES-256 key
+        let unbound_key = UnboundKey::new(&aead::AES_256_GCM, hash_output.as_ref())
+            .expect("SHA-256 digest is a valid AES-256 key");
+        secret::wipe_digest(&mut hash_output);
+        OfflineCache {
+            path: env_config.offline_cache.clone(),
+            max_age: env_config.offline_max_age,
+            key: LessSafeKey::new(unbound_key),
+            server: env_config.vault_server.to_string(),
+            warnings: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Checks if the offline cache is enabled.
+    pub fn is_enabled(&self) -> bool {
+        self.path.is_some()
+    }
+
+    /// Acquires the lock on the pending warnings, recovering from a poisoned lock.
+    fn warnings(&self) -> MutexGuard<'_, Vec<Warning>> {
+        self.warnings.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// Returns the warnings reported since the last call, and clears them.
+    pub fn take_warnings(&self) -> Vec<Warning> {
+        std::mem::take(&mut *self.warnings())
+    }
+
+    /// Decides whether a failed health check can be tolerated, because the offline cache can be served instead.
+    ///
+    /// # Arguments
+    /// * `err` - Error returned by the health check.
+    ///
+    /// # Returns
+    /// * An empty `Result` if the error can be tolerated, otherwise the same error.
+    pub fn tolerate(&self, err: VaultError) -> Result<(), VaultError> {
+        if self.is_enabled() && is_unreachable(&err) {
+            self.warnings().push(Warning::Unreachable(err.to_string()));
+            return Ok(());
+        }
+        Err(err)
+    }
+
+    /// Reads and decrypts the cache file.
+    fn load(&self, path: &Path) -> Result<CacheFile, VaultError> {
+        // The secrets are decrypted in place, so the buffer is wiped once it is parsed
+        let mut contents = Zeroizing::new(fs::read(path)?);
+        if contents.len() < aead::NONCE_LEN {
+            return Err(VaultError::Decryption(
+                "Offline cache file is too short".to_string(),
+            ));
+        }
+        let (nonce_bytes, ciphertext) = contents.split_at_mut(aead::NONCE_LEN);
+        let nonce = Nonce::try_assume_unique_for_key(nonce_bytes)
+            .map_err(|_| VaultError::Decryption("Invalid nonce".to_string()))?;
+        let plaintext = self
+            .key
+            .open_in_place(nonce, Aad::from(self.server.as_bytes()), ciphertext)
+            .map_err(|_| {
+                VaultError::Decryption("Failed to decrypt the offline cache".to_string())
+            })?;
+        serde_json::from_slice(plaintext).map_err(|err| VaultError::Decryption(err.to_string()))
+    }
+
+    /// Encrypts and writes the cache file, readable only by the owner.
+    fn save(&self, path: &Path, cache_file: &CacheFile) -> Result<(), VaultError> {
+        let mut buffer = serde_json::to_vec(cache_file)
+            .map(Zeroizing::new)
+            .map_err(|err| VaultError::Io(format!("Failed to serialize the cache: {}", err)))?;
+        // Room for the tag, so that the plaintext is never left behind by a reallocation
+        buffer.reserve_exact(aead::AES_256_GCM.tag_len());
+        let mut nonce_bytes = [0u8; aead::NONCE_LEN];
+        SystemRandom::new()
+            .fill(&mut nonce_bytes)
+            .map_err(|_| VaultError::Io("Failed to generate a nonce".to_string()))?;
+        self.key
+            .seal_in_place_append_tag(
+                Nonce::assume_unique_for_key(nonce_bytes),
+                Aad::from(self.server.as_bytes()),
+                &mut *buffer,
+            )
+            .map_err(|_| VaultError::Io("Failed to encrypt the offline cache".to_string()))?;
+        let mut contents = nonce_bytes.to_vec();
+        contents.extend_from_slice(&buffer);
+        util::write_private(path, &contents)
+    }
+
+    /// Applies a change to the cache file, and reports the failures as warnings instead of failing the request.
+    ///
+    /// The file is locked while it is read and written, and isn't rewritten when nothing has changed.
+    ///
+    /// # Arguments
+    /// * `change` - Function that applies the change, and returns whether the cache file was modified.
+    fn update(&self, change: impl FnOnce(&mut CacheFile) -> bool) {
+        if let Some(path) = &self.path {
+            let result = UpdateLock::acquire(path).and_then(|_lock| {
+                // Unreadable caches (eg: after rotating the credentials) are replaced with a fresh one
+                let mut cache_file = self.load(path).unwrap_or_default();
+                if !change(&mut cache_file) {
+                    return Ok(());
+                }
+                self.save(path, &cache_file)
+            });
+            if let Err(err) = result {
+                self.warnings().push(Warning::CacheUpdate(err.to_string()));
+            }
+        }
+    }
+
+    /// Stores all the secrets of a table, replacing the previously stored secrets.
+    ///
+    /// # Arguments
+    /// * `table_name` - Table name.
+    /// * `payload` - Decrypted payload with all the secrets in the table.
+    pub fn store_table(&self, table_name: &str, payload: &Value) {
+        if let Value::Object(secrets) = payload {
+            let saved_at = now();
+            self.update(|cache_file| {
+                if let Some(StoredTable {
+                    loaded_at: Some(loaded_at),
+                    secrets: stored,
+                }) = cache_file.tables.get(table_name)
+                {
+                    let unchanged = stored.len() == secrets.len()
+                        && secrets.iter().all(|(key, value)| {
+                            stored.get(key).is_some_and(|stored| stored.value == *value)
+                        });
+                    if unchanged && saved_at.saturating_sub(*loaded_at) < REFRESH_INTERVAL {
+                        return false;
+                    }
+                }
+                let table = StoredTable {
+                    loaded_at: Some(saved_at),
+                    secrets: secrets
+                        .iter()
+                        .map(|(key, value)| {
+                            let value = value.clone();
+                            (key.to_string(), StoredSecret { value, saved_at })
+                        })
+                        .collect(),
+                };
+                cache_file.tables.insert(table_name.to_string(), table);
+                true
+            });
+        }
+    }
+
+    /// Stores the secrets from a decrypted payload.
+    ///
+    /// # Arguments
+    /// * `table_name` - Table name where the secrets are stored.
+    /// * `payload` - Decrypted payload with the secrets as key-value pairs.
+    pub fn store_secrets(&self, table_name: &str, payload: &Value) {
+        if let Value::Object(secrets) = payload {
+            let saved_at = now();
+            self.update(|cache_file| {
+                let table = cache_file.tables.entry(table_name.to_string()).or_default();
+                let mut changed = false;
+                for (key, value) in secrets {
+                    if let Some(stored) = table.secrets.get(key) {
+                        if stored.value == *value
+                            && saved_at.saturating_sub(stored.saved_at) < REFRESH_INTERVAL
+                        {
+                            continue;
+                        }
+                    }
+                    let value = value.clone();
+                    table
+                        .secrets
+                        .insert(key.to_string(), StoredSecret { value, saved_at });
+                    changed = true;
+                }
+                changed
+            });
+        }
+    }
+
+    /// Removes the secrets that were updated or deleted in the server.
+    ///
+    /// # Arguments
+    /// * `table_name` - Table name where the secrets are stored.
+    /// * `keys` - Secret keys.
+    pub fn remove_secrets(&self, table_name: &str, keys: &[&str]) {
+        self.update(|cache_file| {
+            let table = match cache_file.tables.get_mut(table_name) {
+                Some(table) => table,
+                None => return false,
+            };
+            let mut changed = table.loaded_at.take().is_some();
+            for key in keys {
+                changed |= table.secrets.remove(*key).is_some();
+            }
+            changed
+        });
+    }
+
+    /// Removes a table that was deleted in the server.
+    ///
+    /// # Arguments
+    /// * `table_name` - Table name.
+    pub fn remove_table(&self, table_name: &str) {
+        self.update(|cache_file| cache_file.tables.remove(table_name).is_some());
+    }
+
+    /// Reports a warning about serving stale secrets.
+    fn warn(&self, err: &VaultError, table_name: &str, saved_at: u64) {
+        self.warnings().push(Warning::Stale {
+            table_name: table_name.to_string(),
+            age: now().saturating_sub(saved_at),
+            error: err.to_string(),
+        });
+    }
+
+    /// Checks if a stored entry is within the max-age limit.
+    fn is_fresh(&self, saved_at: u64) -> bool {
+        now().saturating_sub(saved_at) <= self.max_age
+    }
+
+    /// Stores the secrets retrieved from the server, or serves them from the offline cache when the server is unreachable.
+    ///
+    /// # Arguments
+    /// * `table_name` - Table name where the secrets are stored.
+    /// * `keys` - Secret keys that were requested.
+    /// * `result` - Decrypted secrets, or the error returned by the request to the server.
+    ///
+    /// # Returns
+    /// * A `Result` with the secrets as key-value pairs.
+    pub fn resolve_secrets(
+        &self,
+        table_name: &str,
+        keys: &[&str],
+        result: Result<Value, VaultError>,
+    ) -> Result<Value, VaultError> {
+        match result {
+            Ok(secrets) => {
+                self.store_secrets(table_name, &secrets);
+                Ok(secrets)
+            }
+            Err(err) => self.secrets(table_name, keys, err),
+        }
+    }
+
+    /// Stores a table retrieved from the server, or serves it from the offline cache when the server is unreachable.
+    ///
+    /// # Arguments
+    /// * `table_name` - Table name.
+    /// * `result` - Decrypted table, or the error returned by the request to the server.
+    ///
+    /// # Returns
+    /// * A `Result` with all the secrets in the table.
+    pub fn resolve_table(
+        &self,
+        table_name: &str,
+        result: Result<Value, VaultError>,
+    ) -> Result<Value, VaultError> {
+        match result {
+            Ok(table) => {
+                self.store_table(table_name, &table);
+                Ok(table)
+            }
+            Err(err) => self.table(table_name, err),
+        }
+    }
+
+    /// Serves all the secrets of a table from the offline cache, when the server is unreachable.
+    ///
+    /// # Arguments
+    /// * `table_name` - Table name.
+    /// * `err` - Error returned by the request to the server.
+    ///
+    /// # Returns
+    /// * A `Result` with the stored secrets, otherwise the original error.
+    pub fn table(&self, table_name: &str, err: VaultError) -> Result<Value, VaultError> {
+        let path = match &self.path {
+            Some(path) if is_unreachable(&err) => path,
+            _ => return Err(err),
+        };
+        let cache_file = match self.load(path) {
+            Ok(cache_file) => cache_file,
+            Err(_) => return Err(err),
+        };
+        match cache_file.tables.get(table_name) {
+            Some(StoredTable {
+                loaded_at: Some(loaded_at),
+                secrets,
+            }) if self.is_fresh(*loaded_at) => {
+                self.warn(&err, table_name, *loaded_at);
+                let secrets: Map<String, Value> = secrets
+                    .iter()
+                    .map(|(key, stored)| (key.to_string(), stored.value.clone()))
+                    .collect();
+                Ok(Value::Object(secrets))
+            }
+            _ => Err(err),
+        }
+    }
+
+    /// Serves the requested secrets from the offline cache, when the server is unreachable.
+    ///
+    /// # Arguments
+    /// * `table_name` - Table name where the secrets are stored.
+    /// * `keys` - Secret keys.
+    /// * `err` - Error returned by the request to the server.
+    ///
+    /// # Returns
+    /// * A `Result` with the stored secrets as key-value pairs, if all of them are available.
+    pub fn secrets(
+        &self,
+        table_name: &str,
+        keys: &[&str],
+        err: VaultError,
+    ) -> Result<Value, VaultError> {
+        let path = match &self.path {
+            Some(path) if is_unreachable(&err) => path,
+            _ => return Err(err),
+        };
+        let cache_file = match self.load(path) {
+            Ok(cache_file) => cache_file,
+            Err(_) => return Err(err),
+        };
+        let table = match cache_file.tables.get(table_name) {
+            Some(table) => table,
+            None => return Err(err),
+        };
+        let mut secrets = Map::new();
+        let mut oldest = u64::MAX;
+        for key in keys {
+            match table.secrets.get(*key) {
+                Some(stored) if self.is_fresh(stored.saved_at) => {
+                    oldest = oldest.min(stored.saved_at);
+                    secrets.insert(key.to_string(), stored.value.clone());
+                }
+                _ => return Err(err),
+            }
+        }
+        self.warn(&err, table_name, oldest);
+        Ok(Value::Object(secrets))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// Creates an offline cache backed by a file under the system's temporary directory.
+    fn offline_cache(name: &str) -> (OfflineCache, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("vaultapi-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let path = dir.join("offline.cache");
+        let url = reqwest::Url::parse("http://127.0.0.1:8080").unwrap();
+        let mut env_config = EnvConfig::new(url, "apikey", "secret");
+        env_config.offline_cache = Some(path.clone());
+        (OfflineCache::new(&env_config), path)
+    }
+
+    #[test]
+    fn unchanged_secrets_are_not_rewritten() {
+        let (offline, path) = offline_cache("unchanged");
+        let payload = json!({"key": "value"});
+        offline.store_secrets("table", &payload);
+        let saved = fs::read(&path).unwrap();
+        // Each write uses a new nonce, so a rewrite always changes the file
+        offline.store_secrets("table", &payload);
+        offline.store_table("other", &payload);
+        let stored = fs::read(&path).unwrap();
+        offline.store_table("other", &payload);
+        assert_eq!(fs::read(&path).unwrap(), stored);
+        assert_ne!(saved, stored);
+        offline.store_secrets("table", &json!({"key": "changed"}));
+        assert_ne!(fs::read(&path).unwrap(), stored);
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn unreachable_server_serves_the_stored_secrets() {
+        let (offline, path) = offline_cache("fallback");
+        offline.store_table("table", &json!({"key": "value"}));
+        let unreachable = || VaultError::Transport("connection refused".to_string());
+        let served = offline.secrets("table", &["key"], unreachable()).unwrap();
+        assert_eq!(served, json!({"key": "value"}));
+        assert_eq!(offline.table("table", unreachable()).unwrap(), served);
+        assert!(offline
+            .secrets("table", &["missing"], unreachable())
+            .is_err());
+        // Errors from a reachable server are never masked by the cache
+        let unauthorized = VaultError::Unauthorized("invalid apikey".to_string());
+        assert!(offline.table("table", unauthorized).is_err());
+        assert!(!path.with_file_name("offline.cache.lock").exists());
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
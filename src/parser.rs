@@ -1,21 +1,84 @@
 use crate::constant;
-use crate::enums;
-use crate::util;
-use reqwest::blocking::Client;
+use crate::credentials::{self, Credentials, Store};
+use crate::decipher;
+use crate::diff::Reveal;
+use crate::error::VaultError;
+use crate::export::ExportFormat;
+use crate::format::Format;
+use crate::import::FileFormat;
+use crate::profile::{self, Profile};
+use crate::retry::RetryPolicy;
+use crate::secret::Secret;
+use crate::tls::TlsConfig;
 use reqwest::Url;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
 
 /// Default transit key length. This will be the AES key length for transit encryption.
 const TRANSIT_KEY_LENGTH: usize = 32;
 /// Default transit time bucket. This will be the interval for which the transit epoch will remain constant.
 const TRANSIT_TIME_BUCKET: u64 = 60;
+/// Default transit bucket tolerance. This will be the number of adjacent epoch buckets to try when decrypting.
+const TRANSIT_BUCKET_TOLERANCE: u64 = 1;
+/// Default cache TTL in seconds. Caching of the decrypted secrets is disabled by default.
+const CACHE_TTL: u64 = 0;
+/// Default max-age in seconds, for the secrets served from the offline cache.
+const OFFLINE_MAX_AGE: u64 = 86400;
+/// Default timeout in seconds for each request, from connecting until the response body is read.
+const TIMEOUT: u64 = 30;
+/// Default timeout in seconds to establish a connection with the server.
+const CONNECT_TIMEOUT: u64 = 10;
 
 /// Struct to construct environment variables.
 pub struct EnvConfig {
     pub vault_server: Url,
-    pub apikey: String,
-    pub secret: String,
+    pub apikey: Secret<String>,
+    pub secret: Secret<String>,
     pub transit_key_length: usize,
     pub transit_time_bucket: u64,
+    pub transit_bucket_tolerance: u64,
+    pub cache_ttl: u64,
+    pub offline_cache: Option<PathBuf>,
+    pub offline_max_age: u64,
+    pub retry_policy: RetryPolicy,
+    pub timeout: Option<Duration>,
+    pub connect_timeout: Option<Duration>,
+    pub proxy: Option<String>,
+    pub no_proxy: Option<String>,
+    pub tls: TlsConfig,
+}
+
+impl EnvConfig {
+    /// Creates a new configuration with the default transit settings.
+    ///
+    /// # Arguments
+    /// * `vault_server` - VaultAPI server URL.
+    /// * `apikey` - API key to authenticate the VaultAPI server.
+    /// * `secret` - Secret to derive the transit encryption key.
+    ///
+    /// # Returns
+    /// * An `EnvConfig` object.
+    pub fn new(vault_server: Url, apikey: &str, secret: &str) -> EnvConfig {
+        EnvConfig {
+            vault_server,
+            apikey: Secret::from(apikey),
+            secret: Secret::from(secret),
+            transit_key_length: TRANSIT_KEY_LENGTH,
+            transit_time_bucket: TRANSIT_TIME_BUCKET,
+            transit_bucket_tolerance: TRANSIT_BUCKET_TOLERANCE,
+            cache_ttl: CACHE_TTL,
+            offline_cache: None,
+            offline_max_age: OFFLINE_MAX_AGE,
+            retry_policy: RetryPolicy::default(),
+            timeout: Some(Duration::from_secs(TIMEOUT)),
+            connect_timeout: Some(Duration::from_secs(CONNECT_TIMEOUT)),
+            proxy: None,
+            no_proxy: None,
+            tls: TlsConfig::default(),
+        }
+    }
 }
 
 /// Struct to construct the commandline arguments.
@@ -25,29 +88,258 @@ pub struct ArgConfig {
     pub get_secret: String,
     pub get_secrets: String,
     pub get_table: String,
+    pub format: Format,
+    /// Profile selected with `--profile`, or `VAULTAPI_PROFILE` when the option is not given.
+    pub profile: Option<String>,
+    pub command: Option<Command>,
+}
+
+/// Struct to represent a table on the server of a profile, parsed from `profile:table`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Endpoint {
+    /// Profile to load from the config file, or `None` to use the current configuration.
+    pub profile: Option<String>,
+    pub table_name: String,
+}
+
+/// Parses the endpoint from `profile:table`, or `table` for the current configuration.
+impl FromStr for Endpoint {
+    type Err = VaultError;
+
+    fn from_str(endpoint: &str) -> Result<Self, Self::Err> {
+        let (profile, table_name) = match endpoint.split_once(':') {
+            Some((profile, table_name)) => (Some(profile), table_name),
+            None => (None, endpoint),
+        };
+        if table_name.is_empty() {
+            return Err(VaultError::InvalidArgument(format!(
+                "'{}' is missing the table name, expected profile:table",
+                endpoint
+            )));
+        }
+        Ok(Endpoint {
+            profile: profile
+                .filter(|profile| !profile.is_empty())
+                .map(str::to_string),
+            table_name: table_name.to_string(),
+        })
+    }
+}
+
+/// Enum to represent the subcommands supported by the CLI.
+#[derive(Debug)]
+pub enum Command {
+    /// Retrieve the given keys, or the entire table when no keys are given.
+    Get {
+        table_name: String,
+        keys: Vec<String>,
+    },
+    /// Create or update secrets in a table.
+    Put {
+        table_name: String,
+        secrets: HashMap<String, String>,
+    },
+    /// Delete a secret from a table.
+    Delete { table_name: String, key: String },
+    /// List all the tables.
+    ListTables,
+    /// Create a new table.
+    CreateTable { table_name: String },
+    /// Delete an existing table.
+    DeleteTable { table_name: String },
+    /// Run a command with the secrets set as environment variables.
+    Exec {
+        table_name: String,
+        keys: Vec<String>,
+        prefix: String,
+        override_env: bool,
+        program: Vec<String>,
+    },
+    /// Import the secrets from a file into a table.
+    Import {
+        table_name: String,
+        path: PathBuf,
+        file_format: Option<FileFormat>,
+        create_table: bool,
+        dry_run: bool,
+        no_overwrite: bool,
+        chunk_size: usize,
+    },
+    /// Compare the secrets in a file against a table.
+    Diff {
+        table_name: String,
+        path: PathBuf,
+        file_format: Option<FileFormat>,
+        reveal: Reveal,
+    },
+    /// Show the changes to converge the server with a manifest.
+    Plan { path: PathBuf, prune: bool },
+    /// Apply the changes to converge the server with a manifest.
+    Apply {
+        path: PathBuf,
+        prune: bool,
+        chunk_size: usize,
+    },
+    /// Export the secrets of a table to a file.
+    Export {
+        table_name: String,
+        keys: Vec<String>,
+        export_format: ExportFormat,
+        output: Option<PathBuf>,
+        name: Option<String>,
+        namespace: Option<String>,
+    },
+    /// Copy the secrets from a table to another, which may be on a different server.
+    ///
+    /// `sync` is parsed as a copy of all the keys, that can also delete the keys missing in the source.
+    Copy {
+        from: Endpoint,
+        to: Endpoint,
+        keys: Vec<String>,
+        create_table: bool,
+        dry_run: bool,
+        prune: bool,
+        chunk_size: usize,
+    },
+    /// Store the credentials for the server in the OS keyring, or the encrypted credentials file.
+    Login { stdin: bool, store: Store },
+    /// Remove the stored credentials for the server.
+    Logout,
+}
+
+/// Enum to represent the order in which the env vars and the settings of a profile are applied.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Precedence {
+    /// Env vars override the profile, which is the case for the config file's `default_profile`.
+    Environment,
+    /// Profile overrides the env vars, which is the case for a profile selected with `--profile` or `VAULTAPI_PROFILE`.
+    Profile,
+    /// Env vars are ignored, which is the case for the profiles of the `copy` and `sync` endpoints.
+    ProfileOnly,
+}
+
+impl Precedence {
+    /// Returns the precedence for the profile that is selected with `--profile` or `VAULTAPI_PROFILE`,
+    /// or for the default profile when none is selected.
+    ///
+    /// # Arguments
+    /// * `name` - Name of the selected profile, if any.
+    pub fn selected(name: Option<&str>) -> Precedence {
+        match name {
+            Some(_) => Precedence::Profile,
+            None => Precedence::Environment,
+        }
+    }
 }
 
-/// Function to retrieve environment variables.
+/// Function to layer the value of an environment variable with the value set in the profile.
 ///
 /// # Arguments
-/// * `key` - Key to retrieve the environment variables.
-/// * `default` - Default value if env var is not found.
+/// * `precedence` - Order in which the env var and the profile are applied.
+/// * `env_value` - Function to retrieve the value from the environment.
+/// * `profile_value` - Value set in the profile.
 ///
 /// # Returns
-/// * Retrieved environment variable as a ``String`` object.
-fn get_env(key: &str, default: &str) -> String {
-    match std::env::var(key) {
-        Ok(value) => value,
-        Err(_) => {
-            if !default.is_empty() {
-                return default.to_string();
-            }
-            println!("{:} environment variable not set", key);
-            std::process::exit(1)
-        }
+/// * An `Option` with the value that takes precedence, or `None` if neither is set.
+fn layer<T>(
+    precedence: Precedence,
+    env_value: impl FnOnce() -> Option<T>,
+    profile_value: Option<T>,
+) -> Option<T> {
+    match precedence {
+        Precedence::Environment => env_value().or(profile_value),
+        Precedence::Profile => profile_value.or_else(env_value),
+        Precedence::ProfileOnly => profile_value,
     }
 }
 
+/// Function to retrieve a setting from the environment and the profile, falling back to the default.
+///
+/// # Arguments
+/// * `precedence` - Order in which the env var and the profile are applied.
+/// * `key` - Key to retrieve the environment variable.
+/// * `profile_value` - Value set in the profile.
+/// * `default` - Default value if neither is set, or the env var is invalid.
+///
+/// # Returns
+/// * The value of the setting.
+fn setting<T: FromStr>(
+    precedence: Precedence,
+    key: &str,
+    profile_value: Option<T>,
+    default: T,
+) -> T {
+    let env_value = || {
+        std::env::var(key)
+            .ok()
+            .and_then(|value| value.parse::<T>().ok())
+    };
+    layer(precedence, env_value, profile_value).unwrap_or(default)
+}
+
+/// Function to retrieve an optional string setting from the environment and the profile.
+///
+/// # Arguments
+/// * `precedence` - Order in which the env var and the profile are applied.
+/// * `key` - Key to retrieve the environment variable.
+/// * `profile_value` - Value set in the profile.
+///
+/// # Returns
+/// * An `Option` with the value, or `None` if neither is set.
+fn optional_setting(
+    precedence: Precedence,
+    key: &str,
+    profile_value: &Option<String>,
+) -> Option<String> {
+    layer(
+        precedence,
+        || std::env::var(key).ok(),
+        profile_value.clone(),
+    )
+    .filter(|value| !value.is_empty())
+}
+
+/// Function to retrieve a secret setting from the environment and the profile.
+///
+/// # Arguments
+/// * `precedence` - Order in which the env var and the profile are applied.
+/// * `key` - Key to retrieve the environment variable.
+/// * `profile_value` - Value set in the profile.
+///
+/// # Returns
+/// * An `Option` with the value, or `None` if neither is set.
+fn secret_setting(
+    precedence: Precedence,
+    key: &str,
+    profile_value: &Option<Secret<String>>,
+) -> Option<Secret<String>> {
+    let env_value = || std::env::var(key).ok().map(Secret::new);
+    layer(precedence, env_value, profile_value.clone()).filter(|value| !value.expose().is_empty())
+}
+
+/// Function to retrieve a duration in seconds from the environment and the profile.
+///
+/// # Arguments
+/// * `precedence` - Order in which the env var and the profile are applied.
+/// * `key` - Key to retrieve the environment variable.
+/// * `profile_value` - Value in seconds set in the profile.
+/// * `default` - Default value in seconds, if neither is set or the env var is invalid.
+///
+/// # Returns
+/// * An `Option` with the duration, or `None` if it is set to zero.
+fn env_seconds(
+    precedence: Precedence,
+    key: &str,
+    profile_value: Option<f64>,
+    default: u64,
+) -> Option<Duration> {
+    let seconds = setting(precedence, key, profile_value, default as f64);
+    if seconds.is_finite() && seconds > 0.0 {
+        return Some(Duration::from_secs_f64(seconds));
+    }
+    None
+}
+
 /// Load environment variables from an env file.
 ///
 /// # Arguments
@@ -65,62 +357,307 @@ pub fn default_env_file() -> String {
     std::env::var("env_file").unwrap_or(std::env::var("ENV_FILE").unwrap_or(".env".to_string()))
 }
 
-/// Function to validate the health check of the server endpoint.
+/// Function to retrieve environment variables and load it as an ``EnvConfig`` object.
+///
+/// A profile selected with `--profile` or `VAULTAPI_PROFILE` takes precedence over the environment variables,
+/// which in turn take precedence over the config file's `default_profile` and the defaults.
+///
+/// # Returns
+/// * A `Result` with the retrieved environment variable as an ``EnvConfig`` object.
+pub fn env_variables() -> Result<EnvConfig, VaultError> {
+    load_env(&default_env_file());
+    let name = profile::selected_name();
+    let profile = profile::selected(name.as_deref())?;
+    env_config(profile, Precedence::selected(name.as_deref()))
+}
+
+/// Function to retrieve environment variables and load it as an ``EnvConfig`` object,
+/// falling back to the credentials stored with `vaultapi login`.
 ///
 /// # Arguments
-/// * `server_url` - Server URL to perform the health check.
-fn health_check(server_url: &Url) {
-    let client = Client::new();
-    let url = util::urljoin(&[server_url.as_ref(), enums::EndpointMapping::Health.as_str()]);
-    let request = client.get(url);
-    match request.send() {
-        Ok(init_response) => match init_response.error_for_status() {
-            Ok(_) => {}
-            Err(err) => {
-                println!("{}", err);
-                std::process::exit(1)
-            }
-        },
-        Err(err) => {
-            println!("{}", err);
-            std::process::exit(1)
-        }
+/// * `name` - Profile selected with `--profile` or `VAULTAPI_PROFILE`, or `None` for the default profile.
+///
+/// # Returns
+/// * A `Result` with the retrieved environment variable as an ``EnvConfig`` object.
+pub fn env_variables_with_store(name: Option<&str>) -> Result<EnvConfig, VaultError> {
+    load_env(&default_env_file());
+    let profile = profile::selected(name)?;
+    env_config_with_store(profile, Precedence::selected(name))
+}
+
+/// Function to retrieve the VaultAPI server URL, from the environment or the profile.
+///
+/// # Arguments
+/// * `profile` - Profile with the server URL.
+/// * `precedence` - Order in which the env var and the profile are applied.
+///
+/// # Returns
+/// * A `Result` with the server URL.
+pub fn vault_server(profile: &Profile, precedence: Precedence) -> Result<Url, VaultError> {
+    let env_value = || std::env::var("VAULT_SERVER").ok();
+    let vault_server_env = layer(precedence, env_value, profile.vault_server.clone())
+        .filter(|vault_server| !vault_server.is_empty())
+        .ok_or_else(|| {
+            VaultError::MissingConfig("VAULT_SERVER environment variable not set".to_string())
+        })?;
+    match Url::parse(&vault_server_env) {
+        Ok(url) => Ok(url),
+        Err(_) => Err(VaultError::MissingConfig(
+            "Failed to parse vault address".to_string(),
+        )),
     }
 }
 
-/// Function to retrieve environment variables and load it as an ``EnvConfig`` object.
+/// Function to pair the API key and the secret that are set in the same source.
+///
+/// # Arguments
+/// * `source` - Name of the source, to report a partial pair.
+/// * `apikey` - API key set in the source.
+/// * `secret` - Secret set in the source.
 ///
 /// # Returns
-/// * Retrieved environment variable as an ``EnvConfig`` object.
-pub fn env_variables() -> EnvConfig {
-    load_env(&default_env_file());
-    // Retrieve the API key from the environment
-    let apikey = get_env("APIKEY", "");
-    let secret = get_env("SECRET", "");
-    let vault_server_env = get_env("VAULT_SERVER", "");
-    let vault_server = match Url::parse(&vault_server_env) {
-        Ok(url) => url,
-        Err(_) => {
-            println!("Failed to parse vault address");
-            std::process::exit(1)
+/// * A `Result` with the credentials, or `None` if neither is set in the source.
+fn credential_pair(
+    source: &str,
+    apikey: Option<Secret<String>>,
+    secret: Option<Secret<String>>,
+) -> Result<Option<Credentials>, VaultError> {
+    let is_set = |value: &Secret<String>| !value.expose().is_empty();
+    match (apikey.filter(is_set), secret.filter(is_set)) {
+        (Some(apikey), Some(secret)) => Ok(Some(Credentials { apikey, secret })),
+        (None, None) => Ok(None),
+        (apikey, _) => {
+            let (set, missing) = match apikey {
+                Some(_) => ("APIKEY", "SECRET"),
+                None => ("SECRET", "APIKEY"),
+            };
+            Err(VaultError::MissingConfig(format!(
+                "{} is set in the {} without {}, both must be set in the same place",
+                set, source, missing
+            )))
         }
+    }
+}
+
+/// Function to resolve the API key and the secret as a pair, from a single source.
+///
+/// The environment and the profile are checked in the order of the precedence.
+///
+/// # Arguments
+/// * `profile` - Profile with the credentials.
+/// * `precedence` - Order in which the env vars and the profile are applied.
+///
+/// # Returns
+/// * A `Result` with an `Option` of the credentials, if they are set in either source.
+fn credentials(
+    profile: &Profile,
+    precedence: Precedence,
+) -> Result<Option<Credentials>, VaultError> {
+    let env_var = |key: &str| std::env::var(key).ok().map(Secret::new);
+    let from_env = || credential_pair("environment", env_var("APIKEY"), env_var("SECRET"));
+    let from_profile =
+        || credential_pair("profile", profile.apikey.clone(), profile.secret.clone());
+    match precedence {
+        Precedence::Environment => match from_env()? {
+            Some(credentials) => Ok(Some(credentials)),
+            None => from_profile(),
+        },
+        Precedence::Profile => match from_profile()? {
+            Some(credentials) => Ok(Some(credentials)),
+            None => from_env(),
+        },
+        Precedence::ProfileOnly => from_profile(),
+    }
+}
+
+/// Function to load the ``EnvConfig`` object from the environment variables and a profile.
+///
+/// The credentials stored with `vaultapi login` are not used, see ``env_config_with_store``.
+///
+/// # Arguments
+/// * `profile` - Profile with the settings, that are layered with the environment variables.
+/// * `precedence` - Order in which the env vars and the profile are applied.
+///
+/// # Returns
+/// * A `Result` with the ``EnvConfig`` object.
+pub fn env_config(profile: Profile, precedence: Precedence) -> Result<EnvConfig, VaultError> {
+    let credentials = credentials(&profile, precedence)?.ok_or_else(|| {
+        VaultError::MissingConfig("APIKEY and SECRET environment variables not set".to_string())
+    })?;
+    env_config_with(profile, precedence, credentials)
+}
+
+/// Function to load the ``EnvConfig`` object from the environment variables and a profile,
+/// falling back to the credentials stored with `vaultapi login`.
+///
+/// Reading the stored credentials may access the OS keyring, or prompt for the passphrase of the
+/// credentials file when `VAULTAPI_PASSPHRASE` is not set, so this is only used by the CLI.
+///
+/// # Arguments
+/// * `profile` - Profile with the settings, that are layered with the environment variables.
+/// * `precedence` - Order in which the env vars and the profile are applied.
+///
+/// # Returns
+/// * A `Result` with the ``EnvConfig`` object.
+pub fn env_config_with_store(
+    profile: Profile,
+    precedence: Precedence,
+) -> Result<EnvConfig, VaultError> {
+    let credentials = match credentials(&profile, precedence)? {
+        Some(credentials) => credentials,
+        None => credentials::lookup(vault_server(&profile, precedence)?.as_str())?.ok_or_else(
+            || {
+                VaultError::MissingConfig(
+                    "APIKEY and SECRET environment variables not set, or use 'vaultapi login' to store them"
+                        .to_string(),
+                )
+            },
+        )?,
     };
-    health_check(&vault_server);
-    let transit_key_length = match std::env::var("TRANSMIT_KEY_LENGTH") {
-        Ok(value) => value.parse::<usize>().unwrap_or(TRANSIT_KEY_LENGTH),
-        Err(_) => TRANSIT_KEY_LENGTH,
+    env_config_with(profile, precedence, credentials)
+}
+
+/// Function to load the ``EnvConfig`` object from the environment variables and a profile,
+/// with the given credentials instead of the ones in the environment, profile or store.
+///
+/// # Arguments
+/// * `profile` - Profile with the settings, that are layered with the environment variables.
+/// * `precedence` - Order in which the env vars and the profile are applied.
+/// * `credentials` - API key and secret to authenticate the server.
+///
+/// # Returns
+/// * A `Result` with the ``EnvConfig`` object.
+pub fn env_config_with(
+    profile: Profile,
+    precedence: Precedence,
+    credentials: Credentials,
+) -> Result<EnvConfig, VaultError> {
+    let vault_server = vault_server(&profile, precedence)?;
+    let Credentials { apikey, secret } = credentials;
+    let transit_key_length = setting(
+        precedence,
+        "TRANSMIT_KEY_LENGTH",
+        profile.transit_key_length,
+        TRANSIT_KEY_LENGTH,
+    );
+    let transit_time_bucket = setting(
+        precedence,
+        "TRANSIT_TIME_BUCKET",
+        profile.transit_time_bucket,
+        TRANSIT_TIME_BUCKET,
+    );
+    let transit_bucket_tolerance = setting(
+        precedence,
+        "TRANSIT_BUCKET_TOLERANCE",
+        profile.transit_bucket_tolerance,
+        TRANSIT_BUCKET_TOLERANCE,
+    );
+    if transit_bucket_tolerance > decipher::MAX_BUCKET_TOLERANCE {
+        return Err(VaultError::MissingConfig(format!(
+            "TRANSIT_BUCKET_TOLERANCE must be at most {}, got {}",
+            decipher::MAX_BUCKET_TOLERANCE,
+            transit_bucket_tolerance
+        )));
+    }
+    let cache_ttl = setting(precedence, "VAULT_CACHE_TTL", profile.cache_ttl, CACHE_TTL);
+    let env_path = |key: &str, profile_value: &Option<PathBuf>| {
+        let env_value = || std::env::var(key).ok().map(PathBuf::from);
+        layer(precedence, env_value, profile_value.clone())
+            .filter(|path| !path.as_os_str().is_empty())
+    };
+    let offline_cache = env_path("VAULT_OFFLINE_CACHE", &profile.offline_cache);
+    let offline_max_age = setting(
+        precedence,
+        "VAULT_OFFLINE_MAX_AGE",
+        profile.offline_max_age,
+        OFFLINE_MAX_AGE,
+    );
+    let defaults = RetryPolicy::default();
+    let retry_policy = RetryPolicy {
+        max_attempts: setting(
+            precedence,
+            "VAULT_RETRY_ATTEMPTS",
+            profile.retry_attempts,
+            defaults.max_attempts,
+        )
+        .max(1),
+        base_delay: Duration::from_millis(setting(
+            precedence,
+            "VAULT_RETRY_BASE_DELAY",
+            profile.retry_base_delay,
+            defaults.base_delay.as_millis() as u64,
+        )),
+        max_delay: Duration::from_millis(setting(
+            precedence,
+            "VAULT_RETRY_MAX_DELAY",
+            profile.retry_max_delay,
+            defaults.max_delay.as_millis() as u64,
+        )),
+        ..defaults
     };
-    let transit_time_bucket = match std::env::var("TRANSIT_TIME_BUCKET") {
-        Ok(value) => value.parse::<u64>().unwrap_or(TRANSIT_TIME_BUCKET),
-        Err(_) => TRANSIT_TIME_BUCKET,
+    let timeout = env_seconds(precedence, "VAULT_TIMEOUT", profile.timeout, TIMEOUT);
+    let connect_timeout = env_seconds(
+        precedence,
+        "VAULT_CONNECT_TIMEOUT",
+        profile.connect_timeout,
+        CONNECT_TIMEOUT,
+    );
+    let proxy = optional_setting(precedence, "VAULT_PROXY", &profile.proxy);
+    let no_proxy = optional_setting(precedence, "VAULT_NO_PROXY", &profile.no_proxy);
+    let pinned_spki: Vec<String> = layer(
+        precedence,
+        || {
+            std::env::var("VAULT_PINNED_SPKI")
+                .ok()
+                .map(|value| value.split(',').map(String::from).collect())
+        },
+        profile.pinned_spki.clone(),
+    )
+    .unwrap_or_default();
+    let insecure = layer(
+        precedence,
+        || {
+            std::env::var("VAULT_INSECURE")
+                .ok()
+                .map(|value| matches!(value.to_lowercase().as_str(), "1" | "true" | "yes"))
+        },
+        profile.insecure,
+    )
+    .unwrap_or_default();
+    let tls = TlsConfig {
+        ca_cert: env_path("VAULT_CA_CERT", &profile.ca_cert),
+        client_cert: env_path("VAULT_CLIENT_CERT", &profile.client_cert),
+        client_key: env_path("VAULT_CLIENT_KEY", &profile.client_key),
+        client_cert_password: secret_setting(
+            precedence,
+            "VAULT_CLIENT_CERT_PASSWORD",
+            &profile.client_cert_password,
+        ),
+        pinned_spki: pinned_spki
+            .iter()
+            .map(|pin| pin.trim())
+            .filter(|pin| !pin.is_empty())
+            .map(String::from)
+            .collect(),
+        insecure,
     };
-    EnvConfig {
+    Ok(EnvConfig {
         vault_server,
         apikey,
         secret,
         transit_key_length,
         transit_time_bucket,
-    }
+        transit_bucket_tolerance,
+        cache_ttl,
+        offline_cache,
+        offline_max_age,
+        retry_policy,
+        timeout,
+        connect_timeout,
+        proxy,
+        no_proxy,
+        tls,
+    })
 }
 
 /// Parses and returns the command-line arguments.
@@ -129,17 +666,20 @@ pub fn env_variables() -> EnvConfig {
 /// * `metadata` - Metadata object loaded with cargo information.
 ///
 /// # Returns
-/// * Commandline arguments loaded as an ``ArgConfig`` object.
-pub fn arguments(metadata: &constant::MetaData) -> ArgConfig {
+/// * A `Result` with the commandline arguments loaded as an ``ArgConfig`` object.
+pub fn arguments(metadata: &constant::MetaData) -> Result<ArgConfig, VaultError> {
     let args: Vec<String> = std::env::args().collect();
 
     let mut version = false;
     let mut env_file = String::new();
+    let mut profile = String::new();
     let mut cipher = String::new();
     let mut table_name = String::new();
     let mut get_secret = String::new();
     let mut get_secrets = String::new();
     let mut get_table = String::new();
+    let mut format = Format::default();
+    let mut command = None;
 
     // Loop through the command-line arguments and parse them.
     let mut i = 1; // Start from the second argument (args[0] is the program name).
@@ -148,10 +688,39 @@ pub fn arguments(metadata: &constant::MetaData) -> ArgConfig {
             "-h" | "--help" => {
                 let helper = "VaultAPI-Client takes the arguments, --env_file and --version/-v\n\n\
                 --env_file: Custom filename to load the environment variables. Defaults to '.env'\n\
+                --profile: Profile to load from the config file. Defaults to 'VAULTAPI_PROFILE'\n\
                 --cipher: Cipher text to decrypt\n\
-                --version: Get the package version.\n"
+                --table: Name of the table to retrieve the secret from.\n\
+                --get-secret: Get the value of a particular secret key.\n\
+                --get-secrets: Get the values of multiple keys using a comma separated list.\n\
+                --get-table: Get all the secrets stored in a table.\n\
+                --format: Output format: json, dotenv, shell, yaml or raw.\n\
+                --version: Get the package version.\n\n\
+                Commands:\n\
+                get --table <TABLE> [KEY]...: Get the given secrets, or the entire table.\n\
+                put --table <TABLE> <KEY=VALUE>...: Create or update secrets in a table.\n\
+                delete --table <TABLE> <KEY>: Delete a secret from a table.\n\
+                tables list: List all the tables.\n\
+                tables create <TABLE>: Create a new table.\n\
+                tables delete <TABLE>: Delete an existing table.\n\
+                exec --table <TABLE> [--keys <KEYS>] [--prefix <PREFIX>] [--no-override] -- <COMMAND>...: \
+                Run a command with the secrets set as environment variables.\n\
+                import --table <TABLE> [--format <FORMAT>] [--create-table] [--dry-run] [--chunk-size <N>] <FILE>: \
+                Import the secrets from a dotenv, JSON, YAML or TOML file.\n\
+                diff --table <TABLE> [--format <FORMAT>] [--show-values | --hash-values] <FILE>: \
+                Compare the secrets in a file against a table, exiting with 1 on drift.\n\
+                plan [--prune] <MANIFEST>: Show the changes to converge the tables with a manifest, exiting with 1 on changes.\n\
+                apply [--prune] [--chunk-size <N>] <MANIFEST>: Apply the changes to converge the tables with a manifest.\n\
+                export --table <TABLE> [--format <FORMAT>] [--keys <KEYS>] [--output <FILE>] [--name <NAME>] [--namespace <NAMESPACE>]: \
+                Export the secrets as dotenv, json, yaml, k8s-secret or docker-env.\n\
+                copy --from <PROFILE:TABLE> --to <PROFILE:TABLE> [--keys <KEYS>] [--create-table] [--dry-run] [--chunk-size <N>]: \
+                Copy the secrets between tables, on the servers of two profiles.\n\
+                sync --from <PROFILE:TABLE> --to <PROFILE:TABLE> [--prune] [--create-table] [--dry-run] [--chunk-size <N>]: \
+                Copy all the secrets, and delete the ones missing in the source with --prune.\n\
+                login [--file] [--stdin]: Store the credentials in the OS keyring, or an encrypted file.\n\
+                logout: Remove the stored credentials.\n"
                     .to_string();
-                println!("Usage: {} [OPTIONS]\n\n{}", args[0], helper);
+                println!("Usage: {} [OPTIONS] [COMMAND]\n\n{}", args[0], helper);
                 std::process::exit(0)
             }
             "-V" | "-v" | "--version" => {
@@ -162,17 +731,22 @@ pub fn arguments(metadata: &constant::MetaData) -> ArgConfig {
                 if i < args.len() {
                     env_file = args[i].clone();
                 } else {
-                    println!("--env_file requires a value.");
-                    std::process::exit(1)
+                    return Err(VaultError::InvalidArgument(
+                        "--env_file requires a value.".to_string(),
+                    ));
                 }
             }
+            "--profile" => {
+                profile = option_value(&args, &mut i)?;
+            }
             "--cipher" => {
                 i += 1; // Move to the next argument.
                 if i < args.len() {
                     cipher = args[i].clone();
                 } else {
-                    println!("--cipher requires a value.");
-                    std::process::exit(1)
+                    return Err(VaultError::InvalidArgument(
+                        "--cipher requires a value.".to_string(),
+                    ));
                 }
             }
             "--table" => {
@@ -180,8 +754,9 @@ pub fn arguments(metadata: &constant::MetaData) -> ArgConfig {
                 if i < args.len() {
                     table_name = args[i].clone();
                 } else {
-                    println!("--get-secret requires a value.");
-                    std::process::exit(1)
+                    return Err(VaultError::InvalidArgument(
+                        "--table requires a value.".to_string(),
+                    ));
                 }
             }
             "--get-secret" => {
@@ -189,8 +764,9 @@ pub fn arguments(metadata: &constant::MetaData) -> ArgConfig {
                 if i < args.len() {
                     get_secret = args[i].clone();
                 } else {
-                    println!("--get-secret requires a value.");
-                    std::process::exit(1)
+                    return Err(VaultError::InvalidArgument(
+                        "--get-secret requires a value.".to_string(),
+                    ));
                 }
             }
             "--get-secrets" => {
@@ -198,8 +774,9 @@ pub fn arguments(metadata: &constant::MetaData) -> ArgConfig {
                 if i < args.len() {
                     get_secrets = args[i].clone();
                 } else {
-                    println!("--get-secrets requires a value.");
-                    std::process::exit(1)
+                    return Err(VaultError::InvalidArgument(
+                        "--get-secrets requires a value.".to_string(),
+                    ));
                 }
             }
             "--get-table" => {
@@ -207,13 +784,51 @@ pub fn arguments(metadata: &constant::MetaData) -> ArgConfig {
                 if i < args.len() {
                     get_table = args[i].clone();
                 } else {
-                    println!("--get-table requires a value.");
-                    std::process::exit(1)
+                    return Err(VaultError::InvalidArgument(
+                        "--get-table requires a value.".to_string(),
+                    ));
                 }
             }
+            "--format" => {
+                format = option_value(&args, &mut i)?.parse::<Format>()?;
+            }
+            "get" | "put" | "delete" | "tables" => {
+                command = Some(subcommand(&args[i..])?);
+                break;
+            }
+            "exec" => {
+                command = Some(exec_command(&args[i..])?);
+                break;
+            }
+            "import" => {
+                command = Some(import_command(&args[i..])?);
+                break;
+            }
+            "diff" => {
+                command = Some(diff_command(&args[i..])?);
+                break;
+            }
+            "plan" | "apply" => {
+                command = Some(manifest_command(&args[i..])?);
+                break;
+            }
+            "export" => {
+                command = Some(export_command(&args[i..])?);
+                break;
+            }
+            "copy" | "sync" => {
+                command = Some(copy_command(&args[i..])?);
+                break;
+            }
+            "login" | "logout" => {
+                command = Some(login_command(&args[i..])?);
+                break;
+            }
             _ => {
-                println!("Unknown argument: {}", args[i]);
-                std::process::exit(1)
+                return Err(VaultError::InvalidArgument(format!(
+                    "Unknown argument: {}",
+                    args[i]
+                )))
             }
         }
         i += 1;
@@ -226,11 +841,686 @@ pub fn arguments(metadata: &constant::MetaData) -> ArgConfig {
         env_file = default_env_file();
     }
     load_env(&env_file);
-    ArgConfig {
+    // Commandline takes precedence over the environment variable
+    let profile = Some(profile)
+        .filter(|profile| !profile.is_empty())
+        .or_else(profile::selected_name);
+    Ok(ArgConfig {
         cipher,
         table_name,
         get_secret,
         get_secrets,
         get_table,
+        format,
+        profile,
+        command,
+    })
+}
+
+/// Retrieves the value for an option that requires one.
+///
+/// # Arguments
+/// * `args` - Commandline arguments.
+/// * `index` - Index of the option, which is moved to the value.
+///
+/// # Returns
+/// * A `Result` with the option's value.
+fn option_value(args: &[String], index: &mut usize) -> Result<String, VaultError> {
+    let option = &args[*index];
+    *index += 1; // Move to the next argument.
+    match args.get(*index) {
+        Some(value) => Ok(value.clone()),
+        None => Err(VaultError::InvalidArgument(format!(
+            "{} requires a value.",
+            option
+        ))),
+    }
+}
+
+/// Retrieves the value for `--chunk-size`, which has to be a positive number.
+///
+/// # Arguments
+/// * `args` - Commandline arguments.
+/// * `index` - Index of the option, which is moved to the value.
+///
+/// # Returns
+/// * A `Result` with the chunk size.
+fn chunk_size_value(args: &[String], index: &mut usize) -> Result<usize, VaultError> {
+    match option_value(args, index)?.parse::<usize>() {
+        Ok(size) if size > 0 => Ok(size),
+        _ => Err(VaultError::InvalidArgument(
+            "--chunk-size requires a positive number".to_string(),
+        )),
+    }
+}
+
+/// Parses a subcommand along with its arguments.
+///
+/// # Arguments
+/// * `args` - Commandline arguments, starting with the subcommand.
+///
+/// # Returns
+/// * A `Result` with the parsed `Command`.
+fn subcommand(args: &[String]) -> Result<Command, VaultError> {
+    let mut table_name = String::new();
+    let mut positional: Vec<String> = Vec::new();
+    let mut i = 1;
+    while i < args.len() {
+        match args[i].as_str() {
+            "--table" => table_name = option_value(args, &mut i)?,
+            arg if arg.starts_with("--") => {
+                return Err(VaultError::InvalidArgument(format!(
+                    "Unknown argument for '{}': {}",
+                    args[0], arg
+                )))
+            }
+            arg => positional.push(arg.to_string()),
+        }
+        i += 1;
+    }
+    let require_table = |table_name: String| {
+        if table_name.is_empty() {
+            Err(VaultError::InvalidArgument(format!(
+                "'{}' requires --table",
+                args[0]
+            )))
+        } else {
+            Ok(table_name)
+        }
+    };
+    match args[0].as_str() {
+        "get" => Ok(Command::Get {
+            table_name: require_table(table_name)?,
+            keys: positional,
+        }),
+        "put" => {
+            if positional.is_empty() {
+                return Err(VaultError::InvalidArgument(
+                    "'put' requires at least one KEY=VALUE pair".to_string(),
+                ));
+            }
+            let mut secrets = HashMap::new();
+            for pair in positional {
+                match pair.split_once('=') {
+                    Some((key, value)) if !key.is_empty() => {
+                        secrets.insert(key.to_string(), value.to_string());
+                    }
+                    _ => {
+                        return Err(VaultError::InvalidArgument(format!(
+                            "Invalid secret '{}', expected KEY=VALUE",
+                            pair
+                        )))
+                    }
+                }
+            }
+            Ok(Command::Put {
+                table_name: require_table(table_name)?,
+                secrets,
+            })
+        }
+        "delete" => match positional.as_slice() {
+            [key] => Ok(Command::Delete {
+                table_name: require_table(table_name)?,
+                key: key.to_string(),
+            }),
+            _ => Err(VaultError::InvalidArgument(
+                "'delete' requires exactly one KEY".to_string(),
+            )),
+        },
+        _ => match positional
+            .iter()
+            .map(String::as_str)
+            .collect::<Vec<&str>>()
+            .as_slice()
+        {
+            ["list"] => Ok(Command::ListTables),
+            ["create", table_name] => Ok(Command::CreateTable {
+                table_name: table_name.to_string(),
+            }),
+            ["delete", table_name] => Ok(Command::DeleteTable {
+                table_name: table_name.to_string(),
+            }),
+            _ => Err(VaultError::InvalidArgument(
+                "'tables' requires one of: list, create <TABLE>, delete <TABLE>".to_string(),
+            )),
+        },
+    }
+}
+
+/// Parses the `exec` subcommand, where everything after `--` (or the first positional argument) is the command to run.
+///
+/// # Arguments
+/// * `args` - Commandline arguments, starting with the subcommand.
+///
+/// # Returns
+/// * A `Result` with the parsed `Command`.
+fn exec_command(args: &[String]) -> Result<Command, VaultError> {
+    let mut table_name = String::new();
+    let mut keys = Vec::new();
+    let mut prefix = String::new();
+    let mut override_env = true;
+    let mut i = 1;
+    while i < args.len() {
+        match args[i].as_str() {
+            "--table" => table_name = option_value(args, &mut i)?,
+            "--keys" => {
+                keys = option_value(args, &mut i)?
+                    .split(',')
+                    .map(|key| key.trim().to_string())
+                    .filter(|key| !key.is_empty())
+                    .collect()
+            }
+            "--prefix" => prefix = option_value(args, &mut i)?,
+            "--no-override" => override_env = false,
+            "--" => {
+                i += 1;
+                break;
+            }
+            arg if arg.starts_with("--") => {
+                return Err(VaultError::InvalidArgument(format!(
+                    "Unknown argument for 'exec': {}",
+                    arg
+                )))
+            }
+            _ => break,
+        }
+        i += 1;
+    }
+    if table_name.is_empty() {
+        return Err(VaultError::InvalidArgument(
+            "'exec' requires --table".to_string(),
+        ));
+    }
+    let program = args[i.min(args.len())..].to_vec();
+    if program.is_empty() {
+        return Err(VaultError::InvalidArgument(
+            "'exec' requires a command to run".to_string(),
+        ));
+    }
+    Ok(Command::Exec {
+        table_name,
+        keys,
+        prefix,
+        override_env,
+        program,
+    })
+}
+
+/// Default number of secrets to upload with each request, when importing a file.
+const IMPORT_CHUNK_SIZE: usize = 25;
+
+/// Parses the `import` subcommand.
+///
+/// # Arguments
+/// * `args` - Commandline arguments, starting with the subcommand.
+///
+/// # Returns
+/// * A `Result` with the parsed `Command`.
+fn import_command(args: &[String]) -> Result<Command, VaultError> {
+    let mut table_name = String::new();
+    let mut path = None;
+    let mut file_format = None;
+    let mut create_table = false;
+    let mut dry_run = false;
+    let mut no_overwrite = false;
+    let mut chunk_size = IMPORT_CHUNK_SIZE;
+    let mut i = 1;
+    while i < args.len() {
+        match args[i].as_str() {
+            "--table" => table_name = option_value(args, &mut i)?,
+            "--format" => file_format = Some(option_value(args, &mut i)?.parse::<FileFormat>()?),
+            "--create-table" => create_table = true,
+            "--dry-run" => dry_run = true,
+            "--no-overwrite" => no_overwrite = true,
+            "--chunk-size" => chunk_size = chunk_size_value(args, &mut i)?,
+            arg if arg.starts_with("--") => {
+                return Err(VaultError::InvalidArgument(format!(
+                    "Unknown argument for 'import': {}",
+                    arg
+                )))
+            }
+            arg if path.is_none() => path = Some(PathBuf::from(arg)),
+            arg => {
+                return Err(VaultError::InvalidArgument(format!(
+                    "'import' takes a single file, found: {}",
+                    arg
+                )))
+            }
+        }
+        i += 1;
+    }
+    if table_name.is_empty() {
+        return Err(VaultError::InvalidArgument(
+            "'import' requires --table".to_string(),
+        ));
+    }
+    let path = path.ok_or_else(|| {
+        VaultError::InvalidArgument("'import' requires a file to import".to_string())
+    })?;
+    Ok(Command::Import {
+        table_name,
+        path,
+        file_format,
+        create_table,
+        dry_run,
+        no_overwrite,
+        chunk_size,
+    })
+}
+
+/// Parses the `diff` subcommand.
+///
+/// # Arguments
+/// * `args` - Commandline arguments, starting with the subcommand.
+///
+/// # Returns
+/// * A `Result` with the parsed `Command`.
+fn diff_command(args: &[String]) -> Result<Command, VaultError> {
+    let mut table_name = String::new();
+    let mut path = None;
+    let mut file_format = None;
+    let mut reveal = None;
+    let mut i = 1;
+    while i < args.len() {
+        match args[i].as_str() {
+            "--table" => table_name = option_value(args, &mut i)?,
+            "--format" => file_format = Some(option_value(args, &mut i)?.parse::<FileFormat>()?),
+            arg @ ("--show-values" | "--hash-values") => {
+                if reveal.is_some() {
+                    return Err(VaultError::InvalidArgument(
+                        "--show-values and --hash-values can't be used together".to_string(),
+                    ));
+                }
+                reveal = Some(match arg {
+                    "--show-values" => Reveal::Show,
+                    _ => Reveal::Hash,
+                });
+            }
+            arg if arg.starts_with("--") => {
+                return Err(VaultError::InvalidArgument(format!(
+                    "Unknown argument for 'diff': {}",
+                    arg
+                )))
+            }
+            arg if path.is_none() => path = Some(PathBuf::from(arg)),
+            arg => {
+                return Err(VaultError::InvalidArgument(format!(
+                    "'diff' takes a single file, found: {}",
+                    arg
+                )))
+            }
+        }
+        i += 1;
+    }
+    if table_name.is_empty() {
+        return Err(VaultError::InvalidArgument(
+            "'diff' requires --table".to_string(),
+        ));
+    }
+    let path = path.ok_or_else(|| {
+        VaultError::InvalidArgument("'diff' requires a file to compare".to_string())
+    })?;
+    Ok(Command::Diff {
+        table_name,
+        path,
+        file_format,
+        reveal: reveal.unwrap_or_default(),
+    })
+}
+
+/// Parses the `plan` and `apply` subcommands.
+///
+/// # Arguments
+/// * `args` - Commandline arguments, starting with the subcommand.
+///
+/// # Returns
+/// * A `Result` with the parsed `Command`.
+fn manifest_command(args: &[String]) -> Result<Command, VaultError> {
+    let command = args[0].as_str();
+    let mut path = None;
+    let mut prune = false;
+    let mut chunk_size = IMPORT_CHUNK_SIZE;
+    let mut i = 1;
+    while i < args.len() {
+        match args[i].as_str() {
+            "--prune" => prune = true,
+            "--chunk-size" if command == "apply" => chunk_size = chunk_size_value(args, &mut i)?,
+            arg if arg.starts_with("--") => {
+                return Err(VaultError::InvalidArgument(format!(
+                    "Unknown argument for '{}': {}",
+                    command, arg
+                )))
+            }
+            arg if path.is_none() => path = Some(PathBuf::from(arg)),
+            arg => {
+                return Err(VaultError::InvalidArgument(format!(
+                    "'{}' takes a single manifest, found: {}",
+                    command, arg
+                )))
+            }
+        }
+        i += 1;
+    }
+    let path = path.ok_or_else(|| {
+        VaultError::InvalidArgument(format!("'{}' requires a manifest file", command))
+    })?;
+    match command {
+        "plan" => Ok(Command::Plan { path, prune }),
+        _ => Ok(Command::Apply {
+            path,
+            prune,
+            chunk_size,
+        }),
+    }
+}
+
+/// Parses the `export` subcommand.
+///
+/// # Arguments
+/// * `args` - Commandline arguments, starting with the subcommand.
+///
+/// # Returns
+/// * A `Result` with the parsed `Command`.
+fn export_command(args: &[String]) -> Result<Command, VaultError> {
+    let mut table_name = String::new();
+    let mut keys = Vec::new();
+    let mut export_format = ExportFormat::Dotenv;
+    let mut output = None;
+    let mut name = None;
+    let mut namespace = None;
+    let mut i = 1;
+    while i < args.len() {
+        match args[i].as_str() {
+            "--table" => table_name = option_value(args, &mut i)?,
+            "--keys" => {
+                keys = option_value(args, &mut i)?
+                    .split(',')
+                    .map(|key| key.trim().to_string())
+                    .filter(|key| !key.is_empty())
+                    .collect()
+            }
+            "--format" => export_format = option_value(args, &mut i)?.parse::<ExportFormat>()?,
+            "--output" | "-o" => output = Some(PathBuf::from(option_value(args, &mut i)?)),
+            "--name" => name = Some(option_value(args, &mut i)?),
+            "--namespace" => namespace = Some(option_value(args, &mut i)?),
+            arg => {
+                return Err(VaultError::InvalidArgument(format!(
+                    "Unknown argument for 'export': {}",
+                    arg
+                )))
+            }
+        }
+        i += 1;
+    }
+    if table_name.is_empty() {
+        return Err(VaultError::InvalidArgument(
+            "'export' requires --table".to_string(),
+        ));
+    }
+    if export_format != ExportFormat::K8sSecret && (name.is_some() || namespace.is_some()) {
+        return Err(VaultError::InvalidArgument(
+            "--name and --namespace are only supported with the k8s-secret format".to_string(),
+        ));
+    }
+    Ok(Command::Export {
+        table_name,
+        keys,
+        export_format,
+        output,
+        name,
+        namespace,
+    })
+}
+
+/// Parses the `copy` and `sync` subcommands.
+///
+/// # Arguments
+/// * `args` - Commandline arguments, starting with the subcommand.
+///
+/// # Returns
+/// * A `Result` with the parsed `Command`.
+fn copy_command(args: &[String]) -> Result<Command, VaultError> {
+    let command = args[0].as_str();
+    let mut from = None;
+    let mut to = None;
+    let mut keys = Vec::new();
+    let mut create_table = false;
+    let mut dry_run = false;
+    let mut prune = false;
+    let mut chunk_size = IMPORT_CHUNK_SIZE;
+    let mut i = 1;
+    while i < args.len() {
+        match args[i].as_str() {
+            "--from" => from = Some(option_value(args, &mut i)?.parse::<Endpoint>()?),
+            "--to" => to = Some(option_value(args, &mut i)?.parse::<Endpoint>()?),
+            "--keys" if command == "copy" => {
+                keys = option_value(args, &mut i)?
+                    .split(',')
+                    .map(|key| key.trim().to_string())
+                    .filter(|key| !key.is_empty())
+                    .collect()
+            }
+            "--prune" if command == "sync" => prune = true,
+            "--create-table" => create_table = true,
+            "--dry-run" => dry_run = true,
+            "--chunk-size" => chunk_size = chunk_size_value(args, &mut i)?,
+            arg => {
+                return Err(VaultError::InvalidArgument(format!(
+                    "Unknown argument for '{}': {}",
+                    command, arg
+                )))
+            }
+        }
+        i += 1;
+    }
+    match (from, to) {
+        (Some(from), Some(to)) => Ok(Command::Copy {
+            from,
+            to,
+            keys,
+            create_table,
+            dry_run,
+            prune,
+            chunk_size,
+        }),
+        _ => Err(VaultError::InvalidArgument(format!(
+            "'{}' requires --from and --to",
+            command
+        ))),
+    }
+}
+
+/// Parses the `login` and `logout` subcommands.
+///
+/// # Arguments
+/// * `args` - Commandline arguments, starting with the subcommand.
+///
+/// # Returns
+/// * A `Result` with the parsed `Command`.
+fn login_command(args: &[String]) -> Result<Command, VaultError> {
+    let mut stdin = false;
+    let mut store = Store::Keyring;
+    for arg in &args[1..] {
+        match (args[0].as_str(), arg.as_str()) {
+            ("login", "--stdin") => stdin = true,
+            ("login", "--file") => store = Store::File,
+            (command, arg) => {
+                return Err(VaultError::InvalidArgument(format!(
+                    "Unknown argument for '{}': {}",
+                    command, arg
+                )))
+            }
+        }
+    }
+    match args[0].as_str() {
+        "login" => Ok(Command::Login { stdin, store }),
+        _ => Ok(Command::Logout),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn credentials_are_resolved_as_a_pair() {
+        let value = |value: &str| Some(Secret::from(value));
+        let paired = credential_pair("profile", value("key"), value("secret"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(paired.apikey.expose(), "key");
+        assert_eq!(paired.secret.expose(), "secret");
+        assert!(credential_pair("profile", None, value(""))
+            .unwrap()
+            .is_none());
+        for (apikey, secret) in [(value("key"), None), (value(""), value("secret"))] {
+            assert!(matches!(
+                credential_pair("environment", apikey, secret),
+                Err(VaultError::MissingConfig(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn large_bucket_tolerances_are_rejected() {
+        // Only the profile is read, so the env vars don't affect this test
+        let config = |tolerance: u64| {
+            let profile = Profile {
+                vault_server: Some("http://127.0.0.1:8080".to_string()),
+                transit_bucket_tolerance: Some(tolerance),
+                ..Profile::default()
+            };
+            let credentials = Credentials {
+                apikey: Secret::from("key"),
+                secret: Secret::from("secret"),
+            };
+            env_config_with(profile, Precedence::ProfileOnly, credentials)
+        };
+        let max = decipher::MAX_BUCKET_TOLERANCE;
+        assert_eq!(config(max).unwrap().transit_bucket_tolerance, max);
+        for tolerance in [max + 1, u64::MAX] {
+            match config(tolerance) {
+                Err(VaultError::MissingConfig(message)) => {
+                    assert!(message.contains("TRANSIT_BUCKET_TOLERANCE"))
+                }
+                Err(err) => panic!("expected a config error, got {:?}", err),
+                Ok(_) => panic!("expected the tolerance {} to be rejected", tolerance),
+            }
+        }
+    }
+
+    /// Converts the arguments into the `Vec<String>` that the parsers expect.
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn write_subcommands_are_parsed() {
+        let command = subcommand(&args(&["get", "--table", "production", "A", "B"])).unwrap();
+        assert!(matches!(
+            command,
+            Command::Get { table_name, keys } if table_name == "production" && keys == ["A", "B"]
+        ));
+        let command =
+            subcommand(&args(&["put", "--table", "t", "A=1", "URL=a=b", "EMPTY="])).unwrap();
+        match command {
+            Command::Put {
+                table_name,
+                secrets,
+            } => {
+                assert_eq!(table_name, "t");
+                assert_eq!(secrets["A"], "1");
+                // Only the first `=` separates the key, so the values may contain `=`
+                assert_eq!(secrets["URL"], "a=b");
+                assert_eq!(secrets["EMPTY"], "");
+            }
+            other => panic!("expected a put command, got {:?}", other),
+        }
+        assert!(matches!(
+            subcommand(&args(&["delete", "--table", "t", "A"])).unwrap(),
+            Command::Delete { table_name, key } if table_name == "t" && key == "A"
+        ));
+        assert!(matches!(
+            subcommand(&args(&["tables", "delete", "t"])).unwrap(),
+            Command::DeleteTable { table_name } if table_name == "t"
+        ));
+        for invalid in [
+            &["get", "A"][..],
+            &["put", "--table", "t"],
+            &["put", "--table", "t", "A"],
+            &["put", "--table", "t", "=1"],
+            &["delete", "--table", "t"],
+            &["delete", "--table", "t", "A", "B"],
+            &["delete", "--table", "t", "--force", "A"],
+            &["tables", "create"],
+        ] {
+            assert!(matches!(
+                subcommand(&args(invalid)),
+                Err(VaultError::InvalidArgument(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn exec_command_is_parsed() {
+        let command = exec_command(&args(&[
+            "exec",
+            "--table",
+            "production",
+            "--keys",
+            "A, B,,",
+            "--prefix",
+            "APP_",
+            "--no-override",
+            "--",
+            "env",
+            "--table",
+        ]))
+        .unwrap();
+        assert!(matches!(
+            command,
+            Command::Exec { table_name, keys, prefix, override_env: false, program }
+                if table_name == "production"
+                    && keys == ["A", "B"]
+                    && prefix == "APP_"
+                    && program == ["env", "--table"]
+        ));
+        // The command starts at the first positional argument, without `--`
+        let command = exec_command(&args(&["exec", "--table", "t", "printenv", "A"])).unwrap();
+        assert!(matches!(
+            command,
+            Command::Exec { override_env: true, program, .. } if program == ["printenv", "A"]
+        ));
+        for invalid in [
+            &["exec", "--table", "t"][..],
+            &["exec", "--table", "t", "--"],
+            &["exec", "--", "env"],
+            &["exec", "--table", "t", "--unknown", "env"],
+            &["exec", "--table"],
+        ] {
+            assert!(matches!(
+                exec_command(&args(invalid)),
+                Err(VaultError::InvalidArgument(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn selected_profile_overrides_env_vars() {
+        let env_value = || Some("env");
+        assert_eq!(
+            layer(Precedence::Profile, env_value, Some("profile")),
+            Some("profile")
+        );
+        assert_eq!(layer(Precedence::Profile, env_value, None), Some("env"));
+        assert_eq!(
+            layer(Precedence::Environment, env_value, Some("profile")),
+            Some("env")
+        );
+        assert_eq!(
+            layer(Precedence::Environment, || None, Some("profile")),
+            Some("profile")
+        );
+        assert_eq!(layer(Precedence::ProfileOnly, env_value, None), None);
     }
 }
@@ -0,0 +1,321 @@
+use crate::error::VaultError;
+use crate::secret::Secret;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Settings of a named profile in the config file.
+///
+/// Every setting is optional. The corresponding environment variables override the `default_profile`,
+/// and are overridden by a profile selected with `--profile` or `VAULTAPI_PROFILE`.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    pub vault_server: Option<String>,
+    pub apikey: Option<Secret<String>>,
+    /// File with the API key, as an alternative to storing it in the config file.
+    pub apikey_file: Option<PathBuf>,
+    pub secret: Option<Secret<String>>,
+    /// File with the secret, as an alternative to storing it in the config file.
+    pub secret_file: Option<PathBuf>,
+    pub transit_key_length: Option<usize>,
+    pub transit_time_bucket: Option<u64>,
+    pub transit_bucket_tolerance: Option<u64>,
+    pub cache_ttl: Option<u64>,
+    pub offline_cache: Option<PathBuf>,
+    pub offline_max_age: Option<u64>,
+    pub retry_attempts: Option<u32>,
+    /// Delay before the first retry, in milliseconds.
+    pub retry_base_delay: Option<u64>,
+    /// Upper limit for the delay between the attempts, in milliseconds.
+    pub retry_max_delay: Option<u64>,
+    /// Timeout in seconds for each request, `0` to disable.
+    pub timeout: Option<f64>,
+    /// Timeout in seconds to connect to the server, `0` to disable.
+    pub connect_timeout: Option<f64>,
+    pub proxy: Option<String>,
+    pub no_proxy: Option<String>,
+    pub ca_cert: Option<PathBuf>,
+    pub client_cert: Option<PathBuf>,
+    pub client_key: Option<PathBuf>,
+    pub client_cert_password: Option<Secret<String>>,
+    pub pinned_spki: Option<Vec<String>>,
+    pub insecure: Option<bool>,
+}
+
+/// Contents of the config file.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConfigFile {
+    /// Profile to use when none is selected with `--profile` or `VAULTAPI_PROFILE`.
+    default_profile: Option<String>,
+    #[serde(default)]
+    profiles: HashMap<String, Profile>,
+}
+
+/// Returns the path to the config file.
+///
+/// # Returns
+/// * An `Option` with `VAULTAPI_CONFIG` if set, otherwise `~/.config/vaultapi/config.toml`.
+pub fn config_path() -> Option<PathBuf> {
+    if let Some(path) = std::env::var_os("VAULTAPI_CONFIG").filter(|path| !path.is_empty()) {
+        return Some(PathBuf::from(path));
+    }
+    home_dir().map(|home| home.join(".config").join("vaultapi").join("config.toml"))
+}
+
+/// Returns the home directory of the current user.
+fn home_dir() -> Option<PathBuf> {
+    std::env::var_os("HOME")
+        .or_else(|| std::env::var_os("USERPROFILE"))
+        .filter(|home| !home.is_empty())
+        .map(PathBuf::from)
+}
+
+/// Resolves a path from the config file, expanding `~` and relative to the config file's directory.
+fn resolve(path: &Path, config_dir: &Path) -> PathBuf {
+    if let Ok(relative) = path.strip_prefix("~") {
+        if let Some(home) = home_dir() {
+            return home.join(relative);
+        }
+    }
+    config_dir.join(path)
+}
+
+/// Reads a key file, without the trailing newline.
+fn read_key(path: &Path) -> Result<Secret<String>, VaultError> {
+    let contents = Secret::new(fs::read_to_string(path).map_err(|err| {
+        VaultError::MissingConfig(format!("Failed to read '{}': {}", path.display(), err))
+    })?);
+    Ok(Secret::from(
+        contents.expose().trim_end_matches(['\r', '\n']),
+    ))
+}
+
+impl Profile {
+    /// Resolves the paths in the profile and loads the key files.
+    fn resolve(mut self, config_dir: &Path) -> Result<Profile, VaultError> {
+        for path in [
+            &mut self.apikey_file,
+            &mut self.secret_file,
+            &mut self.offline_cache,
+            &mut self.ca_cert,
+            &mut self.client_cert,
+            &mut self.client_key,
+        ]
+        .into_iter()
+        .flatten()
+        {
+            *path = resolve(path, config_dir);
+        }
+        if self.apikey.is_none() {
+            self.apikey = self.apikey_file.as_deref().map(read_key).transpose()?;
+        }
+        if self.secret.is_none() {
+            self.secret = self.secret_file.as_deref().map(read_key).transpose()?;
+        }
+        Ok(self)
+    }
+}
+
+/// Loads a profile from the config file.
+///
+/// # Arguments
+/// * `path` - Path to the config file.
+/// * `name` - Profile name, or `None` to use the file's `default_profile`.
+///
+/// # Returns
+/// * A `Result` with the profile, which is empty if no profile is selected.
+pub fn load(path: &Path, name: Option<&str>) -> Result<Profile, VaultError> {
+    let config_file = match fs::read_to_string(path) {
+        // Profiles may hold the credentials, so the contents are wiped after parsing
+        Ok(contents) => {
+            toml::from_str::<ConfigFile>(Secret::new(contents).expose()).map_err(|err| {
+                VaultError::MissingConfig(format!(
+                    "Invalid config file '{}': {}",
+                    path.display(),
+                    err
+                ))
+            })?
+        }
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound && name.is_none() => {
+            return Ok(Profile::default())
+        }
+        Err(err) => {
+            return Err(VaultError::MissingConfig(format!(
+                "Failed to read '{}': {}",
+                path.display(),
+                err
+            )))
+        }
+    };
+    let name = match name.or(config_file.default_profile.as_deref()) {
+        Some(name) => name,
+        None => return Ok(Profile::default()),
+    };
+    match config_file.profiles.get(name) {
+        Some(profile) => profile
+            .clone()
+            .resolve(path.parent().unwrap_or(Path::new(""))),
+        None => Err(VaultError::MissingConfig(format!(
+            "Profile '{}' not found in '{}'",
+            name,
+            path.display()
+        ))),
+    }
+}
+
+/// Returns the name of the profile selected with `--profile` or `VAULTAPI_PROFILE`
+///
+/// # Returns
+/// * An `Option` with the profile name, or `None` to use the config file's `default_profile`.
+pub fn selected_name() -> Option<String> {
+    std::env::var("VAULTAPI_PROFILE")
+        .ok()
+        .filter(|name| !name.is_empty())
+}
+
+/// Loads the selected profile, or the default profile.
+///
+/// # Arguments
+/// * `name` - Profile selected with `--profile` or `VAULTAPI_PROFILE`, or `None` for the default profile.
+///
+/// # Returns
+/// * A `Result` with the selected profile, which is empty if there is no config file.
+pub fn selected(name: Option<&str>) -> Result<Profile, VaultError> {
+    match config_path() {
+        Some(path) => load(&path, name),
+        None if name.is_some() => Err(VaultError::MissingConfig(
+            "Unable to locate the config file, set VAULTAPI_CONFIG".to_string(),
+        )),
+        None => Ok(Profile::default()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Precedence;
+
+    /// Creates an empty directory for a test, under the system's temporary directory.
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("vaultapi-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn profile_is_selected_by_name() {
+        let dir = temp_dir("profile-selection");
+        let path = dir.join("config.toml");
+        fs::write(
+            &path,
+            r#"
+default_profile = "production"
+
+[profiles.production]
+vault_server = "https://production.example.com"
+
+[profiles.staging]
+vault_server = "https://staging.example.com"
+
+[profiles.dev]
+vault_server = "http://localhost:8080"
+"#,
+        )
+        .unwrap();
+        // Only this test reads these env vars, so they are not shared with other tests
+        std::env::set_var("VAULTAPI_CONFIG", &path);
+        let server = |name: Option<&str>| selected(name).unwrap().vault_server.unwrap();
+
+        std::env::remove_var("VAULTAPI_PROFILE");
+        assert_eq!(selected_name(), None);
+        assert_eq!(server(None), "https://production.example.com");
+        // Env vars override the default profile's settings
+        assert_eq!(Precedence::selected(None), Precedence::Environment);
+
+        std::env::set_var("VAULTAPI_PROFILE", "staging");
+        let name = selected_name();
+        assert_eq!(server(name.as_deref()), "https://staging.example.com");
+        // The selected profile's settings override the env vars
+        assert_eq!(Precedence::selected(name.as_deref()), Precedence::Profile);
+
+        // `--profile` is passed explicitly, so it takes precedence over `VAULTAPI_PROFILE`
+        assert_eq!(server(Some("dev")), "http://localhost:8080");
+        assert!(matches!(
+            selected(Some("missing")),
+            Err(VaultError::MissingConfig(_))
+        ));
+
+        std::env::remove_var("VAULTAPI_PROFILE");
+        std::env::remove_var("VAULTAPI_CONFIG");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn key_files_are_read_without_the_trailing_newline() {
+        let dir = temp_dir("profile-keys");
+        fs::write(dir.join("apikey"), "key with spaces \r\n").unwrap();
+        fs::write(dir.join("secret"), "secret\n").unwrap();
+        let path = dir.join("config.toml");
+        fs::write(
+            &path,
+            r#"
+[profiles.files]
+apikey_file = "apikey"
+secret_file = "secret"
+
+[profiles.inline]
+apikey = "inline"
+apikey_file = "missing"
+"#,
+        )
+        .unwrap();
+        let profile = load(&path, Some("files")).unwrap();
+        assert_eq!(profile.apikey.unwrap().expose(), "key with spaces ");
+        assert_eq!(profile.secret.unwrap().expose(), "secret");
+        // Keys set in the config file take precedence, so the key file is never read
+        let profile = load(&path, Some("inline")).unwrap();
+        assert_eq!(profile.apikey.unwrap().expose(), "inline");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn paths_are_resolved_from_the_config_directory() {
+        let config_dir = Path::new("/etc/vaultapi");
+        assert_eq!(
+            resolve(Path::new("certs/ca.pem"), config_dir),
+            PathBuf::from("/etc/vaultapi/certs/ca.pem")
+        );
+        assert_eq!(
+            resolve(Path::new("/opt/ca.pem"), config_dir),
+            PathBuf::from("/opt/ca.pem")
+        );
+        if let Some(home) = home_dir() {
+            assert_eq!(
+                resolve(Path::new("~/certs/ca.pem"), config_dir),
+                home.join("certs/ca.pem")
+            );
+        }
+    }
+
+    #[test]
+    fn unknown_fields_are_rejected() {
+        let dir = temp_dir("profile-fields");
+        let path = dir.join("config.toml");
+        fs::write(
+            &path,
+            "[profiles.typo]\nvault_sever = \"http://localhost\"\n",
+        )
+        .unwrap();
+        match load(&path, Some("typo")) {
+            Err(VaultError::MissingConfig(message)) => assert!(message.contains("vault_sever")),
+            other => panic!("expected an error for the unknown field, got {:?}", other),
+        }
+        fs::write(&path, "default = \"typo\"\n").unwrap();
+        assert!(load(&path, None).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
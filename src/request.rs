@@ -1,18 +1,24 @@
+use crate::client::VaultClient;
 use crate::decipher;
 use crate::enums::Method;
+use crate::error::VaultError;
 use crate::parser::ArgConfig;
 use crate::parser::EnvConfig;
+use crate::retry::{self, RetryPolicy};
+use crate::secret::Secret;
 use reqwest::blocking::Client;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
 use serde_json::Value;
 use std::collections::HashMap;
-use std::process::exit;
+use std::time::Duration;
 
 pub struct PreparedRequest {
     pub url: String,
     pub method: Method,
     pub params: HashMap<String, String>,
     pub payload: HashMap<String, Value>,
-    pub headers: HashMap<String, String>,
+    /// Header values, which are wiped from memory when the request is dropped.
+    pub headers: HashMap<String, Secret<String>>,
 }
 
 /// Constructs authentication headers.
@@ -21,183 +27,289 @@ pub struct PreparedRequest {
 /// * `apikey` - APIkey to authenticate the server.
 ///
 /// # Returns
-/// * A `HashMap<String, String>` containing auth headers.
-pub fn auth_headers(apikey: &String) -> HashMap<String, String> {
+/// * A `HashMap<String, Secret<String>>` containing auth headers.
+pub fn auth_headers(apikey: &str) -> HashMap<String, Secret<String>> {
     let mut headers = HashMap::new();
-    let bearer = format!("Bearer {}", apikey);
+    let bearer = Secret::concat(&["Bearer ", apikey]);
     headers.insert("Authorization".to_string(), bearer);
-    headers.insert("Accept".to_string(), "application/json".to_string());
+    headers.insert("Accept".to_string(), Secret::from("application/json"));
     headers
 }
 
-/// Constructs the required fields to make a request.
+/// Converts the headers of a prepared request into a header map.
+///
+/// The values are marked as sensitive, so that the HTTP client never includes them in its debug output.
 ///
 /// # Arguments
-/// * `env_config` - Config object to retrieve environment variables.
-/// * `arg_config` - Config object to retrieve CLI arguments.
+/// * `prepared_request` - Prepared request object.
 ///
 /// # Returns
-/// * A `RequestMaterials` struct containing auth headers, query parameters, and the request URL.
-fn create_request_materials(arg_config: &ArgConfig, env_config: &EnvConfig) -> PreparedRequest {
-    // Add URL parameters
-    let mut url = String::new();
-    let mut params = HashMap::new();
-
-    if !arg_config.table_name.is_empty() {
-        params.insert("table_name".to_string(), arg_config.table_name.to_string());
-    } else if !arg_config.get_table.is_empty() {
-        params.insert("table_name".to_string(), arg_config.get_table.to_string());
-    } else {
-        println!("Table name is mandatory to retrieve the secret");
-        exit(1)
-    }
-
-    if !arg_config.get_secrets.is_empty() {
-        url = format!("{}get-secrets", &env_config.vault_server);
-        params.insert("keys".to_string(), arg_config.get_secrets.to_string());
-    } else if !arg_config.get_secret.is_empty() {
-        url = format!("{}get-secret", &env_config.vault_server);
-        params.insert("key".to_string(), arg_config.get_secret.to_string());
-    } else if !arg_config.get_table.is_empty() {
-        url = format!("{}get-table", &env_config.vault_server);
-    } else if arg_config.table_name.is_empty() {
-        println!("Required parameters unfilled!");
-        exit(1)
-    }
-    PreparedRequest {
-        url,
-        method: Method::Get,
-        params,
-        payload: HashMap::new(),
-        headers: auth_headers(&env_config.apikey),
+/// * A `Result` with the `HeaderMap` object.
+fn request_headers(prepared_request: &PreparedRequest) -> Result<HeaderMap, VaultError> {
+    let mut headers = HeaderMap::new();
+    for (key, value) in &prepared_request.headers {
+        let name = HeaderName::from_bytes(key.as_bytes())
+            .map_err(|_| VaultError::MissingConfig(format!("Invalid header name '{}'", key)))?;
+        let mut value = HeaderValue::from_str(value.expose()).map_err(|_| {
+            VaultError::MissingConfig(format!("Invalid value for header '{}'", key))
+        })?;
+        value.set_sensitive(true);
+        headers.insert(name, value);
     }
+    Ok(headers)
 }
 
 /// Process the response from the server's detail object and decrypt the response text.
 ///
 /// # Arguments
 /// * `env_config` - Config object to retrieve environment variables.
-/// * `arg_config` - Config object to retrieve CLI arguments.
+/// * `response` - Detail object from the server's response.
 ///
 /// # Returns
-/// * A `Result<Value, String>` containing deciphered content.
-pub fn decrypt_response(env_config: &EnvConfig, response: &Value) -> Result<Value, String> {
+/// * A `Result<Value, VaultError>` containing deciphered content.
+pub fn decrypt_response(env_config: &EnvConfig, response: &Value) -> Result<Value, VaultError> {
     // Check if the result is the expected "detail" field, or handle accordingly
     match response {
-        Value::Null => {
-            println!("No 'detail' key found in the response.");
-            exit(1)
-        }
-        Value::String(cipher_text) => {
-            return decipher::transit_decrypt(
-                &env_config.apikey,
-                &env_config.secret,
-                cipher_text,
-                env_config.transit_key_length,
-                env_config.transit_time_bucket,
-            )
-        }
-        Value::Object(obj) => {
-            println!("Detail is an object: {:?}", obj);
-        }
-        _ => {
-            println!("Unexpected value returned: {:?}", response);
-        }
+        Value::Null => Err(VaultError::MalformedResponse(
+            "No 'detail' key found in the response.".to_string(),
+        )),
+        Value::String(cipher_text) => decipher::transit_decrypt(
+            env_config.apikey.expose(),
+            env_config.secret.expose(),
+            cipher_text,
+            env_config.transit_key_length,
+            env_config.transit_time_bucket,
+            env_config.transit_bucket_tolerance,
+        ),
+        Value::Object(obj) => Err(VaultError::MalformedResponse(format!(
+            "Detail is an object: {:?}",
+            obj
+        ))),
+        _ => Err(VaultError::MalformedResponse(format!(
+            "Unexpected value returned: {:?}",
+            response
+        ))),
     }
-    exit(1)
 }
 
-/// Function to create a server request and process the response.
+/// Function to retrieve the secrets requested with the CLI arguments.
 ///
 /// # Arguments
-/// * `env_config` - Config object to retrieve environment variables.
 /// * `arg_config` - Config object to retrieve CLI arguments.
+/// * `vault_client` - Client object to make requests to the server.
 ///
 /// # Returns
-/// * A `Result<Value, String>` containing deciphered content.
-pub fn server_connection(arg_config: &ArgConfig, env_config: &EnvConfig) -> Result<Value, String> {
-    let prepared_request = create_request_materials(arg_config, env_config);
-    let response = make_request(prepared_request);
-    decrypt_response(env_config, &response)
+/// * A `Result<Value, VaultError>` containing deciphered content.
+pub fn server_connection(
+    arg_config: &ArgConfig,
+    vault_client: &VaultClient,
+) -> Result<Value, VaultError> {
+    let table_name = if !arg_config.table_name.is_empty() {
+        &arg_config.table_name
+    } else if !arg_config.get_table.is_empty() {
+        &arg_config.get_table
+    } else {
+        return Err(VaultError::InvalidArgument(
+            "Table name is mandatory to retrieve the secret".to_string(),
+        ));
+    };
+
+    if !arg_config.get_secrets.is_empty() {
+        let keys: Vec<&str> = arg_config.get_secrets.split(',').map(str::trim).collect();
+        let secrets = vault_client.get_secrets(&keys, table_name)?;
+        Ok(Value::Object(
+            secrets
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.expose().clone()))
+                .collect(),
+        ))
+    } else if !arg_config.get_secret.is_empty() {
+        let secret = vault_client.get_secret(&arg_config.get_secret, table_name)?;
+        Ok(secret.expose().clone())
+    } else if !arg_config.get_table.is_empty() {
+        Ok(vault_client.get_table(table_name)?.expose().clone())
+    } else {
+        Err(VaultError::InvalidArgument(
+            "Required parameters unfilled!".to_string(),
+        ))
+    }
 }
 
-/// Function to make a `GET` request to the server.
+/// Converts an unsuccessful status code into a `VaultError`, along with the server's detail message.
 ///
 /// # Arguments
-/// * `prepared_request` - Prepared request object.
+/// * `status` - Unsuccessful status code returned by the server.
+/// * `body` - JSON body from the server's response, if any.
 ///
 /// # Returns
-/// * A `Value` object containing the server response.
-pub fn make_request(prepared_request: PreparedRequest) -> Value {
-    // Create a reqwest client
-    let client = Client::new();
+/// * A `VaultError` object.
+pub fn status_error(status: reqwest::StatusCode, body: Option<Value>) -> VaultError {
+    let code = status.as_u16();
+    let reason = status.canonical_reason().unwrap_or_default().to_string();
+    let detail = match body.as_ref().and_then(|json| json.get("detail")) {
+        Some(Value::String(detail)) => detail.to_string(),
+        Some(detail) => detail.to_string(),
+        None => reason,
+    };
+    if code == 401 || code == 403 {
+        return VaultError::Unauthorized(detail);
+    }
+    VaultError::Status { code, detail }
+}
 
-    // Build the URL with parameters if provided
+/// Constructs the request URL along with the query parameters.
+///
+/// # Arguments
+/// * `prepared_request` - Prepared request object.
+///
+/// # Returns
+/// * A `Result` with the `Url` object.
+pub fn request_url(prepared_request: &PreparedRequest) -> Result<reqwest::Url, VaultError> {
     let mut url = match reqwest::Url::parse(&prepared_request.url) {
         Ok(url) => url,
         Err(_) => {
-            eprintln!("Invalid URL: {}", prepared_request.url);
-            exit(1)
+            return Err(VaultError::MissingConfig(format!(
+                "Invalid URL: {}",
+                prepared_request.url
+            )))
         }
     };
     if !prepared_request.params.is_empty() {
-        let query: Vec<(String, String)> = prepared_request.params.into_iter().collect();
-        url.query_pairs_mut().extend_pairs(query);
+        url.query_pairs_mut()
+            .extend_pairs(prepared_request.params.iter());
     }
+    Ok(url)
+}
 
-    // Prepare the request builder
-    let mut request = match prepared_request.method {
-        Method::Get => client.get(url),
-        Method::Post => client.post(url),
-        Method::Put => client.put(url),
-        Method::Delete => client.delete(url),
-    };
-
-    // Add headers if provided
-    if !prepared_request.headers.is_empty() {
-        for (key, value) in prepared_request.headers {
-            request = request.header(&key, value);
-        }
+/// Extracts the "detail" object from the server's response.
+///
+/// # Arguments
+/// * `json` - Parsed JSON response from the server.
+///
+/// # Returns
+/// * A `Result` with the detail `Value`, or null if the "detail" key is not present.
+pub fn response_detail(json: Result<Value, reqwest::Error>) -> Result<Value, VaultError> {
+    match json {
+        Ok(json) => Ok(json.get("detail").cloned().unwrap_or(Value::Null)),
+        Err(err) => Err(VaultError::MalformedResponse(format!(
+            "Failed to parse response as JSON: {}",
+            err
+        ))),
     }
+}
 
-    // Add JSON payload if provided
-    if !prepared_request.payload.is_empty() {
-        request = request.json(&prepared_request.payload)
-    }
+/// Error from a single attempt, along with the delay requested by the server with `Retry-After`.
+type AttemptError = (VaultError, Option<Duration>);
 
-    // Make the request
-    match request.send() {
-        Ok(init_response) => {
-            match init_response.error_for_status() {
-                Ok(response) => {
-                    match response.json::<Value>() {
-                        Ok(json) => {
-                            // Try to get the value of "detail" if it exists
-                            if let Some(detail) = json.get("detail") {
-                                detail.clone()
-                            } else {
-                                // Return null if "detail" key is not present
-                                Value::Null
-                            }
-                        }
-                        Err(err) => {
-                            println!("Failed to parse response as JSON: {}", err);
-                            exit(1);
-                        }
-                    }
-                }
-                Err(err) => {
-                    println!("Server response: {}", err);
-                    exit(1)
-                }
-            }
+/// Makes a single attempt of the request, and converts the response into the server's detail object.
+///
+/// Blocking and async clients have different types with the same methods, so this is a macro,
+/// that appends `.await` to the calls that return a future when the `await` token is given.
+macro_rules! attempt {
+    ($client:expr, $prepared_request:expr, $url:expr, $headers:expr $(, $await:tt)?) => {{
+        let prepared_request: &PreparedRequest = $prepared_request;
+        // Prepare the request builder
+        let mut request = match prepared_request.method {
+            Method::Get => $client.get($url),
+            Method::Post => $client.post($url),
+            Method::Put => $client.put($url),
+            Method::Delete => $client.delete($url),
+        };
+
+        // Add headers if provided
+        request = request.headers($headers);
+
+        // Add JSON payload if provided
+        if !prepared_request.payload.is_empty() {
+            request = request.json(&prepared_request.payload)
         }
-        Err(err) => {
-            println!(
-                "Failed to fetch data from {}: {}",
-                prepared_request.url, err
-            );
-            exit(1);
+
+        // Make the request
+        match request.send()$(.$await)? {
+            Err(err) => Err((
+                VaultError::Transport(format!(
+                    "Failed to fetch data from {}: {}",
+                    prepared_request.url, err
+                )),
+                None,
+            )),
+            Ok(response) if !response.status().is_success() => {
+                let status = response.status();
+                let retry_after = retry::retry_after(response.headers());
+                Err((
+                    status_error(status, response.json::<Value>()$(.$await)?.ok()),
+                    retry_after,
+                ))
+            }
+            Ok(response) => {
+                response_detail(response.json::<Value>()$(.$await)?).map_err(|err| (err, None))
+            }
         }
+    }};
+}
+
+/// Function to make a request to the server, retrying the transient failures as per the retry policy.
+///
+/// # Arguments
+/// * `client` - Reusable HTTP client.
+/// * `prepared_request` - Prepared request object.
+/// * `retry_policy` - Policy to retry the failed attempts.
+///
+/// # Returns
+/// * A `Result<Value, VaultError>` containing the server response.
+pub fn make_request(
+    client: &Client,
+    prepared_request: PreparedRequest,
+    retry_policy: &RetryPolicy,
+) -> Result<Value, VaultError> {
+    let url = request_url(&prepared_request)?;
+    let headers = request_headers(&prepared_request)?;
+    let mut attempt = 1;
+    loop {
+        let result: Result<Value, AttemptError> =
+            attempt!(client, &prepared_request, url.clone(), headers.clone());
+        let (err, retry_after) = match result {
+            Ok(response) => return Ok(response),
+            Err(attempt_error) => attempt_error,
+        };
+        let delay = retry_policy.next_delay(&prepared_request.method, attempt, err, retry_after)?;
+        std::thread::sleep(delay);
+        attempt += 1;
+    }
+}
+
+/// Function to make a request to the server without blocking the async runtime.
+///
+/// # Arguments
+/// * `client` - Reusable async HTTP client.
+/// * `prepared_request` - Prepared request object.
+/// * `retry_policy` - Policy to retry the failed attempts.
+///
+/// # Returns
+/// * A `Result<Value, VaultError>` containing the server response.
+#[cfg(feature = "async")]
+pub async fn make_request_async(
+    client: &reqwest::Client,
+    prepared_request: PreparedRequest,
+    retry_policy: &RetryPolicy,
+) -> Result<Value, VaultError> {
+    let url = request_url(&prepared_request)?;
+    let headers = request_headers(&prepared_request)?;
+    let mut attempt = 1;
+    loop {
+        let result: Result<Value, AttemptError> = attempt!(
+            client,
+            &prepared_request,
+            url.clone(),
+            headers.clone(),
+            await
+        );
+        let (err, retry_after) = match result {
+            Ok(response) => return Ok(response),
+            Err(attempt_error) => attempt_error,
+        };
+        let delay = retry_policy.next_delay(&prepared_request.method, attempt, err, retry_after)?;
+        tokio::time::sleep(delay).await;
+        attempt += 1;
     }
 }
@@ -0,0 +1,251 @@
+use crate::enums::Method;
+use crate::error::VaultError;
+use ring::rand::{SecureRandom, SystemRandom};
+use std::time::Duration;
+
+/// Default number of attempts, including the first request.
+const MAX_ATTEMPTS: u32 = 3;
+/// Default delay before the first retry, in milliseconds.
+const BASE_DELAY: u64 = 200;
+/// Default upper limit for the delay between the attempts, in milliseconds.
+const MAX_DELAY: u64 = 5000;
+
+/// Policy to retry the requests that failed with a transient error, with exponential backoff and jitter.
+///
+/// Only the `GET` requests are retried by default, and `put_secret` only when `retry_put` is set.
+/// `DELETE` requests are never retried, since a retry after a lost response fails with `404`
+/// even though the first attempt succeeded.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// Maximum number of attempts, including the first request. `1` disables the retries.
+    pub max_attempts: u32,
+    /// Delay before the first retry, which doubles after each attempt.
+    pub base_delay: Duration,
+    /// Upper limit for the delay between the attempts, including the server's `Retry-After`.
+    pub max_delay: Duration,
+    /// Randomizes the delay between half and the full backoff, to avoid retrying in lockstep.
+    pub jitter: bool,
+    /// Status codes that are considered transient.
+    pub retryable_statuses: Vec<u16>,
+    /// Retries the `put_secret` requests as well.
+    pub retry_put: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: MAX_ATTEMPTS,
+            base_delay: Duration::from_millis(BASE_DELAY),
+            max_delay: Duration::from_millis(MAX_DELAY),
+            jitter: true,
+            retryable_statuses: vec![429, 502, 503, 504],
+            retry_put: false,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Creates a policy that makes exactly one attempt.
+    pub fn none() -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: 1,
+            ..RetryPolicy::default()
+        }
+    }
+
+    /// Checks if a failed request can be retried.
+    ///
+    /// # Arguments
+    /// * `method` - Request method.
+    /// * `attempt` - Number of attempts made so far.
+    /// * `err` - Error returned by the last attempt.
+    ///
+    /// # Returns
+    /// * A boolean flag indicating whether the request should be retried.
+    pub(crate) fn should_retry(&self, method: &Method, attempt: u32, err: &VaultError) -> bool {
+        if attempt >= self.max_attempts {
+            return false;
+        }
+        let idempotent = match method {
+            Method::Get => true,
+            Method::Put => self.retry_put,
+            Method::Delete | Method::Post => false,
+        };
+        idempotent
+            && match err {
+                VaultError::Transport(_) => true,
+                VaultError::Status { code, .. } => self.retryable_statuses.contains(code),
+                _ => false,
+            }
+    }
+
+    /// Decides whether a failed attempt is retried, and how long to wait before the next attempt.
+    ///
+    /// # Arguments
+    /// * `method` - Request method.
+    /// * `attempt` - Number of attempts made so far.
+    /// * `err` - Error returned by the last attempt.
+    /// * `retry_after` - Delay requested by the server with the `Retry-After` header.
+    ///
+    /// # Returns
+    /// * A `Result` with the delay before the next attempt, otherwise the error when it can't be retried.
+    pub(crate) fn next_delay(
+        &self,
+        method: &Method,
+        attempt: u32,
+        err: VaultError,
+        retry_after: Option<Duration>,
+    ) -> Result<Duration, VaultError> {
+        if !self.should_retry(method, attempt, &err) {
+            return Err(err);
+        }
+        Ok(self.delay(attempt, retry_after))
+    }
+
+    /// Computes the delay before the next attempt.
+    ///
+    /// # Arguments
+    /// * `attempt` - Number of attempts made so far.
+    /// * `retry_after` - Delay requested by the server with the `Retry-After` header.
+    ///
+    /// # Returns
+    /// * A `Duration` to wait before the next attempt.
+    pub(crate) fn delay(&self, attempt: u32, retry_after: Option<Duration>) -> Duration {
+        if let Some(retry_after) = retry_after {
+            return retry_after.min(self.max_delay);
+        }
+        let backoff = self
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)))
+            .min(self.max_delay);
+        if !self.jitter {
+            return backoff;
+        }
+        let mut random = [0u8; 8];
+        if SystemRandom::new().fill(&mut random).is_err() {
+            return backoff;
+        }
+        let half = backoff / 2;
+        let spread = half.as_millis() as u64 + 1;
+        half + Duration::from_millis(u64::from_le_bytes(random) % spread)
+    }
+}
+
+/// Parses the `Retry-After` header, when it is set as a number of seconds.
+///
+/// # Arguments
+/// * `headers` - Response headers.
+///
+/// # Returns
+/// * An `Option` with the requested delay.
+pub(crate) fn retry_after(headers: &reqwest::header::HeaderMap) -> Option<Duration> {
+    headers
+        .get(reqwest::header::RETRY_AFTER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse::<u64>().ok())
+        .map(Duration::from_secs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::{HeaderMap, HeaderValue, RETRY_AFTER};
+
+    /// Creates a policy without jitter, so that the delays are predictable.
+    fn policy() -> RetryPolicy {
+        RetryPolicy {
+            jitter: false,
+            ..RetryPolicy::default()
+        }
+    }
+
+    /// Creates an error with the given status code.
+    fn status(code: u16) -> VaultError {
+        VaultError::Status {
+            code,
+            detail: String::new(),
+        }
+    }
+
+    #[test]
+    fn delay_grows_exponentially_up_to_the_limit() {
+        let policy = policy();
+        let delays: Vec<u128> = (1..=7)
+            .map(|attempt| policy.delay(attempt, None).as_millis())
+            .collect();
+        assert_eq!(delays, vec![200, 400, 800, 1600, 3200, 5000, 5000]);
+        // Large attempt counts saturate instead of overflowing
+        assert_eq!(policy.delay(u32::MAX, None), policy.max_delay);
+    }
+
+    #[test]
+    fn jitter_stays_between_half_and_the_full_backoff() {
+        let jittered = RetryPolicy::default();
+        for attempt in 1..=6 {
+            let backoff = policy().delay(attempt, None);
+            for _ in 0..50 {
+                let delay = jittered.delay(attempt, None);
+                assert!(delay >= backoff / 2 && delay <= backoff, "{:?}", delay);
+            }
+        }
+    }
+
+    #[test]
+    fn retry_after_is_capped_at_the_max_delay() {
+        let policy = RetryPolicy::default();
+        assert_eq!(
+            policy.delay(1, Some(Duration::from_secs(2))),
+            Duration::from_secs(2)
+        );
+        assert_eq!(
+            policy.delay(1, Some(Duration::from_secs(3600))),
+            policy.max_delay
+        );
+        assert_eq!(policy.delay(3, Some(Duration::ZERO)), Duration::ZERO);
+
+        let mut headers = HeaderMap::new();
+        assert_eq!(retry_after(&headers), None);
+        headers.insert(RETRY_AFTER, HeaderValue::from_static(" 120 "));
+        assert_eq!(retry_after(&headers), Some(Duration::from_secs(120)));
+        // HTTP dates are not supported, so the backoff is used instead
+        headers.insert(
+            RETRY_AFTER,
+            HeaderValue::from_static("Wed, 21 Oct 2015 07:28:00 GMT"),
+        );
+        assert_eq!(retry_after(&headers), None);
+    }
+
+    #[test]
+    fn only_idempotent_requests_are_retried() {
+        let policy = policy();
+        let transport = VaultError::Transport("connection reset".to_string());
+        assert!(policy.should_retry(&Method::Get, 1, &transport));
+        assert!(policy.should_retry(&Method::Get, 2, &status(503)));
+        assert!(!policy.should_retry(&Method::Get, 3, &status(503)));
+        assert!(!policy.should_retry(&Method::Get, 1, &status(500)));
+        assert!(!policy.should_retry(&Method::Get, 1, &status(404)));
+        assert!(!policy.should_retry(&Method::Get, 1, &VaultError::Unauthorized(String::new())));
+        assert!(!policy.should_retry(&Method::Put, 1, &status(503)));
+        for method in [Method::Delete, Method::Post] {
+            assert!(!policy.should_retry(&method, 1, &transport));
+        }
+
+        let policy = RetryPolicy {
+            retry_put: true,
+            ..policy
+        };
+        assert!(policy.should_retry(&Method::Put, 1, &status(429)));
+        assert!(!policy.should_retry(&Method::Delete, 1, &status(429)));
+        assert!(!RetryPolicy::none().should_retry(&Method::Get, 1, &transport));
+        assert!(matches!(
+            policy.next_delay(&Method::Delete, 1, status(503), None),
+            Err(VaultError::Status { code: 503, .. })
+        ));
+        assert_eq!(
+            policy
+                .next_delay(&Method::Get, 1, status(503), None)
+                .unwrap(),
+            policy.base_delay
+        );
+    }
+}
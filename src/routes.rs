@@ -1,100 +1,202 @@
-use crate::enums::Method;
+use crate::client::VaultClient;
+use crate::enums::{EndpointMapping, Method};
+use crate::error::VaultError;
 use crate::parser::EnvConfig;
-use crate::{enums, parser, request, util};
+use crate::request::{auth_headers, PreparedRequest};
+use crate::secret::Secret;
+use crate::util;
+use serde::de::DeserializeOwned;
 use serde_json::{to_value, Value};
 use std::collections::HashMap;
-use std::process::exit;
+
+/// Constructs the full URL for an API endpoint.
+///
+/// # Arguments
+/// * `env_config` - Environment variables' configuration.
+/// * `endpoint` - Endpoint mapping enum.
+///
+/// # Returns
+/// * A `String` with the endpoint URL.
+pub(crate) fn endpoint_url(env_config: &EnvConfig, endpoint: EndpointMapping) -> String {
+    util::urljoin(&[env_config.vault_server.as_ref(), endpoint.as_str()])
+}
 
 /// Function to generate a table request, with just the table name as query param.
 ///
 /// # Arguments
-/// * `url` - API endpoint Url.
+/// * `env_config` - Environment variables' configuration.
+/// * `endpoint` - Endpoint mapping enum.
 /// * `method` - Method enum.
 /// * `table_name` - Table name.
-/// * `env_config` - Environment variables' configuration.
 ///
 /// # Returns
 /// * A `PreparedRequest` struct containing the request essentials.
 fn table_request(
-    url: String,
-    method: Method,
-    table_name: &String,
     env_config: &EnvConfig,
-) -> request::PreparedRequest {
-    request::PreparedRequest {
-        url: url.to_string(),
+    endpoint: EndpointMapping,
+    method: Method,
+    table_name: &str,
+) -> PreparedRequest {
+    PreparedRequest {
+        url: endpoint_url(env_config, endpoint),
         method,
         params: HashMap::from([("table_name".to_string(), table_name.to_string())]),
         payload: HashMap::new(),
-        headers: request::auth_headers(&env_config.apikey),
+        headers: auth_headers(env_config.apikey.expose()),
     }
 }
 
-/// Retrieve secret(s) from the server.
-///
-/// # Arguments
-/// * `key` - Secret key for the which the value has to be retrieved.
-/// * `table_name` - Table name where the secret is stored.
-///
-/// # Returns
-/// * A `Result<Value, String>` object with decrypted payload.
-pub fn get_secret(key: &String, table_name: &String) -> Result<Value, String> {
-    let env_config = parser::env_variables();
-    let url = util::urljoin(&[
-        env_config.vault_server.as_ref(),
-        enums::EndpointMapping::GetSecret.as_str(),
-    ]);
+/// Prepares the request to retrieve a secret.
+pub(crate) fn get_secret_request(
+    env_config: &EnvConfig,
+    key: &str,
+    table_name: &str,
+) -> PreparedRequest {
     let params = HashMap::from([
         ("table_name".to_string(), table_name.to_string()),
         ("key".to_string(), key.to_string()),
     ]);
-    let request = request::PreparedRequest {
-        url,
+    PreparedRequest {
+        url: endpoint_url(env_config, EndpointMapping::GetSecret),
         method: Method::Get,
         params,
         payload: HashMap::new(),
-        headers: request::auth_headers(&env_config.apikey),
-    };
-    let response = request::make_request(request);
-    request::decrypt_response(&env_config, &response)
+        headers: auth_headers(env_config.apikey.expose()),
+    }
 }
 
-/// Retrieve ALL the secrets stored in a particular table from the server.
+/// Prepares the request to retrieve multiple secrets.
+pub(crate) fn get_secrets_request(
+    env_config: &EnvConfig,
+    keys: &[&str],
+    table_name: &str,
+) -> PreparedRequest {
+    let params = HashMap::from([
+        ("table_name".to_string(), table_name.to_string()),
+        ("keys".to_string(), keys.join(",")),
+    ]);
+    PreparedRequest {
+        url: endpoint_url(env_config, EndpointMapping::GetSecrets),
+        method: Method::Get,
+        params,
+        payload: HashMap::new(),
+        headers: auth_headers(env_config.apikey.expose()),
+    }
+}
+
+/// Prepares the request to retrieve all the secrets in a table.
+pub(crate) fn get_table_request(env_config: &EnvConfig, table_name: &str) -> PreparedRequest {
+    table_request(
+        env_config,
+        EndpointMapping::GetTable,
+        Method::Get,
+        table_name,
+    )
+}
+
+/// Prepares the request to list all the table names.
+pub(crate) fn list_tables_request(env_config: &EnvConfig) -> PreparedRequest {
+    PreparedRequest {
+        url: endpoint_url(env_config, EndpointMapping::ListTables),
+        method: Method::Get,
+        params: HashMap::new(),
+        payload: HashMap::new(),
+        headers: auth_headers(env_config.apikey.expose()),
+    }
+}
+
+/// Prepares the request to create or update secrets in a table.
+pub(crate) fn put_secret_request(
+    env_config: &EnvConfig,
+    secrets: &HashMap<String, String>,
+    table_name: &str,
+) -> Result<PreparedRequest, VaultError> {
+    let sec: Value =
+        to_value(secrets.to_owned()).map_err(|err| VaultError::InvalidArgument(err.to_string()))?;
+    let mut payload = HashMap::new();
+    payload.insert("secrets".to_string(), sec);
+    payload.insert(
+        "table_name".to_string(),
+        Value::String(table_name.to_string()),
+    );
+    Ok(PreparedRequest {
+        url: endpoint_url(env_config, EndpointMapping::PutSecret),
+        method: Method::Put,
+        params: HashMap::new(),
+        payload,
+        headers: auth_headers(env_config.apikey.expose()),
+    })
+}
+
+/// Prepares the request to delete a secret from a table.
+pub(crate) fn delete_secret_request(
+    env_config: &EnvConfig,
+    key: &str,
+    table_name: &str,
+) -> PreparedRequest {
+    PreparedRequest {
+        url: endpoint_url(env_config, EndpointMapping::DeleteSecret),
+        method: Method::Delete,
+        params: HashMap::new(),
+        payload: HashMap::from([
+            ("key".to_string(), Value::String(key.to_string())),
+            (
+                "table_name".to_string(),
+                Value::String(table_name.to_string()),
+            ),
+        ]),
+        headers: auth_headers(env_config.apikey.expose()),
+    }
+}
+
+/// Prepares the request to create a new table.
+pub(crate) fn create_table_request(env_config: &EnvConfig, table_name: &str) -> PreparedRequest {
+    table_request(
+        env_config,
+        EndpointMapping::CreateTable,
+        Method::Post,
+        table_name,
+    )
+}
+
+/// Prepares the request to delete an existing table.
+pub(crate) fn delete_table_request(env_config: &EnvConfig, table_name: &str) -> PreparedRequest {
+    table_request(
+        env_config,
+        EndpointMapping::DeleteTable,
+        Method::Delete,
+        table_name,
+    )
+}
+
+/// Converts the decrypted payload into a map of secrets.
 ///
 /// # Arguments
-/// * `table_name` - Table name where the secrets are stored.
+/// * `payload` - Decrypted payload from the server.
 ///
 /// # Returns
-/// * A `Result<Value, String>` object with decrypted payload.
-pub fn get_table(table_name: &String) -> Result<Value, String> {
-    let env_config = parser::env_variables();
-    let url = util::urljoin(&[
-        env_config.vault_server.as_ref(),
-        enums::EndpointMapping::GetTable.as_str(),
-    ]);
-    let request = table_request(url, Method::Get, table_name, &env_config);
-    let response = request::make_request(request);
-    request::decrypt_response(&env_config, &response)
+/// * A `Result` with a `HashMap` of the secrets, that are wiped from memory when dropped.
+pub(crate) fn secrets_map(payload: Value) -> Result<HashMap<String, Secret<Value>>, VaultError> {
+    match payload {
+        Value::Object(secrets) => Ok(secrets
+            .into_iter()
+            .map(|(key, value)| (key, Secret::json(value)))
+            .collect()),
+        _ => Err(VaultError::MalformedResponse(format!(
+            "Expected secrets as key-value pairs, received: {}",
+            payload
+        ))),
+    }
 }
 
-/// List all available table names in the server.
+/// Converts the response from the list tables endpoint into table names.
+///
+/// # Arguments
+/// * `response` - Detail object from the server's response.
 ///
 /// # Returns
-/// * A `Vec<String>` with all the table names.
-pub fn list_tables() -> Vec<String> {
-    let env_config = parser::env_variables();
-    let url = util::urljoin(&[
-        env_config.vault_server.as_ref(),
-        enums::EndpointMapping::ListTables.as_str(),
-    ]);
-    let request = request::PreparedRequest {
-        url,
-        method: Method::Get,
-        params: HashMap::new(),
-        payload: HashMap::new(),
-        headers: request::auth_headers(&env_config.apikey),
-    };
-    let response = request::make_request(request);
+/// * A `Result` with a `Vec<String>` of all the table names.
+pub(crate) fn table_names(response: Value) -> Result<Vec<String>, VaultError> {
     match response {
         Value::Array(array) => {
             let mut table_names: Vec<String> = Vec::new();
@@ -103,20 +205,91 @@ pub fn list_tables() -> Vec<String> {
                     Value::String(s) => table_names.push(s),
                     Value::Number(n) => table_names.push(n.to_string()),
                     _ => {
-                        println!("Unknown value received for table name: {}", value);
-                        exit(1)
+                        return Err(VaultError::MalformedResponse(format!(
+                            "Unknown value received for table name: {}",
+                            value
+                        )))
                     }
                 }
             }
-            table_names
-        }
-        _ => {
-            println!("Unexpected value returned: {:?}", response);
-            exit(1)
+            Ok(table_names)
         }
+        _ => Err(VaultError::MalformedResponse(format!(
+            "Unexpected value returned: {:?}",
+            response
+        ))),
     }
 }
 
+/// Retrieve secret(s) from the server.
+///
+/// # Arguments
+/// * `key` - Secret key for the which the value has to be retrieved.
+/// * `table_name` - Table name where the secret is stored.
+///
+/// # Returns
+/// * A `Result` with the decrypted payload, that is wiped from memory when dropped.
+pub fn get_secret(key: &str, table_name: &str) -> Result<Secret<Value>, VaultError> {
+    VaultClient::from_env()?.get_secret(key, table_name)
+}
+
+/// Retrieve multiple secrets from the server in a single request.
+///
+/// # Arguments
+/// * `keys` - Secret keys for the which the values have to be retrieved.
+/// * `table_name` - Table name where the secrets are stored.
+///
+/// # Returns
+/// * A `Result` with a `HashMap` of the decrypted secrets, that are wiped from memory when dropped.
+pub fn get_secrets(
+    keys: &[&str],
+    table_name: &str,
+) -> Result<HashMap<String, Secret<Value>>, VaultError> {
+    VaultClient::from_env()?.get_secrets(keys, table_name)
+}
+
+/// Retrieve ALL the secrets stored in a particular table from the server.
+///
+/// # Arguments
+/// * `table_name` - Table name where the secrets are stored.
+///
+/// # Returns
+/// * A `Result` with the decrypted payload, that is wiped from memory when dropped.
+pub fn get_table(table_name: &str) -> Result<Secret<Value>, VaultError> {
+    VaultClient::from_env()?.get_table(table_name)
+}
+
+/// Retrieve a secret from the server, and deserialize its value into the requested type.
+///
+/// # Arguments
+/// * `key` - Secret key for the which the value has to be retrieved.
+/// * `table_name` - Table name where the secret is stored.
+///
+/// # Returns
+/// * A `Result` with the deserialized secret value.
+pub fn get_secret_as<T: DeserializeOwned>(key: &str, table_name: &str) -> Result<T, VaultError> {
+    VaultClient::from_env()?.get_secret_as(key, table_name)
+}
+
+/// Retrieve ALL the secrets stored in a table, and deserialize them into the requested type.
+///
+/// # Arguments
+/// * `table_name` - Table name where the secrets are stored.
+///
+/// # Returns
+/// * A `Result` with the deserialized table.
+pub fn get_table_as<T: DeserializeOwned>(table_name: &str) -> Result<T, VaultError> {
+    VaultClient::from_env()?.get_table_as(table_name)
+}
+
+/// List all available table names in the server.
+///
+/// # Returns
+/// * A `Result` with a `Vec<String>` of all the table names.
+pub fn list_tables() -> Result<Vec<String>, VaultError> {
+    VaultClient::from_env()?.list_tables()
+}
+
 /// Creates or updates a secret value stored in a table.
 ///
 /// # Arguments
@@ -124,28 +297,12 @@ pub fn list_tables() -> Vec<String> {
 /// * `table_name` - Table name where the secret has to be added/updated.
 ///
 /// # Returns
-/// * A `Value` object with response from the server.
-pub fn put_secret(secrets: &HashMap<String, String>, table_name: &String) -> Value {
-    let env_config = parser::env_variables();
-    let url = util::urljoin(&[
-        env_config.vault_server.as_ref(),
-        enums::EndpointMapping::PutSecret.as_str(),
-    ]);
-    let sec: Value = to_value(secrets.to_owned()).unwrap();
-    let mut payload = HashMap::new();
-    payload.insert("secrets".to_string(), sec);
-    payload.insert(
-        "table_name".to_string(),
-        Value::String(table_name.to_string()),
-    );
-    let request = request::PreparedRequest {
-        url,
-        method: Method::Put,
-        params: HashMap::new(),
-        payload,
-        headers: request::auth_headers(&env_config.apikey),
-    };
-    request::make_request(request)
+/// * A `Result` with the `Value` object from the server's response.
+pub fn put_secret(
+    secrets: &HashMap<String, String>,
+    table_name: &str,
+) -> Result<Value, VaultError> {
+    VaultClient::from_env()?.put_secret(secrets, table_name)
 }
 
 /// Deletes a secret stored in a table.
@@ -155,27 +312,9 @@ pub fn put_secret(secrets: &HashMap<String, String>, table_name: &String) -> Val
 /// * `table_name` - Table name where the secret exists.
 ///
 /// # Returns
-/// * A `Value` object with response from the server.
-pub fn delete_secret(key: &String, table_name: &String) -> Value {
-    let env_config = parser::env_variables();
-    let url = util::urljoin(&[
-        env_config.vault_server.as_ref(),
-        enums::EndpointMapping::DeleteSecret.as_str(),
-    ]);
-    let request = request::PreparedRequest {
-        url,
-        method: Method::Delete,
-        params: HashMap::new(),
-        payload: HashMap::from([
-            ("key".to_string(), Value::String(key.to_string())),
-            (
-                "table_name".to_string(),
-                Value::String(table_name.to_string()),
-            ),
-        ]),
-        headers: request::auth_headers(&env_config.apikey),
-    };
-    request::make_request(request)
+/// * A `Result` with the `Value` object from the server's response.
+pub fn delete_secret(key: &str, table_name: &str) -> Result<Value, VaultError> {
+    VaultClient::from_env()?.delete_secret(key, table_name)
 }
 
 /// Creates a new table.
@@ -184,15 +323,9 @@ pub fn delete_secret(key: &String, table_name: &String) -> Value {
 /// * `table_name` - Table name that has to be created.
 ///
 /// # Returns
-/// * A `Value` object with response from the server.
-pub fn create_table(table_name: &String) -> Value {
-    let env_config = parser::env_variables();
-    let url = util::urljoin(&[
-        env_config.vault_server.as_ref(),
-        enums::EndpointMapping::CreateTable.as_str(),
-    ]);
-    let request = table_request(url, Method::Post, table_name, &env_config);
-    request::make_request(request)
+/// * A `Result` with the `Value` object from the server's response.
+pub fn create_table(table_name: &str) -> Result<Value, VaultError> {
+    VaultClient::from_env()?.create_table(table_name)
 }
 
 /// Deletes an existing table.
@@ -201,13 +334,7 @@ pub fn create_table(table_name: &String) -> Value {
 /// * `table_name` - Table name that has to be deleted.
 ///
 /// # Returns
-/// * A `Value` object with response from the server.
-pub fn delete_table(table_name: &String) -> Value {
-    let env_config = parser::env_variables();
-    let url = util::urljoin(&[
-        env_config.vault_server.as_ref(),
-        enums::EndpointMapping::DeleteTable.as_str(),
-    ]);
-    let request = table_request(url, Method::Delete, table_name, &env_config);
-    request::make_request(request)
+/// * A `Result` with the `Value` object from the server's response.
+pub fn delete_table(table_name: &str) -> Result<Value, VaultError> {
+    VaultClient::from_env()?.delete_table(table_name)
 }
@@ -0,0 +1,199 @@
+use serde::{Deserialize, Deserializer};
+use serde_json::Value;
+use std::fmt;
+use zeroize::Zeroize;
+
+/// Wrapper for a secret value, that is wiped from memory when dropped.
+///
+/// `Debug` and `Display` print `[REDACTED]`, so the value can only be read with an explicit call to `expose`.
+/// Deserializes transparently, so it can be used as a field in the structs loaded with `get_table_as`.
+pub struct Secret<T> {
+    value: T,
+    /// Function that wipes the value, since JSON values don't implement `Zeroize`.
+    wipe: fn(&mut T),
+}
+
+impl<T: Zeroize> Secret<T> {
+    /// Wraps a secret value.
+    ///
+    /// # Arguments
+    /// * `value` - Secret value.
+    ///
+    /// # Returns
+    /// * A `Secret` object.
+    pub fn new(value: T) -> Secret<T> {
+        Secret {
+            value,
+            wipe: Zeroize::zeroize,
+        }
+    }
+}
+
+impl<T> Secret<T> {
+    /// Returns a reference to the secret value.
+    pub fn expose(&self) -> &T {
+        &self.value
+    }
+}
+
+impl Secret<Value> {
+    /// Wraps a decrypted JSON value.
+    ///
+    /// Strings (including the keys of objects) are wiped when dropped, and the other values are cleared.
+    ///
+    /// # Arguments
+    /// * `value` - Decrypted JSON value.
+    ///
+    /// # Returns
+    /// * A `Secret<Value>` object.
+    pub fn json(value: Value) -> Secret<Value> {
+        Secret {
+            value,
+            wipe: wipe_value,
+        }
+    }
+}
+
+/// Wipes the strings in a JSON value, including the keys of the objects, and clears the value.
+///
+/// # Arguments
+/// * `value` - JSON value to be wiped.
+pub(crate) fn wipe_value(value: &mut Value) {
+    match value {
+        Value::String(text) => text.zeroize(),
+        Value::Array(values) => values.iter_mut().for_each(wipe_value),
+        Value::Object(map) => {
+            for (mut key, mut value) in std::mem::take(map) {
+                key.zeroize();
+                wipe_value(&mut value);
+            }
+        }
+        _ => {}
+    }
+    *value = Value::Null;
+}
+
+impl Secret<String> {
+    /// Concatenates the parts into a secret string.
+    ///
+    /// The string is allocated once with the exact capacity, so no partial copy is left behind by a reallocation.
+    ///
+    /// # Arguments
+    /// * `parts` - Parts of the secret string.
+    ///
+    /// # Returns
+    /// * A `Secret<String>` object.
+    pub fn concat(parts: &[&str]) -> Secret<String> {
+        let mut value = String::with_capacity(parts.iter().map(|part| part.len()).sum());
+        for part in parts {
+            value.push_str(part);
+        }
+        Secret::new(value)
+    }
+}
+
+/// Overwrites a digest of secret material, since ring's `Digest` doesn't implement `Zeroize`.
+///
+/// The digest is replaced with the digest of an empty input, with a volatile write that isn't optimized away.
+///
+/// # Arguments
+/// * `digest` - Digest to be wiped.
+pub(crate) fn wipe_digest(digest: &mut ring::digest::Digest) {
+    let empty = ring::digest::digest(digest.algorithm(), &[]);
+    // SAFETY: the pointer comes from a mutable reference, so it is valid and aligned,
+    // and `Digest` is `Copy` so overwriting it without dropping the old value leaks nothing.
+    unsafe { std::ptr::write_volatile(digest, empty) };
+    std::sync::atomic::compiler_fence(std::sync::atomic::Ordering::SeqCst);
+}
+
+impl<T> Drop for Secret<T> {
+    fn drop(&mut self) {
+        (self.wipe)(&mut self.value);
+    }
+}
+
+impl<T: Clone> Clone for Secret<T> {
+    fn clone(&self) -> Self {
+        Secret {
+            value: self.value.clone(),
+            wipe: self.wipe,
+        }
+    }
+}
+
+impl<T: Zeroize + Default> Default for Secret<T> {
+    fn default() -> Self {
+        Secret::new(T::default())
+    }
+}
+
+impl<T: Zeroize> From<T> for Secret<T> {
+    fn from(value: T) -> Self {
+        Secret::new(value)
+    }
+}
+
+impl From<&str> for Secret<String> {
+    fn from(value: &str) -> Self {
+        Secret::new(value.to_string())
+    }
+}
+
+impl<T> fmt::Debug for Secret<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[REDACTED]")
+    }
+}
+
+impl<T> fmt::Display for Secret<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[REDACTED]")
+    }
+}
+
+impl<'de, T: Zeroize + Deserialize<'de>> Deserialize<'de> for Secret<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        T::deserialize(deserializer).map(Secret::new)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn secrets_are_redacted() {
+        let secret = Secret::from("hunter2");
+        assert_eq!(format!("{:?}", secret), "[REDACTED]");
+        assert_eq!(secret.to_string(), "[REDACTED]");
+        assert_eq!(
+            format!("{:?}", Secret::json(json!({"key": "hunter2"}))),
+            "[REDACTED]"
+        );
+        assert_eq!(secret.expose(), "hunter2");
+        assert_eq!(secret.clone().expose(), "hunter2");
+        assert_eq!(Secret::concat(&["hunter", "2"]).expose(), "hunter2");
+    }
+
+    #[test]
+    fn secrets_deserialize_transparently() {
+        #[derive(Deserialize)]
+        struct Config {
+            password: Secret<String>,
+            port: Secret<u16>,
+        }
+        let config: Config =
+            serde_json::from_value(json!({"password": "hunter2", "port": 5432})).unwrap();
+        assert_eq!(config.password.expose(), "hunter2");
+        assert_eq!(*config.port.expose(), 5432);
+    }
+
+    #[test]
+    fn json_values_are_wiped() {
+        let mut value =
+            json!({"key": "value", "nested": ["secret", {"inner": "secret"}], "port": 5432});
+        wipe_value(&mut value);
+        assert_eq!(value, Value::Null);
+    }
+}
@@ -0,0 +1,132 @@
+use crate::cache::{CacheStats, SecretCache};
+use crate::error::{VaultError, Warning};
+use crate::offline::OfflineCache;
+use crate::parser::EnvConfig;
+use crate::request;
+use crate::secret::Secret;
+use serde_json::{Map, Value};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Caches shared by the blocking and async clients.
+///
+/// Both the clients build the same requests and handle the responses the same way,
+/// so they only differ in how the request is sent to the server.
+pub(crate) struct ClientState {
+    pub(crate) cache: SecretCache,
+    pub(crate) offline: Arc<OfflineCache>,
+    /// Whether the insecure TLS setting was already reported.
+    insecure_reported: AtomicBool,
+}
+
+impl ClientState {
+    /// Creates the in-memory and offline caches from the environment configuration.
+    ///
+    /// # Arguments
+    /// * `env_config` - Environment variables' configuration.
+    ///
+    /// # Returns
+    /// * A `ClientState` object.
+    pub(crate) fn new(env_config: &EnvConfig) -> ClientState {
+        ClientState {
+            cache: SecretCache::new(Duration::from_secs(env_config.cache_ttl)),
+            offline: Arc::new(OfflineCache::new(env_config)),
+            insecure_reported: AtomicBool::new(false),
+        }
+    }
+
+    /// Returns the number of cache hits and misses.
+    pub(crate) fn cache_stats(&self) -> CacheStats {
+        self.cache.stats()
+    }
+
+    /// Returns the warnings reported since the last call, and clears them.
+    ///
+    /// # Arguments
+    /// * `env_config` - Environment variables' configuration.
+    ///
+    /// # Returns
+    /// * A `Vec<Warning>` with the insecure TLS setting, followed by the offline cache warnings.
+    pub(crate) fn take_warnings(&self, env_config: &EnvConfig) -> Vec<Warning> {
+        let mut warnings = Vec::new();
+        if env_config.tls.insecure && !self.insecure_reported.swap(true, Ordering::Relaxed) {
+            warnings.push(Warning::InsecureTls);
+        }
+        warnings.extend(self.offline.take_warnings());
+        warnings
+    }
+
+    /// Removes all the cached secrets.
+    pub(crate) fn clear_cache(&self) {
+        self.cache.clear()
+    }
+
+    /// Returns a cached secret, in the same shape as the server's response.
+    ///
+    /// # Arguments
+    /// * `table_name` - Table name where the secret is stored.
+    /// * `key` - Secret key.
+    ///
+    /// # Returns
+    /// * An `Option` with the secret as a key-value pair.
+    pub(crate) fn cached_secret(&self, table_name: &str, key: &str) -> Option<Secret<Value>> {
+        self.cache.get(table_name, key).map(|value| {
+            Secret::json(Value::Object(Map::from_iter([(
+                key.to_string(),
+                value.expose().clone(),
+            )])))
+        })
+    }
+
+    /// Decrypts the response with the requested secrets, and caches them in memory.
+    ///
+    /// # Arguments
+    /// * `env_config` - Environment variables' configuration.
+    /// * `table_name` - Table name where the secrets are stored.
+    /// * `response` - Result of the request to the server.
+    ///
+    /// # Returns
+    /// * A `Result` with the decrypted secrets as key-value pairs.
+    pub(crate) fn secrets_fetched(
+        &self,
+        env_config: &EnvConfig,
+        table_name: &str,
+        response: Result<Value, VaultError>,
+    ) -> Result<Value, VaultError> {
+        let secrets = request::decrypt_response(env_config, &response?)?;
+        self.cache.insert(table_name, &secrets);
+        Ok(secrets)
+    }
+
+    /// Decrypts the response with all the secrets of a table, and caches them in memory.
+    ///
+    /// # Arguments
+    /// * `env_config` - Environment variables' configuration.
+    /// * `table_name` - Table name.
+    /// * `response` - Result of the request to the server.
+    ///
+    /// # Returns
+    /// * A `Result` with the decrypted table.
+    pub(crate) fn table_fetched(
+        &self,
+        env_config: &EnvConfig,
+        table_name: &str,
+        response: Result<Value, VaultError>,
+    ) -> Result<Value, VaultError> {
+        let table = request::decrypt_response(env_config, &response?)?;
+        self.cache.insert_table(table_name, &table);
+        Ok(table)
+    }
+
+    /// Invalidates the cached secrets that were updated or deleted in the server.
+    ///
+    /// # Arguments
+    /// * `table_name` - Table name where the secrets are stored.
+    /// * `keys` - Secret keys.
+    pub(crate) fn invalidate(&self, table_name: &str, keys: &[&str]) {
+        for key in keys {
+            self.cache.invalidate(table_name, key);
+        }
+    }
+}
@@ -0,0 +1,614 @@
+use crate::client::VaultClient;
+use crate::decipher;
+use crate::error::VaultError;
+use crate::parser::EnvConfig;
+use reqwest::Url;
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::server::WebPkiClientVerifier;
+use rustls::{RootCertStore, ServerConfig, ServerConnection, StreamOwned};
+use serde_json::{json, Map, Value};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+/// In-memory tables stored by the mock server.
+pub type Tables = BTreeMap<String, BTreeMap<String, String>>;
+
+/// Struct to store the state that is shared between the mock server's connections.
+struct MockState {
+    apikey: String,
+    secret: String,
+    transit_key_length: usize,
+    transit_time_bucket: u64,
+    tables: Mutex<Tables>,
+    failures: Mutex<VecDeque<MockFailure>>,
+    delays: Mutex<VecDeque<Duration>>,
+    requests: AtomicUsize,
+}
+
+/// Struct to store an injected failure, along with the `Retry-After` header to respond with.
+struct MockFailure {
+    status: u16,
+    retry_after: Option<u64>,
+    /// Number of requests to let through before failing.
+    skip: usize,
+}
+
+/// Struct to store the components of an incoming request.
+struct MockRequest {
+    method: String,
+    path: String,
+    params: HashMap<String, String>,
+    headers: HashMap<String, String>,
+    body: Vec<u8>,
+}
+
+/// Struct to store the components of an outgoing response.
+struct MockResponse {
+    status: u16,
+    body: Value,
+    headers: Vec<(String, String)>,
+}
+
+impl MockResponse {
+    /// Creates a response with the given status code and a "detail" object.
+    fn detail(status: u16, detail: Value) -> MockResponse {
+        MockResponse {
+            status,
+            body: json!({ "detail": detail }),
+            headers: Vec::new(),
+        }
+    }
+}
+
+/// TLS settings for the mock server, with the files in PEM format.
+#[derive(Debug, Clone)]
+pub struct MockTls {
+    /// Server certificate chain.
+    pub cert: PathBuf,
+    /// Private key of the server certificate.
+    pub key: PathBuf,
+    /// Root certificate(s) to verify the client certificates with, which makes them mandatory.
+    pub client_ca: Option<PathBuf>,
+}
+
+impl MockTls {
+    /// Creates the rustls server configuration.
+    fn server_config(&self) -> Result<ServerConfig, VaultError> {
+        let invalid = |err: &dyn std::fmt::Display| VaultError::MissingConfig(err.to_string());
+        let chain = CertificateDer::pem_file_iter(&self.cert)
+            .map_err(|err| invalid(&err))?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|err| invalid(&err))?;
+        let key = PrivateKeyDer::from_pem_file(&self.key).map_err(|err| invalid(&err))?;
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let builder = ServerConfig::builder_with_provider(provider.clone())
+            .with_safe_default_protocol_versions()
+            .map_err(|err| invalid(&err))?;
+        let builder = match &self.client_ca {
+            Some(client_ca) => {
+                let mut roots = RootCertStore::empty();
+                for certificate in
+                    CertificateDer::pem_file_iter(client_ca).map_err(|err| invalid(&err))?
+                {
+                    roots
+                        .add(certificate.map_err(|err| invalid(&err))?)
+                        .map_err(|err| invalid(&err))?;
+                }
+                let verifier =
+                    WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider)
+                        .build()
+                        .map_err(|err| invalid(&err))?;
+                builder.with_client_cert_verifier(verifier)
+            }
+            None => builder.with_no_client_auth(),
+        };
+        builder
+            .with_single_cert(chain, key)
+            .map_err(|err| invalid(&err))
+    }
+}
+
+/// In-process mock of the VaultAPI server, for end-to-end tests without a live server.
+///
+/// The server listens on a random port on localhost, stores the tables in memory,
+/// and encrypts the responses with the same transit encryption as the real server.
+/// The server is shut down when the object is dropped.
+pub struct MockServer {
+    address: SocketAddr,
+    scheme: &'static str,
+    state: Arc<MockState>,
+    shutdown: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl MockServer {
+    /// Starts a mock server on a random port on localhost.
+    ///
+    /// # Arguments
+    /// * `apikey` - API key that the server accepts.
+    /// * `secret` - Secret to derive the transit encryption key.
+    ///
+    /// # Returns
+    /// * A `Result` with the running `MockServer` object.
+    pub fn start(apikey: &str, secret: &str) -> Result<MockServer, VaultError> {
+        MockServer::spawn(apikey, secret, None)
+    }
+
+    /// Starts a mock server that serves HTTPS on a random port on localhost.
+    ///
+    /// The TLS settings of the client (eg: `ca_cert`) have to be set on the `env_config` to connect to it.
+    ///
+    /// # Arguments
+    /// * `apikey` - API key that the server accepts.
+    /// * `secret` - Secret to derive the transit encryption key.
+    /// * `tls` - Server certificate, and optionally the CA to require client certificates.
+    ///
+    /// # Returns
+    /// * A `Result` with the running `MockServer` object.
+    pub fn start_tls(apikey: &str, secret: &str, tls: &MockTls) -> Result<MockServer, VaultError> {
+        MockServer::spawn(apikey, secret, Some(Arc::new(tls.server_config()?)))
+    }
+
+    /// Binds the listener, and serves the connections in a background thread.
+    fn spawn(
+        apikey: &str,
+        secret: &str,
+        tls_config: Option<Arc<ServerConfig>>,
+    ) -> Result<MockServer, VaultError> {
+        let scheme = if tls_config.is_some() {
+            "https"
+        } else {
+            "http"
+        };
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .map_err(|err| VaultError::Transport(err.to_string()))?;
+        let address = listener
+            .local_addr()
+            .map_err(|err| VaultError::Transport(err.to_string()))?;
+        let defaults = EnvConfig::new(server_url(scheme, &address), apikey, secret);
+        let state = Arc::new(MockState {
+            apikey: apikey.to_string(),
+            secret: secret.to_string(),
+            transit_key_length: defaults.transit_key_length,
+            transit_time_bucket: defaults.transit_time_bucket,
+            tables: Mutex::new(BTreeMap::new()),
+            failures: Mutex::new(VecDeque::new()),
+            delays: Mutex::new(VecDeque::new()),
+            requests: AtomicUsize::new(0),
+        });
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let handle = {
+            let state = state.clone();
+            let shutdown = shutdown.clone();
+            std::thread::spawn(move || {
+                for stream in listener.incoming() {
+                    if shutdown.load(Ordering::SeqCst) {
+                        break;
+                    }
+                    if let Ok(stream) = stream {
+                        let state = state.clone();
+                        let tls_config = tls_config.clone();
+                        std::thread::spawn(move || match tls_config {
+                            Some(tls_config) => {
+                                // Failed handshakes drop the connection, as the client sees it from a real server
+                                if let Ok(connection) = ServerConnection::new(tls_config) {
+                                    serve_connection(StreamOwned::new(connection, stream), &state)
+                                }
+                            }
+                            None => serve_connection(stream, &state),
+                        });
+                    }
+                }
+            })
+        };
+        Ok(MockServer {
+            address,
+            scheme,
+            state,
+            shutdown,
+            handle: Some(handle),
+        })
+    }
+
+    /// Returns the base URL of the mock server.
+    pub fn url(&self) -> Url {
+        server_url(self.scheme, &self.address)
+    }
+
+    /// Returns the environment configuration to connect to the mock server.
+    pub fn env_config(&self) -> EnvConfig {
+        EnvConfig::new(self.url(), &self.state.apikey, &self.state.secret)
+    }
+
+    /// Creates a client that is connected to the mock server.
+    ///
+    /// # Returns
+    /// * A `Result` with the `VaultClient` object.
+    pub fn client(&self) -> Result<VaultClient, VaultError> {
+        VaultClient::new(self.env_config())
+    }
+
+    /// Stores a secret in a table, creating the table if it doesn't exist.
+    ///
+    /// # Arguments
+    /// * `table_name` - Table name where the secret has to be stored.
+    /// * `key` - Secret key.
+    /// * `value` - Secret value.
+    pub fn insert_secret(&self, table_name: &str, key: &str, value: &str) {
+        self.state
+            .tables
+            .lock()
+            .unwrap()
+            .entry(table_name.to_string())
+            .or_default()
+            .insert(key.to_string(), value.to_string());
+    }
+
+    /// Fails the next request with the given status code, to test the handling of transient errors.
+    ///
+    /// Failures are queued, so calling this multiple times fails as many consecutive requests.
+    /// Health checks are never failed.
+    ///
+    /// # Arguments
+    /// * `status` - Status code to respond with.
+    /// * `retry_after` - Number of seconds to set in the `Retry-After` header.
+    pub fn fail_next(&self, status: u16, retry_after: Option<u64>) {
+        self.state.failures.lock().unwrap().push_back(MockFailure {
+            status,
+            retry_after,
+            skip: 0,
+        });
+    }
+
+    /// Lets the given number of requests through, and then fails the next one with the given status code.
+    ///
+    /// This is used to fail a request in the middle of a command, such as the second chunk of an import.
+    ///
+    /// # Arguments
+    /// * `count` - Number of requests to let through, excluding the health checks.
+    /// * `status` - Status code to respond with.
+    pub fn fail_after(&self, count: usize, status: u16) {
+        self.state.failures.lock().unwrap().push_back(MockFailure {
+            status,
+            retry_after: None,
+            skip: count,
+        });
+    }
+
+    /// Delays the response to the next request, to test the handling of slow servers and timeouts.
+    ///
+    /// Delays are queued like the failures, and health checks are never delayed.
+    ///
+    /// # Arguments
+    /// * `delay` - Duration to wait before responding.
+    pub fn delay_next(&self, delay: Duration) {
+        self.state.delays.lock().unwrap().push_back(delay);
+    }
+
+    /// Returns the number of requests received so far, excluding the health checks.
+    pub fn request_count(&self) -> usize {
+        self.state.requests.load(Ordering::SeqCst)
+    }
+
+    /// Returns a snapshot of all the tables stored in the mock server.
+    pub fn tables(&self) -> Tables {
+        self.state.tables.lock().unwrap().clone()
+    }
+}
+
+/// Stops accepting new connections when the mock server goes out of scope.
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        self.shutdown.store(true, Ordering::SeqCst);
+        // Unblock the listener, so that it can observe the shutdown flag
+        let _ = TcpStream::connect(self.address);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+/// Constructs the base URL for a socket address.
+fn server_url(scheme: &str, address: &SocketAddr) -> Url {
+    Url::parse(&format!("{}://{}/", scheme, address)).expect("socket address is a valid URL")
+}
+
+/// Serves all the requests received on a keep-alive connection.
+///
+/// # Arguments
+/// * `stream` - Connection stream.
+/// * `state` - State shared across all the connections.
+fn serve_connection<S: Read + Write>(stream: S, state: &MockState) {
+    let mut reader = BufReader::new(stream);
+    while let Some(request) = read_request(&mut reader) {
+        let response = route(&request, state);
+        let body = response.body.to_string();
+        let headers: String = response
+            .headers
+            .iter()
+            .map(|(name, value)| format!("{}: {}\r\n", name, value))
+            .collect();
+        let head = format!(
+            "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n{}\r\n",
+            response.status,
+            reqwest::StatusCode::from_u16(response.status)
+                .ok()
+                .and_then(|status| status.canonical_reason())
+                .unwrap_or_default(),
+            body.len(),
+            headers
+        );
+        let stream = reader.get_mut();
+        if stream.write_all(head.as_bytes()).is_err()
+            || stream.write_all(body.as_bytes()).is_err()
+            || stream.flush().is_err()
+        {
+            return;
+        }
+    }
+}
+
+/// Reads a single HTTP/1.1 request from the connection.
+///
+/// # Returns
+/// * An `Option` with the `MockRequest`, or `None` if the connection was closed.
+fn read_request<S: Read>(reader: &mut BufReader<S>) -> Option<MockRequest> {
+    let mut request_line = String::new();
+    if reader.read_line(&mut request_line).ok()? == 0 {
+        return None;
+    }
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next()?.to_string();
+    let target = parts.next()?.to_string();
+
+    let mut headers = HashMap::new();
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line).ok()? == 0 {
+            return None;
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            headers.insert(name.trim().to_lowercase(), value.trim().to_string());
+        }
+    }
+
+    let content_length = headers
+        .get("content-length")
+        .and_then(|value| value.parse::<usize>().ok())
+        .unwrap_or(0);
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body).ok()?;
+
+    let url = Url::parse(&format!("http://localhost{}", target)).ok()?;
+    let params = url.query_pairs().into_owned().collect();
+    Some(MockRequest {
+        method,
+        path: url.path().to_string(),
+        params,
+        headers,
+        body,
+    })
+}
+
+/// Routes the request to the appropriate handler.
+fn route(request: &MockRequest, state: &MockState) -> MockResponse {
+    if request.path == "/health" {
+        return MockResponse::detail(200, json!("OK"));
+    }
+    state.requests.fetch_add(1, Ordering::SeqCst);
+    let delay = state.delays.lock().unwrap().pop_front();
+    if let Some(delay) = delay {
+        std::thread::sleep(delay);
+    }
+    let failure = {
+        let mut failures = state.failures.lock().unwrap();
+        match failures.front_mut() {
+            Some(failure) if failure.skip > 0 => {
+                failure.skip -= 1;
+                None
+            }
+            _ => failures.pop_front(),
+        }
+    };
+    if let Some(failure) = failure {
+        let mut response = MockResponse::detail(failure.status, json!("Injected failure"));
+        if let Some(retry_after) = failure.retry_after {
+            response
+                .headers
+                .push(("Retry-After".to_string(), retry_after.to_string()));
+        }
+        return response;
+    }
+    let bearer = format!("Bearer {}", state.apikey);
+    if request.headers.get("authorization") != Some(&bearer) {
+        return MockResponse::detail(401, json!("Unauthorized"));
+    }
+    let body: Value = serde_json::from_slice(&request.body).unwrap_or(Value::Null);
+    let result = match (request.method.as_str(), request.path.as_str()) {
+        ("GET", "/get-secret") => get_secrets(request, state, "key"),
+        ("GET", "/get-secrets") => get_secrets(request, state, "keys"),
+        ("GET", "/get-table") => get_table(request, state),
+        ("PUT", "/put-secret") => put_secret(&body, state),
+        ("GET", "/list-tables") => list_tables(state),
+        ("POST", "/create-table") => create_table(request, state),
+        ("DELETE", "/delete-table") => delete_table(request, state),
+        ("DELETE", "/delete-secret") => delete_secret(&body, state),
+        _ => Err(MockResponse::detail(404, json!("Not Found"))),
+    };
+    result.unwrap_or_else(|response| response)
+}
+
+/// Retrieves a required parameter from the query string.
+fn param<'a>(request: &'a MockRequest, name: &str) -> Result<&'a String, MockResponse> {
+    request
+        .params
+        .get(name)
+        .ok_or_else(|| MockResponse::detail(422, json!(format!("Missing parameter: {}", name))))
+}
+
+/// Retrieves a required string field from the JSON body.
+fn field<'a>(body: &'a Value, name: &str) -> Result<&'a str, MockResponse> {
+    body.get(name)
+        .and_then(Value::as_str)
+        .ok_or_else(|| MockResponse::detail(422, json!(format!("Missing field: {}", name))))
+}
+
+/// Returns an error response for a table that doesn't exist.
+fn table_not_found(table_name: &str) -> MockResponse {
+    MockResponse::detail(404, json!(format!("Table '{}' not found", table_name)))
+}
+
+/// Encrypts the payload with transit encryption and wraps it in a "detail" object.
+fn encrypted(payload: Value, state: &MockState) -> Result<MockResponse, MockResponse> {
+    match decipher::transit_encrypt(
+        &state.apikey,
+        &state.secret,
+        &payload,
+        state.transit_key_length,
+        state.transit_time_bucket,
+    ) {
+        Ok(cipher_text) => Ok(MockResponse::detail(200, json!(cipher_text))),
+        Err(err) => Err(MockResponse::detail(500, json!(err.to_string()))),
+    }
+}
+
+/// Handler for `/get-secret` and `/get-secrets`, where the keys are comma separated.
+fn get_secrets(
+    request: &MockRequest,
+    state: &MockState,
+    key_param: &str,
+) -> Result<MockResponse, MockResponse> {
+    let table_name = param(request, "table_name")?;
+    let keys = param(request, key_param)?;
+    let tables = state.tables.lock().unwrap();
+    let table = tables
+        .get(table_name)
+        .ok_or_else(|| table_not_found(table_name))?;
+    let mut secrets = Map::new();
+    for key in keys.split(',').map(str::trim) {
+        if let Some(value) = table.get(key) {
+            secrets.insert(key.to_string(), json!(value));
+        }
+    }
+    if secrets.is_empty() {
+        return Err(MockResponse::detail(
+            404,
+            json!(format!("Secret(s) not found in table '{}'", table_name)),
+        ));
+    }
+    encrypted(Value::Object(secrets), state)
+}
+
+/// Handler for `/get-table`.
+fn get_table(request: &MockRequest, state: &MockState) -> Result<MockResponse, MockResponse> {
+    let table_name = param(request, "table_name")?;
+    let tables = state.tables.lock().unwrap();
+    let table = tables
+        .get(table_name)
+        .ok_or_else(|| table_not_found(table_name))?;
+    encrypted(json!(table), state)
+}
+
+/// Handler for `/put-secret`.
+fn put_secret(body: &Value, state: &MockState) -> Result<MockResponse, MockResponse> {
+    let table_name = field(body, "table_name")?;
+    let secrets = body
+        .get("secrets")
+        .and_then(Value::as_object)
+        .ok_or_else(|| MockResponse::detail(422, json!("Missing field: secrets")))?;
+    let mut tables = state.tables.lock().unwrap();
+    let table = tables
+        .get_mut(table_name)
+        .ok_or_else(|| table_not_found(table_name))?;
+    for (key, value) in secrets {
+        let value = match value {
+            Value::String(value) => value.to_string(),
+            _ => value.to_string(),
+        };
+        table.insert(key.to_string(), value);
+    }
+    Ok(MockResponse::detail(
+        200,
+        json!(format!(
+            "{} secret(s) stored in table '{}'",
+            secrets.len(),
+            table_name
+        )),
+    ))
+}
+
+/// Handler for `/delete-secret`.
+fn delete_secret(body: &Value, state: &MockState) -> Result<MockResponse, MockResponse> {
+    let table_name = field(body, "table_name")?;
+    let key = field(body, "key")?;
+    let mut tables = state.tables.lock().unwrap();
+    let table = tables
+        .get_mut(table_name)
+        .ok_or_else(|| table_not_found(table_name))?;
+    match table.remove(key) {
+        Some(_) => Ok(MockResponse::detail(
+            200,
+            json!(format!(
+                "Secret '{}' deleted from table '{}'",
+                key, table_name
+            )),
+        )),
+        None => Err(MockResponse::detail(
+            404,
+            json!(format!(
+                "Secret '{}' not found in table '{}'",
+                key, table_name
+            )),
+        )),
+    }
+}
+
+/// Handler for `/list-tables`.
+fn list_tables(state: &MockState) -> Result<MockResponse, MockResponse> {
+    let tables = state.tables.lock().unwrap();
+    Ok(MockResponse::detail(
+        200,
+        json!(tables.keys().collect::<Vec<_>>()),
+    ))
+}
+
+/// Handler for `/create-table`.
+fn create_table(request: &MockRequest, state: &MockState) -> Result<MockResponse, MockResponse> {
+    let table_name = param(request, "table_name")?;
+    let mut tables = state.tables.lock().unwrap();
+    if tables.contains_key(table_name) {
+        return Err(MockResponse::detail(
+            409,
+            json!(format!("Table '{}' already exists", table_name)),
+        ));
+    }
+    tables.insert(table_name.to_string(), BTreeMap::new());
+    Ok(MockResponse::detail(
+        200,
+        json!(format!("Table '{}' created", table_name)),
+    ))
+}
+
+/// Handler for `/delete-table`.
+fn delete_table(request: &MockRequest, state: &MockState) -> Result<MockResponse, MockResponse> {
+    let table_name = param(request, "table_name")?;
+    let mut tables = state.tables.lock().unwrap();
+    match tables.remove(table_name) {
+        Some(_) => Ok(MockResponse::detail(
+            200,
+            json!(format!("Table '{}' deleted", table_name)),
+        )),
+        None => Err(table_not_found(table_name)),
+    }
+}
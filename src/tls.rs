@@ -0,0 +1,270 @@
+use crate::error::VaultError;
+use crate::secret::Secret;
+use base64::{engine::general_purpose, Engine as _};
+use reqwest::{Certificate, Identity};
+use ring::digest;
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::client::WebPkiServerVerifier;
+use rustls::crypto::{self, CryptoProvider};
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName, UnixTime};
+use rustls::{ClientConfig, DigitallySignedStruct, RootCertStore, SignatureScheme};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Struct to store the TLS settings to connect to the server.
+#[derive(Debug, Clone, Default)]
+pub struct TlsConfig {
+    /// PEM file with the root certificate(s) to trust.
+    pub ca_cert: Option<PathBuf>,
+    /// Client certificate, either as PEM (along with `client_key`) or as a PKCS#12 archive.
+    pub client_cert: Option<PathBuf>,
+    /// PEM file with the client certificate's private key, in PKCS#8 format.
+    pub client_key: Option<PathBuf>,
+    /// Password for the PKCS#12 archive.
+    pub client_cert_password: Option<Secret<String>>,
+    /// Base64 encoded SHA-256 hashes of the server certificate's public key (SPKI).
+    pub pinned_spki: Vec<String>,
+    /// Skips the validation of the server certificate. Use only for local development.
+    pub insecure: bool,
+}
+
+/// Reads a file that is referenced in the TLS configuration.
+fn read(path: &Path) -> Result<Vec<u8>, VaultError> {
+    fs::read(path).map_err(|err| {
+        VaultError::MissingConfig(format!("Failed to read '{}': {}", path.display(), err))
+    })
+}
+
+/// Loads the root certificates to trust, in addition to the built-in roots.
+///
+/// # Arguments
+/// * `tls_config` - TLS configuration.
+///
+/// # Returns
+/// * A `Result` with the certificates from the CA bundle.
+pub(crate) fn root_certificates(tls_config: &TlsConfig) -> Result<Vec<Certificate>, VaultError> {
+    match &tls_config.ca_cert {
+        Some(path) => Certificate::from_pem_bundle(&read(path)?).map_err(|err| {
+            VaultError::MissingConfig(format!("Invalid CA bundle '{}': {}", path.display(), err))
+        }),
+        None => Ok(Vec::new()),
+    }
+}
+
+/// Loads the client certificate for mutual TLS.
+///
+/// # Arguments
+/// * `tls_config` - TLS configuration.
+///
+/// # Returns
+/// * A `Result` with an `Option` of the client identity, if one is configured.
+pub(crate) fn identity(tls_config: &TlsConfig) -> Result<Option<Identity>, VaultError> {
+    let cert_path = match &tls_config.client_cert {
+        Some(cert_path) => cert_path,
+        None => return Ok(None),
+    };
+    let identity = match &tls_config.client_key {
+        Some(key_path) => Identity::from_pkcs8_pem(&read(cert_path)?, &read(key_path)?),
+        None => Identity::from_pkcs12_der(
+            &read(cert_path)?,
+            tls_config
+                .client_cert_password
+                .as_ref()
+                .map(|password| password.expose().as_str())
+                .unwrap_or_default(),
+        ),
+    };
+    identity.map(Some).map_err(|err| {
+        VaultError::MissingConfig(format!(
+            "Invalid client certificate '{}': {}",
+            cert_path.display(),
+            err
+        ))
+    })
+}
+
+/// Decodes the SPKI pins, which may be prefixed with `sha256//` as in the HPKP format.
+fn decode_pins(pins: &[String]) -> Result<Vec<Vec<u8>>, VaultError> {
+    pins.iter()
+        .map(|pin| {
+            let encoded = pin.trim();
+            let encoded = encoded.strip_prefix("sha256//").unwrap_or(encoded);
+            match general_purpose::STANDARD.decode(encoded) {
+                Ok(hash) if hash.len() == digest::SHA256_OUTPUT_LEN => Ok(hash),
+                _ => Err(VaultError::MissingConfig(format!(
+                    "Invalid SPKI pin '{}', expected a base64 encoded SHA-256 hash",
+                    pin
+                ))),
+            }
+        })
+        .collect()
+}
+
+/// Computes the pin of a certificate, as the SHA-256 hash of its public key (SPKI).
+///
+/// # Arguments
+/// * `certificate` - DER encoded certificate.
+///
+/// # Returns
+/// * A `Result` with the base64 encoded pin.
+pub fn spki_pin(certificate: &[u8]) -> Result<String, VaultError> {
+    let der = CertificateDer::from(certificate);
+    let cert = webpki::EndEntityCert::try_from(&der)
+        .map_err(|err| VaultError::InvalidArgument(format!("Invalid certificate: {:?}", err)))?;
+    let hash = digest::digest(&digest::SHA256, cert.subject_public_key_info().as_ref());
+    Ok(general_purpose::STANDARD.encode(hash))
+}
+
+/// Verifier that pins the server certificate's public key, on top of the regular chain validation.
+#[derive(Debug)]
+struct PinnedVerifier {
+    /// Chain validation, which is skipped in the insecure mode.
+    inner: Option<Arc<WebPkiServerVerifier>>,
+    pins: Vec<Vec<u8>>,
+    provider: Arc<CryptoProvider>,
+}
+
+impl ServerCertVerifier for PinnedVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        if let Some(inner) = &self.inner {
+            inner.verify_server_cert(end_entity, intermediates, server_name, ocsp_response, now)?;
+        }
+        let cert = webpki::EndEntityCert::try_from(end_entity).map_err(|_| {
+            rustls::Error::InvalidCertificate(rustls::CertificateError::BadEncoding)
+        })?;
+        let hash = digest::digest(&digest::SHA256, cert.subject_public_key_info().as_ref());
+        if !self.pins.iter().any(|pin| pin.as_slice() == hash.as_ref()) {
+            return Err(rustls::Error::InvalidCertificate(
+                rustls::CertificateError::ApplicationVerificationFailure,
+            ));
+        }
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        crypto::verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        crypto::verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.provider
+            .signature_verification_algorithms
+            .supported_schemes()
+    }
+}
+
+/// Loads the root certificates for the pinned configuration, from the CA bundle or the system's roots.
+fn root_store(tls_config: &TlsConfig) -> Result<RootCertStore, VaultError> {
+    let path = match &tls_config.ca_cert {
+        Some(path) => path.to_path_buf(),
+        None => openssl_probe::probe().cert_file.ok_or_else(|| {
+            VaultError::MissingConfig(
+                "No system CA bundle found, set VAULT_CA_CERT to pin the certificate".to_string(),
+            )
+        })?,
+    };
+    let mut roots = RootCertStore::empty();
+    for certificate in CertificateDer::pem_slice_iter(&read(&path)?) {
+        let certificate = certificate.map_err(|err| {
+            VaultError::MissingConfig(format!("Invalid CA bundle '{}': {}", path.display(), err))
+        })?;
+        // System bundles may contain certificates that are not valid trust anchors
+        let _ = roots.add(certificate);
+    }
+    Ok(roots)
+}
+
+/// Creates the TLS configuration that pins the server certificate, if any pins are configured.
+///
+/// Pinning requires a custom verifier, so the connection uses rustls instead of the default TLS backend.
+///
+/// # Arguments
+/// * `tls_config` - TLS configuration.
+///
+/// # Returns
+/// * A `Result` with an `Option` of the rustls client configuration.
+pub(crate) fn pinned_config(tls_config: &TlsConfig) -> Result<Option<ClientConfig>, VaultError> {
+    if tls_config.pinned_spki.is_empty() {
+        return Ok(None);
+    }
+    let pins = decode_pins(&tls_config.pinned_spki)?;
+    let provider = Arc::new(crypto::ring::default_provider());
+    let inner = if tls_config.insecure {
+        None
+    } else {
+        let roots = Arc::new(root_store(tls_config)?);
+        let verifier = WebPkiServerVerifier::builder_with_provider(roots, provider.clone())
+            .build()
+            .map_err(|err| VaultError::MissingConfig(err.to_string()))?;
+        Some(verifier)
+    };
+    let verifier = PinnedVerifier {
+        inner,
+        pins,
+        provider: provider.clone(),
+    };
+    let builder = ClientConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()
+        .map_err(|err| VaultError::MissingConfig(err.to_string()))?
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(verifier));
+    let config = match (&tls_config.client_cert, &tls_config.client_key) {
+        (None, _) => builder.with_no_client_auth(),
+        (Some(cert_path), Some(key_path)) => {
+            let invalid = |err: rustls::pki_types::pem::Error| {
+                VaultError::MissingConfig(format!(
+                    "Invalid client certificate '{}': {}",
+                    cert_path.display(),
+                    err
+                ))
+            };
+            let chain = CertificateDer::pem_slice_iter(&read(cert_path)?)
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(invalid)?;
+            let key = PrivateKeyDer::from_pem_slice(&read(key_path)?).map_err(invalid)?;
+            builder
+                .with_client_auth_cert(chain, key)
+                .map_err(|err| VaultError::MissingConfig(err.to_string()))?
+        }
+        (Some(_), None) => {
+            return Err(VaultError::MissingConfig(
+                "PKCS#12 client certificates can't be combined with certificate pinning, \
+                 use a PEM certificate and key instead"
+                    .to_string(),
+            ))
+        }
+    };
+    Ok(Some(config))
+}
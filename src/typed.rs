@@ -0,0 +1,393 @@
+use crate::client::VaultClient;
+use crate::error::VaultError;
+use crate::secret::Secret;
+use serde::de::value::{MapDeserializer, SeqDeserializer};
+use serde::de::{self, DeserializeOwned, Deserializer, IntoDeserializer, Visitor};
+use serde::forward_to_deserialize_any;
+use serde_json::Value;
+use std::collections::HashMap;
+
+/// Deserializes the decrypted secrets into any type that implements `Deserialize`.
+///
+/// Secrets are stored as strings in the server, so strings are parsed into numbers
+/// and booleans when the target type expects one.
+///
+/// # Arguments
+/// * `value` - Decrypted secrets.
+///
+/// # Returns
+/// * A `Result` with the deserialized object, or an error naming the missing/mistyped key.
+pub fn from_value<T: DeserializeOwned>(value: Value) -> Result<T, VaultError> {
+    serde_path_to_error::deserialize(Lenient(value)).map_err(|err| {
+        let path = err.path().to_string();
+        let message = err.inner().to_string();
+        // Missing fields are reported at the parent's path, so the field name is appended to it
+        let key = match message
+            .strip_prefix("missing field `")
+            .and_then(|rest| rest.split_once('`'))
+        {
+            Some((field, _)) if path == "." => field.to_string(),
+            Some((field, _)) => format!("{}.{}", path, field),
+            None => path,
+        };
+        VaultError::Deserialize { key, message }
+    })
+}
+
+/// Deserializes a single secret into any type that implements `Deserialize`.
+///
+/// # Arguments
+/// * `key` - Secret key, used to report errors.
+/// * `value` - Decrypted secret value.
+///
+/// # Returns
+/// * A `Result` with the deserialized object.
+pub fn from_secret<T: DeserializeOwned>(key: &str, value: Value) -> Result<T, VaultError> {
+    T::deserialize(Lenient(value)).map_err(|err| VaultError::Deserialize {
+        key: key.to_string(),
+        message: err.to_string(),
+    })
+}
+
+/// Extracts a secret's value from the decrypted payload, and deserializes it.
+///
+/// # Arguments
+/// * `key` - Secret key.
+/// * `payload` - Decrypted payload with the secret as a key-value pair.
+///
+/// # Returns
+/// * A `Result` with the deserialized object.
+pub(crate) fn secret_value<T: DeserializeOwned>(
+    key: &str,
+    payload: Value,
+) -> Result<T, VaultError> {
+    match payload {
+        Value::Object(mut secrets) => match secrets.remove(key) {
+            Some(value) => from_secret(key, value),
+            None => Err(VaultError::Deserialize {
+                key: key.to_string(),
+                message: "secret not found in the response".to_string(),
+            }),
+        },
+        _ => from_secret(key, payload),
+    }
+}
+
+/// Loads the fields of a config struct from the vault, fetching each table only once.
+///
+/// This is used by the code generated with `#[derive(VaultSecrets)]`.
+pub struct TableLoader<'a> {
+    client: &'a VaultClient,
+    /// Tables retrieved so far, which are wiped from memory when the loader is dropped.
+    tables: HashMap<String, Secret<Value>>,
+}
+
+impl<'a> TableLoader<'a> {
+    /// Creates a new loader that retrieves the tables using the given client.
+    ///
+    /// # Arguments
+    /// * `client` - Client object to make requests to the server.
+    ///
+    /// # Returns
+    /// * A `TableLoader` object.
+    pub fn new(client: &'a VaultClient) -> TableLoader<'a> {
+        TableLoader {
+            client,
+            tables: HashMap::new(),
+        }
+    }
+
+    /// Resolves a field's value from the vault, falling back to an env var and then to a default value.
+    ///
+    /// Fields that are not found anywhere are deserialized from `null`, so `Option` fields become `None`.
+    ///
+    /// # Arguments
+    /// * `table_name` - Table name where the secret is stored.
+    /// * `key` - Secret key.
+    /// * `env` - Environment variable to fall back to, when the key is not in the table.
+    /// * `default` - Default value, when the key is neither in the table nor in the environment.
+    ///
+    /// # Returns
+    /// * A `Result` with the deserialized field value.
+    pub fn field<T: DeserializeOwned>(
+        &mut self,
+        table_name: &str,
+        key: &str,
+        env: Option<&str>,
+        default: Option<&str>,
+    ) -> Result<T, VaultError> {
+        if !self.tables.contains_key(table_name) {
+            let table = self.client.get_table(table_name)?;
+            if !table.expose().is_object() {
+                return Err(VaultError::MalformedResponse(
+                    "Expected secrets as key-value pairs".to_string(),
+                ));
+            }
+            self.tables.insert(table_name.to_string(), table);
+        }
+        if let Some(value) = self.tables[table_name].expose().get(key) {
+            return from_secret(key, value.clone());
+        }
+        if let Some(value) = env.and_then(|env| std::env::var(env).ok()) {
+            return from_secret(key, Value::String(value));
+        }
+        if let Some(value) = default {
+            return from_secret(key, Value::String(value.to_string()));
+        }
+        from_secret(key, Value::Null).map_err(|_| VaultError::Deserialize {
+            key: key.to_string(),
+            message: format!("secret not found in table '{}'", table_name),
+        })
+    }
+}
+
+/// Wrapper around a JSON value, that parses strings into the scalar types expected by the visitor.
+struct Lenient(Value);
+
+/// Allows the wrapper to be used with `MapDeserializer` and `SeqDeserializer`.
+impl IntoDeserializer<'_, serde_json::Error> for Lenient {
+    type Deserializer = Lenient;
+
+    fn into_deserializer(self) -> Self::Deserializer {
+        self
+    }
+}
+
+/// Generates the deserializer methods that parse strings into scalar types.
+macro_rules! parse_scalar {
+    ($($method:ident => $visit:ident: $ty:ty),* $(,)?) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+                match self.0 {
+                    Value::String(text) => match text.trim().parse::<$ty>() {
+                        Ok(parsed) => visitor.$visit(parsed),
+                        Err(_) => Err(de::Error::invalid_value(
+                            de::Unexpected::Str(&text),
+                            &stringify!($ty),
+                        )),
+                    },
+                    other => other.$method(visitor),
+                }
+            }
+        )*
+    };
+}
+
+impl<'de> Deserializer<'de> for Lenient {
+    type Error = serde_json::Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self.0 {
+            Value::Object(map) => {
+                let mut deserializer =
+                    MapDeserializer::new(map.into_iter().map(|(k, v)| (k, Lenient(v))));
+                let value = visitor.visit_map(&mut deserializer)?;
+                deserializer.end()?;
+                Ok(value)
+            }
+            Value::Array(array) => {
+                let mut deserializer = SeqDeserializer::new(array.into_iter().map(Lenient));
+                let value = visitor.visit_seq(&mut deserializer)?;
+                deserializer.end()?;
+                Ok(value)
+            }
+            other => other.deserialize_any(visitor),
+        }
+    }
+
+    parse_scalar! {
+        deserialize_bool => visit_bool: bool,
+        deserialize_i8 => visit_i8: i8,
+        deserialize_i16 => visit_i16: i16,
+        deserialize_i32 => visit_i32: i32,
+        deserialize_i64 => visit_i64: i64,
+        deserialize_i128 => visit_i128: i128,
+        deserialize_u8 => visit_u8: u8,
+        deserialize_u16 => visit_u16: u16,
+        deserialize_u32 => visit_u32: u32,
+        deserialize_u64 => visit_u64: u64,
+        deserialize_u128 => visit_u128: u128,
+        deserialize_f32 => visit_f32: f32,
+        deserialize_f64 => visit_f64: f64,
+    }
+
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.deserialize_string(visitor)
+    }
+
+    fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self.0 {
+            Value::Number(number) => visitor.visit_string(number.to_string()),
+            Value::Bool(boolean) => visitor.visit_string(boolean.to_string()),
+            other => other.deserialize_string(visitor),
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self.0 {
+            Value::Null => visitor.visit_none(),
+            other => visitor.visit_some(Lenient(other)),
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.0.deserialize_enum(name, variants, visitor)
+    }
+
+    forward_to_deserialize_any! {
+        char bytes byte_buf unit unit_struct seq tuple tuple_struct map struct identifier ignored_any
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+    use serde_json::json;
+
+    #[derive(Debug, Deserialize)]
+    struct Database {
+        host: String,
+        port: u16,
+        ssl: bool,
+        timeout: f64,
+        replicas: Option<u8>,
+    }
+
+    #[derive(Debug, Deserialize)]
+    struct Config {
+        database: Database,
+        debug: bool,
+    }
+
+    #[test]
+    fn strings_are_parsed_into_scalars() {
+        let config: Config = from_value(json!({
+            "database": {"host": "db", "port": " 5432 ", "ssl": "true", "timeout": "2.5"},
+            "debug": false,
+        }))
+        .unwrap();
+        assert_eq!(config.database.host, "db");
+        assert_eq!(config.database.port, 5432);
+        assert!(config.database.ssl);
+        assert_eq!(config.database.timeout, 2.5);
+        assert_eq!(config.database.replicas, None);
+        assert!(!config.debug);
+        // Numbers and booleans are converted to strings, when the target type expects one
+        assert_eq!(from_secret::<String>("PORT", json!(5432)).unwrap(), "5432");
+        assert_eq!(
+            from_secret::<Option<u8>>("REPLICAS", json!("3")).unwrap(),
+            Some(3)
+        );
+    }
+
+    #[test]
+    fn errors_name_the_missing_or_mistyped_key() {
+        let error = |value| match from_value::<Config>(value) {
+            Err(VaultError::Deserialize { key, .. }) => key,
+            other => panic!("expected a deserialize error, got {:?}", other),
+        };
+        assert_eq!(
+            error(json!({"database": {}, "debug": true})),
+            "database.host"
+        );
+        assert_eq!(
+            error(json!({
+                "database": {"host": "db", "port": "70000", "ssl": "yes", "timeout": "1"},
+                "debug": true,
+            })),
+            "database.port"
+        );
+        assert_eq!(
+            error(json!({
+                "database": {"host": "db", "port": "5432", "ssl": "true", "timeout": "1"},
+            })),
+            "debug"
+        );
+        match from_secret::<bool>("DEBUG", json!("yes")) {
+            Err(VaultError::Deserialize { key, message }) => {
+                assert_eq!(key, "DEBUG");
+                assert!(message.contains("yes"));
+            }
+            other => panic!("expected a deserialize error, got {:?}", other),
+        }
+        assert!(matches!(
+            secret_value::<u16>("PORT", json!({"HOST": "db"})),
+            Err(VaultError::Deserialize { key, .. }) if key == "PORT"
+        ));
+        assert_eq!(
+            secret_value::<u16>("PORT", json!({"PORT": "5432"})).unwrap(),
+            5432
+        );
+    }
+
+    #[cfg(feature = "testing")]
+    #[test]
+    fn fields_are_resolved_from_the_table_env_and_default() {
+        let server = crate::testing::MockServer::start("apikey", "secret").unwrap();
+        server.insert_secret("production", "PORT", "5432");
+        let vault_client = server.client().unwrap();
+        let mut loader = TableLoader::new(&vault_client);
+        // Only this test reads these env vars, so they are not shared with other tests
+        std::env::set_var("VAULTAPI_TYPED_TEST_PORT", "6543");
+        std::env::set_var("VAULTAPI_TYPED_TEST_HOST", "env-host");
+        std::env::remove_var("VAULTAPI_TYPED_TEST_MISSING");
+
+        let port: u16 = loader
+            .field(
+                "production",
+                "PORT",
+                Some("VAULTAPI_TYPED_TEST_PORT"),
+                Some("1"),
+            )
+            .unwrap();
+        assert_eq!(port, 5432);
+        let host: String = loader
+            .field(
+                "production",
+                "HOST",
+                Some("VAULTAPI_TYPED_TEST_HOST"),
+                Some("default"),
+            )
+            .unwrap();
+        assert_eq!(host, "env-host");
+        let host: String = loader
+            .field(
+                "production",
+                "HOST",
+                Some("VAULTAPI_TYPED_TEST_MISSING"),
+                Some("default"),
+            )
+            .unwrap();
+        assert_eq!(host, "default");
+        let replicas: Option<u8> = loader.field("production", "REPLICAS", None, None).unwrap();
+        assert_eq!(replicas, None);
+        match loader.field::<String>("production", "HOST", None, None) {
+            Err(VaultError::Deserialize { key, message }) => {
+                assert_eq!(key, "HOST");
+                assert!(message.contains("production"));
+            }
+            other => panic!(
+                "expected the missing secret to be reported, got {:?}",
+                other
+            ),
+        }
+        // The table is retrieved only once
+        assert_eq!(server.request_count(), 1);
+        std::env::remove_var("VAULTAPI_TYPED_TEST_PORT");
+        std::env::remove_var("VAULTAPI_TYPED_TEST_HOST");
+    }
+}
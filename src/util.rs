@@ -1,6 +1,125 @@
+use crate::error::VaultError;
+use ring::rand::{SecureRandom, SystemRandom};
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
 pub fn urljoin(args: &[&str]) -> String {
     args.iter()
         .map(|s| s.trim_matches('/')) // Strip leading and trailing slashes
         .collect::<Vec<&str>>()
         .join("/") // Join with single slash
 }
+
+/// Number of attempts to create a temporary file with a name that isn't taken.
+const TEMP_ATTEMPTS: u32 = 8;
+
+/// Creates a new temporary file next to the target, that is readable only by the owner.
+///
+/// The name is randomized and the file is never opened if it already exists,
+/// so the permissions are always applied and no sibling file is ever overwritten.
+///
+/// # Arguments
+/// * `path` - Path to the target file.
+///
+/// # Returns
+/// * A `Result` with the temporary file and its path.
+fn create_temp(path: &Path) -> Result<(fs::File, PathBuf), VaultError> {
+    let file_name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let mut last_err = None;
+    for _ in 0..TEMP_ATTEMPTS {
+        let mut random = [0u8; 8];
+        SystemRandom::new()
+            .fill(&mut random)
+            .map_err(|_| VaultError::Io("Failed to generate a temporary file name".to_string()))?;
+        let temp_path = path.with_file_name(format!(
+            ".{}.{:016x}.tmp",
+            file_name,
+            u64::from_le_bytes(random)
+        ));
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        match options.open(&temp_path) {
+            Ok(file) => return Ok((file, temp_path)),
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => last_err = Some(err),
+            Err(err) => return Err(err.into()),
+        }
+    }
+    Err(last_err
+        .map(VaultError::from)
+        .unwrap_or_else(|| VaultError::Io("Failed to create a temporary file".to_string())))
+}
+
+/// Writes a file that is readable only by the owner.
+///
+/// The contents are written to a new temporary file in the same directory first,
+/// and renamed over the target, so that a crash never leaves a partial file behind.
+///
+/// # Arguments
+/// * `path` - Path to the file.
+/// * `contents` - Contents of the file.
+pub fn write_private(path: &Path, contents: &[u8]) -> Result<(), VaultError> {
+    if let Some(parent) = path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+    {
+        fs::create_dir_all(parent)?;
+    }
+    let (mut file, temp_path) = create_temp(path)?;
+    let written = file
+        .write_all(contents)
+        .and_then(|_| file.sync_all())
+        .and_then(|_| fs::rename(&temp_path, path));
+    if let Err(err) = written {
+        let _ = fs::remove_file(&temp_path);
+        return Err(err.into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Creates an empty directory for a test, under the system's temporary directory.
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("vaultapi-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn write_private_keeps_sibling_files() {
+        let dir = temp_dir("siblings");
+        let path = dir.join("secrets.json");
+        let sibling = dir.join("secrets.tmp");
+        fs::write(&sibling, b"sibling").unwrap();
+        write_private(&path, b"first").unwrap();
+        write_private(&path, b"second").unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"second");
+        assert_eq!(fs::read(&sibling).unwrap(), b"sibling");
+        // Only the target and the sibling are left behind
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 2);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn write_private_restricts_existing_files() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = temp_dir("permissions");
+        let path = dir.join("credentials");
+        fs::write(&path, b"public").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+        write_private(&path, b"private").unwrap();
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
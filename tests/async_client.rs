@@ -0,0 +1,130 @@
+#![cfg(all(feature = "testing", feature = "async"))]
+
+use serde_json::json;
+use std::collections::HashMap;
+use std::future::Future;
+use std::time::Duration;
+use vaultapi::error::{VaultError, Warning};
+use vaultapi::nonblocking::AsyncVaultClient;
+use vaultapi::parser::EnvConfig;
+use vaultapi::testing::MockServer;
+
+/// Runs a future to completion on a single-threaded runtime.
+fn block_on<F: Future>(future: F) -> F::Output {
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap()
+        .block_on(future)
+}
+
+/// Creates the configuration for the mock server, with short delays between the retries.
+fn env_config(server: &MockServer) -> EnvConfig {
+    let mut env_config = server.env_config();
+    env_config.retry_policy.base_delay = Duration::from_millis(1);
+    env_config.retry_policy.max_delay = Duration::from_millis(10);
+    env_config
+}
+
+#[test]
+fn secrets_are_stored_retrieved_and_deleted() {
+    let server = MockServer::start("apikey", "secret").unwrap();
+    block_on(async {
+        let vault_client = AsyncVaultClient::new(env_config(&server)).await.unwrap();
+        vault_client.create_table("production").await.unwrap();
+        let secrets = HashMap::from([
+            ("DB_PASSWORD".to_string(), "p@ss".to_string()),
+            ("DB_PORT".to_string(), "5432".to_string()),
+        ]);
+        vault_client
+            .put_secret(&secrets, "production")
+            .await
+            .unwrap();
+        assert_eq!(
+            vault_client.list_tables().await.unwrap(),
+            vec!["production"]
+        );
+        assert_eq!(
+            vault_client
+                .get_secrets(&["DB_PASSWORD", "DB_PORT"], "production")
+                .await
+                .unwrap()
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.expose().clone()))
+                .collect::<HashMap<_, _>>(),
+            HashMap::from([
+                ("DB_PASSWORD".to_string(), json!("p@ss")),
+                ("DB_PORT".to_string(), json!("5432")),
+            ])
+        );
+        vault_client
+            .delete_secret("DB_PORT", "production")
+            .await
+            .unwrap();
+        assert_eq!(
+            *vault_client.get_table("production").await.unwrap().expose(),
+            json!({"DB_PASSWORD": "p@ss"})
+        );
+        vault_client.delete_table("production").await.unwrap();
+    });
+    assert!(server.tables().is_empty());
+}
+
+#[test]
+fn transient_failures_are_retried() {
+    let server = MockServer::start("apikey", "secret").unwrap();
+    server.insert_secret("production", "key", "value");
+    server.fail_next(503, None);
+    server.fail_next(429, Some(0));
+    block_on(async {
+        let vault_client = AsyncVaultClient::new(env_config(&server)).await.unwrap();
+        assert_eq!(
+            *vault_client
+                .get_secret("key", "production")
+                .await
+                .unwrap()
+                .expose(),
+            json!({"key": "value"})
+        );
+        assert_eq!(server.request_count(), 3);
+        server.fail_next(503, None);
+        assert!(matches!(
+            vault_client.delete_secret("key", "production").await,
+            Err(VaultError::Status { code: 503, .. })
+        ));
+    });
+    assert_eq!(server.request_count(), 4);
+}
+
+#[test]
+fn unreachable_server_is_served_from_the_offline_cache() {
+    let dir = std::env::temp_dir().join(format!("vaultapi-async-fallback-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    let path = dir.join("offline.cache");
+    let server = MockServer::start("apikey", "secret").unwrap();
+    server.insert_secret("production", "key", "value");
+    let mut online = env_config(&server);
+    online.offline_cache = Some(path.clone());
+    let mut offline = env_config(&server);
+    offline.offline_cache = Some(path.clone());
+    offline.retry_policy.max_attempts = 1;
+    block_on(async {
+        let vault_client = AsyncVaultClient::new(online).await.unwrap();
+        vault_client.get_table("production").await.unwrap();
+        drop(server);
+        let vault_client = AsyncVaultClient::new(offline).await.unwrap();
+        assert_eq!(
+            *vault_client
+                .get_secret("key", "production")
+                .await
+                .unwrap()
+                .expose(),
+            json!({"key": "value"})
+        );
+        assert!(matches!(
+            vault_client.take_warnings().as_slice(),
+            [Warning::Unreachable(_), Warning::Stale { table_name, .. }] if table_name == "production"
+        ));
+    });
+    std::fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,88 @@
+#![cfg(feature = "testing")]
+
+use serde_json::json;
+use std::path::PathBuf;
+use vaultapi::commands::{self, Output};
+use vaultapi::parser::Endpoint;
+use vaultapi::testing::MockServer;
+
+/// Writes a config file with a profile for each server, and points `VAULTAPI_CONFIG` to it.
+///
+/// This is the only test in this binary, so the env var is not shared with other tests.
+fn config_file(source: &MockServer, destination: &MockServer) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("vaultapi-copy-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("config.toml");
+    let profile = |name: &str, server: &MockServer, apikey: &str| {
+        format!(
+            "[profiles.{}]\nvault_server = \"{}\"\napikey = \"{}\"\nsecret = \"{}-secret\"\nretry_attempts = 1\n\n",
+            name,
+            server.url(),
+            apikey,
+            apikey
+        )
+    };
+    let contents =
+        profile("source", source, "source") + &profile("destination", destination, "destination");
+    std::fs::write(&path, contents).unwrap();
+    std::env::set_var("VAULTAPI_CONFIG", &path);
+    path
+}
+
+/// Runs the copy between the profiles, and returns the summary.
+fn copy(keys: &[&str], create_table: bool, dry_run: bool, prune: bool) -> serde_json::Value {
+    let from: Endpoint = "source:production".parse().unwrap();
+    let to: Endpoint = "destination:mirror".parse().unwrap();
+    let keys: Vec<String> = keys.iter().map(|key| key.to_string()).collect();
+    match commands::copy(&from, &to, &keys, create_table, dry_run, prune, 25, None) {
+        Ok(Output::Value(value)) => value,
+        Ok(_) => panic!("expected a summary of the copy"),
+        Err(err) => panic!("copy failed: {}", err),
+    }
+}
+
+#[test]
+fn secrets_are_copied_between_servers() {
+    let source = MockServer::start("source", "source-secret").unwrap();
+    let destination = MockServer::start("destination", "destination-secret").unwrap();
+    source.insert_secret("production", "A", "1");
+    source.insert_secret("production", "B", "2");
+    let path = config_file(&source, &destination);
+
+    // A dry run reports the changes, without creating the table or uploading the secrets
+    let summary = copy(&[], true, true, false);
+    assert_eq!(summary["created"], json!(true));
+    assert_eq!(summary["added"], json!(["A", "B"]));
+    assert_eq!(summary["to"]["table"], json!("mirror"));
+    assert!(destination.tables().is_empty());
+
+    let summary = copy(&[], true, false, false);
+    assert_eq!(summary["dry_run"], json!(false));
+    assert_eq!(
+        destination.tables()["mirror"],
+        source.tables()["production"]
+    );
+
+    // Only the selected keys are copied, and the extra keys are kept without prune
+    destination.insert_secret("mirror", "EXTRA", "x");
+    source.insert_secret("production", "B", "changed");
+    let summary = copy(&["B"], false, false, false);
+    assert_eq!(summary["overwritten"], json!(["B"]));
+    assert_eq!(summary["deleted"], json!([]));
+    assert_eq!(destination.tables()["mirror"]["B"], "changed");
+    assert!(destination.tables()["mirror"].contains_key("EXTRA"));
+
+    let summary = copy(&[], false, false, true);
+    assert_eq!(summary["deleted"], json!(["EXTRA"]));
+    assert_eq!(summary["unchanged"], json!(["A", "B"]));
+    assert_eq!(
+        destination.tables()["mirror"],
+        source.tables()["production"]
+    );
+    // The source is only read from
+    assert_eq!(source.tables().len(), 1);
+
+    std::env::remove_var("VAULTAPI_CONFIG");
+    std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+}
@@ -0,0 +1,67 @@
+#![cfg(all(feature = "derive", feature = "testing"))]
+
+use vaultapi::error::VaultError;
+use vaultapi::testing::MockServer;
+use vaultapi::VaultSecrets;
+
+#[derive(VaultSecrets)]
+#[vault(table = "production")]
+struct Config {
+    db_host: String,
+    #[vault(key = "DB_PASSWORD")]
+    password: String,
+    #[vault(env = "VAULTAPI_DERIVE_TEST_PORT", default = "5432")]
+    db_port: u16,
+    #[vault(default = "false")]
+    debug: bool,
+    #[vault(table = "shared")]
+    sentry_dsn: Option<String>,
+    #[vault(table = "shared")]
+    region: Option<String>,
+}
+
+#[derive(VaultSecrets)]
+#[vault(table = "production")]
+struct Invalid {
+    #[allow(dead_code)]
+    db_host: u16,
+}
+
+#[test]
+fn struct_is_loaded_from_the_vault() {
+    let server = MockServer::start("apikey", "secret").unwrap();
+    server.insert_secret("production", "DB_HOST", "db.example.com");
+    server.insert_secret("production", "DB_PASSWORD", "p@ss");
+    server.insert_secret("shared", "SENTRY_DSN", "https://sentry.example.com");
+    let vault_client = server.client().unwrap();
+
+    // Only this test reads the env var, so it is not shared with other tests
+    std::env::remove_var("VAULTAPI_DERIVE_TEST_PORT");
+    let config = Config::load(&vault_client).unwrap();
+    assert_eq!(config.db_host, "db.example.com");
+    assert_eq!(config.password, "p@ss");
+    assert_eq!(config.db_port, 5432);
+    assert!(!config.debug);
+    assert_eq!(
+        config.sentry_dsn.as_deref(),
+        Some("https://sentry.example.com")
+    );
+    assert_eq!(config.region, None);
+    // Each table is retrieved only once
+    assert_eq!(server.request_count(), 2);
+
+    // The env var is used before the default, and is parsed into the field's type
+    std::env::set_var("VAULTAPI_DERIVE_TEST_PORT", "6543");
+    assert_eq!(Config::load(&vault_client).unwrap().db_port, 6543);
+    // The secret stored in the table is used before the env var
+    server.insert_secret("production", "DB_PORT", "7654");
+    vault_client.clear_cache();
+    assert_eq!(Config::load(&vault_client).unwrap().db_port, 7654);
+    std::env::remove_var("VAULTAPI_DERIVE_TEST_PORT");
+
+    match Invalid::load(&vault_client) {
+        Err(VaultError::Deserialize { key, .. }) => assert_eq!(key, "DB_HOST"),
+        Ok(_) => panic!("expected an error for the mistyped secret"),
+        Err(err) => panic!("expected a deserialize error, got {}", err),
+    }
+}
@@ -0,0 +1,31 @@
+#!/usr/bin/env bash
+# Generates the certificates for the TLS tests against the mock server.
+# The private keys are test fixtures only, and are not used anywhere else.
+set -euo pipefail
+cd "$(dirname "$0")"
+
+key() {
+  openssl genpkey -algorithm EC -pkeyopt ec_paramgen_curve:P-256 -out "$1"
+}
+
+# Root CA that signs both the server and the client certificates
+key ca.key
+openssl req -x509 -new -key ca.key -sha256 -days 36500 -subj "/CN=VaultAPI Test CA" \
+  -addext "basicConstraints=critical,CA:TRUE" -addext "keyUsage=critical,keyCertSign,cRLSign" \
+  -out ca.pem
+
+# Server certificate for the mock server on localhost
+key server.key
+openssl req -new -key server.key -subj "/CN=localhost" -out server.csr
+openssl x509 -req -in server.csr -CA ca.pem -CAkey ca.key -CAcreateserial -sha256 -days 36500 \
+  -extfile <(printf "subjectAltName=IP:127.0.0.1,DNS:localhost\nextendedKeyUsage=serverAuth\nbasicConstraints=CA:FALSE") \
+  -out server.pem
+
+# Client certificate for mutual TLS
+key client.key
+openssl req -new -key client.key -subj "/CN=vaultapi-client" -out client.csr
+openssl x509 -req -in client.csr -CA ca.pem -CAkey ca.key -CAcreateserial -sha256 -days 36500 \
+  -extfile <(printf "extendedKeyUsage=clientAuth\nbasicConstraints=CA:FALSE") \
+  -out client.pem
+
+rm -f ca.key ca.srl server.csr client.csr
@@ -0,0 +1,276 @@
+#![cfg(feature = "testing")]
+
+use serde_json::json;
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+use std::time::Duration;
+use vaultapi::client::VaultClient;
+use vaultapi::commands;
+use vaultapi::error::{VaultError, Warning};
+use vaultapi::parser::EnvConfig;
+use vaultapi::testing::MockServer;
+
+/// Creates the configuration for the mock server, with short delays between the retries.
+fn env_config(server: &MockServer) -> EnvConfig {
+    let mut env_config = server.env_config();
+    env_config.retry_policy.base_delay = Duration::from_millis(1);
+    env_config.retry_policy.max_delay = Duration::from_millis(10);
+    env_config
+}
+
+/// Returns a path for the offline cache under the system's temporary directory.
+fn offline_cache(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("vaultapi-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    dir.join("offline.cache")
+}
+
+#[test]
+fn secrets_are_stored_retrieved_and_deleted() {
+    let server = MockServer::start("apikey", "secret").unwrap();
+    let vault_client = VaultClient::new(env_config(&server)).unwrap();
+    vault_client.create_table("production").unwrap();
+    let secrets = HashMap::from([
+        ("DB_PASSWORD".to_string(), "p@ss".to_string()),
+        ("DB_PORT".to_string(), "5432".to_string()),
+    ]);
+    vault_client.put_secret(&secrets, "production").unwrap();
+    assert_eq!(vault_client.list_tables().unwrap(), vec!["production"]);
+    assert_eq!(
+        *vault_client
+            .get_secret("DB_PASSWORD", "production")
+            .unwrap()
+            .expose(),
+        json!({"DB_PASSWORD": "p@ss"})
+    );
+    assert_eq!(
+        *vault_client.get_table("production").unwrap().expose(),
+        json!({"DB_PASSWORD": "p@ss", "DB_PORT": "5432"})
+    );
+
+    vault_client.delete_secret("DB_PORT", "production").unwrap();
+    assert!(!server.tables()["production"].contains_key("DB_PORT"));
+    // The deleted secret is not served from the in-memory cache
+    assert!(matches!(
+        vault_client.get_secret("DB_PORT", "production"),
+        Err(VaultError::Status { code: 404, .. })
+    ));
+    vault_client.delete_table("production").unwrap();
+    assert!(server.tables().is_empty());
+}
+
+#[test]
+fn transient_failures_are_retried() {
+    let server = MockServer::start("apikey", "secret").unwrap();
+    server.insert_secret("production", "key", "value");
+    let vault_client = VaultClient::new(env_config(&server)).unwrap();
+    server.fail_next(503, None);
+    server.fail_next(502, Some(0));
+    assert_eq!(
+        *vault_client
+            .get_secret("key", "production")
+            .unwrap()
+            .expose(),
+        json!({"key": "value"})
+    );
+    assert_eq!(server.request_count(), 3);
+
+    // Deleting a secret is never retried, as the server may have deleted it already
+    server.fail_next(503, None);
+    assert!(matches!(
+        vault_client.delete_secret("key", "production"),
+        Err(VaultError::Status { code: 503, .. })
+    ));
+    assert_eq!(server.request_count(), 4);
+    assert!(server.tables()["production"].contains_key("key"));
+}
+
+#[test]
+fn unauthorized_requests_are_rejected() {
+    let server = MockServer::start("apikey", "secret").unwrap();
+    let mut env_config = env_config(&server);
+    env_config.apikey = "wrong".into();
+    let vault_client = VaultClient::new(env_config).unwrap();
+    assert!(matches!(
+        vault_client.list_tables(),
+        Err(VaultError::Unauthorized(_))
+    ));
+}
+
+#[test]
+fn unreachable_server_is_served_from_the_offline_cache() {
+    let path = offline_cache("client-fallback");
+    let server = MockServer::start("apikey", "secret").unwrap();
+    server.insert_secret("production", "key", "value");
+    let mut config = env_config(&server);
+    config.offline_cache = Some(path.clone());
+    let vault_client = VaultClient::new(config).unwrap();
+    assert_eq!(
+        *vault_client.get_table("production").unwrap().expose(),
+        json!({"key": "value"})
+    );
+    assert!(vault_client.take_warnings().is_empty());
+
+    let mut config = env_config(&server);
+    config.offline_cache = Some(path.clone());
+    config.retry_policy.max_attempts = 1;
+    drop(server);
+    let vault_client = VaultClient::new(config).unwrap();
+    assert_eq!(
+        *vault_client
+            .get_secret("key", "production")
+            .unwrap()
+            .expose(),
+        json!({"key": "value"})
+    );
+    // The failed health check is reported, along with the age of the secrets that were served
+    let warnings = vault_client.take_warnings();
+    assert!(matches!(
+        warnings.as_slice(),
+        [Warning::Unreachable(_), Warning::Stale { table_name, .. }] if table_name == "production"
+    ));
+    assert!(vault_client.get_table("missing").is_err());
+    std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+}
+
+#[test]
+fn secrets_are_imported_in_chunks() {
+    let server = MockServer::start("apikey", "secret").unwrap();
+    server.insert_secret("production", "A", "1");
+    server.insert_secret("production", "B", "old");
+    let vault_client = VaultClient::new(env_config(&server)).unwrap();
+    let secrets: BTreeMap<String, String> =
+        [("A", "1"), ("B", "new"), ("C", "3"), ("D", "4"), ("E", "5")]
+            .into_iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect();
+    let import = |dry_run, no_overwrite| {
+        vault_client.clear_cache();
+        commands::import_secrets(
+            &vault_client,
+            "production",
+            &secrets,
+            false,
+            dry_run,
+            false,
+            no_overwrite,
+            2,
+        )
+    };
+    let before = server.tables();
+
+    // Existing keys with a different value are reported, without uploading anything
+    match import(false, true) {
+        Err(VaultError::InvalidArgument(message)) => assert!(message.ends_with(": B")),
+        other => panic!(
+            "expected the overwritten keys to be rejected, got {:?}",
+            other
+        ),
+    }
+    let summary = import(true, false).unwrap();
+    assert_eq!(summary["added"], json!(["C", "D", "E"]));
+    assert_eq!(summary["overwritten"], json!(["B"]));
+    assert_eq!(summary["unchanged"], json!(["A"]));
+    assert_eq!(server.tables(), before);
+
+    // The second chunk fails, after listing the tables, retrieving the table and uploading the first chunk
+    server.fail_after(3, 500);
+    match import(false, false) {
+        Err(VaultError::Partial {
+            applied,
+            remaining,
+            error,
+        }) => {
+            assert_eq!(applied, vec!["C", "D"]);
+            assert_eq!(remaining, vec!["E", "B"]);
+            assert!(matches!(*error, VaultError::Status { code: 500, .. }));
+        }
+        other => panic!("expected a partial import, got {:?}", other),
+    }
+    assert!(server.tables()["production"].contains_key("D"));
+    assert!(!server.tables()["production"].contains_key("E"));
+
+    // Importing again only uploads the remaining keys, in a single chunk
+    let count = server.request_count();
+    let summary = import(false, false).unwrap();
+    assert_eq!(summary["added"], json!(["E"]));
+    assert_eq!(server.request_count() - count, 3);
+    assert_eq!(server.tables()["production"], secrets);
+}
+
+#[test]
+fn writes_invalidate_the_cached_secrets() {
+    let server = MockServer::start("apikey", "secret").unwrap();
+    server.insert_secret("production", "key", "old");
+    let mut config = env_config(&server);
+    config.cache_ttl = 60;
+    let vault_client = VaultClient::new(config).unwrap();
+    let get = || vault_client.get_secret("key", "production").unwrap();
+    assert_eq!(*get().expose(), json!({"key": "old"}));
+    assert_eq!(*get().expose(), json!({"key": "old"}));
+    assert_eq!(server.request_count(), 1);
+
+    let secrets = HashMap::from([("key".to_string(), "new".to_string())]);
+    vault_client.put_secret(&secrets, "production").unwrap();
+    assert_eq!(*get().expose(), json!({"key": "new"}));
+    assert_eq!(server.request_count(), 3);
+
+    vault_client.delete_secret("key", "production").unwrap();
+    assert!(vault_client.get_secret("key", "production").is_err());
+    let stats = vault_client.cache_stats();
+    assert_eq!((stats.hits, stats.misses), (1, 3));
+}
+
+#[test]
+fn slow_responses_time_out() {
+    let server = MockServer::start("apikey", "secret").unwrap();
+    server.insert_secret("production", "key", "value");
+    let client = |max_attempts| {
+        let mut config = env_config(&server);
+        config.timeout = Some(Duration::from_millis(200));
+        config.retry_policy.max_attempts = max_attempts;
+        VaultClient::new(config).unwrap()
+    };
+    let vault_client = client(1);
+    server.delay_next(Duration::from_secs(1));
+    assert!(matches!(
+        vault_client.get_secret("key", "production"),
+        Err(VaultError::Transport(_))
+    ));
+
+    // Timeouts are transient, so the request succeeds on the next attempt
+    let vault_client = client(2);
+    server.delay_next(Duration::from_secs(1));
+    assert_eq!(
+        *vault_client
+            .get_secret("key", "production")
+            .unwrap()
+            .expose(),
+        json!({"key": "value"})
+    );
+}
+
+#[test]
+fn no_proxy_hosts_bypass_the_proxy() {
+    let server = MockServer::start("apikey", "secret").unwrap();
+    // Nothing listens on the proxy's port, so the requests only succeed when they bypass it
+    let unused = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let proxy = format!("http://{}", unused.local_addr().unwrap());
+    drop(unused);
+    let client = |no_proxy: Option<&str>| {
+        let mut config = env_config(&server);
+        config.retry_policy.max_attempts = 1;
+        config.proxy = Some(proxy.clone());
+        config.no_proxy = no_proxy.map(str::to_string);
+        VaultClient::new(config)
+    };
+    assert!(matches!(client(None), Err(VaultError::Transport(_))));
+    for no_proxy in ["127.0.0.1", "localhost,127.0.0.0/8", "*"] {
+        let vault_client = client(Some(no_proxy)).unwrap();
+        assert!(
+            vault_client.list_tables().unwrap().is_empty(),
+            "{}",
+            no_proxy
+        );
+    }
+}
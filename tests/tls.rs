@@ -0,0 +1,115 @@
+#![cfg(feature = "testing")]
+
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::CertificateDer;
+use serde_json::json;
+use std::path::PathBuf;
+use vaultapi::client::VaultClient;
+use vaultapi::error::VaultError;
+use vaultapi::parser::EnvConfig;
+use vaultapi::testing::{MockServer, MockTls};
+use vaultapi::tls;
+
+/// Returns the path of a certificate or key generated by `tests/fixtures/tls/generate.sh`
+fn fixture(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures/tls")
+        .join(name)
+}
+
+/// Starts a mock server with the fixture certificate, and a secret stored in it.
+fn start(client_ca: Option<PathBuf>) -> MockServer {
+    let mock_tls = MockTls {
+        cert: fixture("server.pem"),
+        key: fixture("server.key"),
+        client_ca,
+    };
+    let server = MockServer::start_tls("apikey", "secret", &mock_tls).unwrap();
+    server.insert_secret("production", "key", "value");
+    server
+}
+
+/// Creates the configuration for the mock server, trusting the fixture CA.
+fn env_config(server: &MockServer) -> EnvConfig {
+    let mut env_config = server.env_config();
+    env_config.retry_policy.max_attempts = 1;
+    env_config.tls.ca_cert = Some(fixture("ca.pem"));
+    env_config
+}
+
+/// Computes the SPKI pin of the server certificate.
+fn server_pin() -> String {
+    let certificate = CertificateDer::from_pem_file(fixture("server.pem")).unwrap();
+    tls::spki_pin(&certificate).unwrap()
+}
+
+/// Checks that the client retrieves the secret from the server.
+fn assert_connects(env_config: EnvConfig) {
+    let vault_client = VaultClient::new(env_config).unwrap();
+    assert_eq!(
+        *vault_client
+            .get_secret("key", "production")
+            .unwrap()
+            .expose(),
+        json!({"key": "value"})
+    );
+}
+
+/// Checks that the client fails the TLS handshake with the server.
+fn assert_rejected(env_config: EnvConfig) {
+    assert!(matches!(
+        VaultClient::new(env_config),
+        Err(VaultError::Transport(_))
+    ));
+}
+
+#[test]
+fn trusted_ca_is_verified() {
+    let server = start(None);
+    assert_connects(env_config(&server));
+}
+
+#[test]
+fn untrusted_certificate_is_rejected_unless_insecure() {
+    let server = start(None);
+    let mut config = env_config(&server);
+    config.tls.ca_cert = None;
+    assert_rejected(config);
+
+    let mut config = env_config(&server);
+    config.tls.ca_cert = None;
+    config.tls.insecure = true;
+    assert_connects(config);
+}
+
+#[test]
+fn pinned_public_key_is_verified() {
+    let server = start(None);
+    let mut config = env_config(&server);
+    config.tls.pinned_spki = vec![format!("sha256//{}", server_pin())];
+    assert_connects(config);
+
+    // Pin of a different key, with a certificate that is otherwise trusted
+    let client_certificate = CertificateDer::from_pem_file(fixture("client.pem")).unwrap();
+    let mut config = env_config(&server);
+    config.tls.pinned_spki = vec![tls::spki_pin(&client_certificate).unwrap()];
+    assert_rejected(config);
+
+    // Pin is still checked when the chain validation is skipped
+    let mut config = env_config(&server);
+    config.tls.ca_cert = None;
+    config.tls.insecure = true;
+    config.tls.pinned_spki = vec![tls::spki_pin(&client_certificate).unwrap()];
+    assert_rejected(config);
+}
+
+#[test]
+fn client_certificate_is_required_for_mutual_tls() {
+    let server = start(Some(fixture("ca.pem")));
+    assert_rejected(env_config(&server));
+
+    let mut config = env_config(&server);
+    config.tls.client_cert = Some(fixture("client.pem"));
+    config.tls.client_key = Some(fixture("client.key"));
+    assert_connects(config);
+}